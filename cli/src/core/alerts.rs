@@ -0,0 +1,220 @@
+use std::path::Path;
+use chrono;
+use hyper::Error;
+use regex::Regex;
+use serde_json;
+
+use formatting::formatter::FormatStr;
+use helpers::io_utils::{read_file, append_to_file};
+use helpers::path_utils::{get_host_from_link, host_matches_domain};
+use super::app::{AppDomain, AppStateMachine};
+use super::checkpoint::Checkpoint;
+use super::client;
+use super::item_walker::{self, WalkSource};
+use super::models::HnItem;
+use super::watch::create_excerpt;
+
+///
+/// Rule file for alerts, any matching rule raises an alert for the item
+///
+#[derive(Serialize, Deserialize)]
+pub struct AlertRulesFile {
+    /// Case insensitive words or phrases searched from titles and comment text
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// Regular expressions matched against titles and comment text
+    #[serde(default)]
+    pub regexes: Vec<String>,
+    /// Domains of story links, subdomains match as well
+    #[serde(default)]
+    pub domains: Vec<String>,
+    /// Usernames of the submitter or commenter
+    #[serde(default)]
+    pub authors: Vec<String>,
+}
+
+pub struct AlertRules {
+    keywords: Vec<String>,
+    regexes: Vec<Regex>,
+    domains: Vec<String>,
+    authors: Vec<String>,
+}
+
+impl AlertRules {
+    pub fn load(path: &Path) -> Result<AlertRules, String> {
+        let contents = read_file(&path.to_string_lossy())
+            .ok_or(format!("Could not read alert rules from {}", path.to_string_lossy()))?;
+        let rules_file: AlertRulesFile = serde_json::from_str(&contents).map_err(|e| format!("Invalid alert rules {}", e))?;
+        AlertRules::compile(rules_file)
+    }
+
+    pub fn compile(rules_file: AlertRulesFile) -> Result<AlertRules, String> {
+        let mut regexes = Vec::new();
+        for pattern in rules_file.regexes.iter() {
+            regexes.push(Regex::new(pattern).map_err(|e| format!("Invalid regex {} in alert rules {}", pattern, e))?);
+        }
+        Ok(AlertRules {
+            keywords: rules_file.keywords.iter().map(|k| k.to_lowercase()).collect(),
+            regexes: regexes,
+            domains: rules_file.domains.iter().map(|d| d.to_lowercase()).collect(),
+            authors: rules_file.authors,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keywords.is_empty() && self.regexes.is_empty() && self.domains.is_empty() && self.authors.is_empty()
+    }
+
+    ///
+    /// Returns the reasons the item matched, empty if it did not match any rule
+    ///
+    pub fn matches(&self, item: &HnItem, format: &FormatStr) -> Vec<String> {
+        let mut reasons = Vec::new();
+        let mut text = item.title.clone().unwrap_or(String::new());
+        match item.text_unescaped() {
+            Some(unescaped) => {
+                text.push(' ');
+                text.push_str(&format.format(&unescaped));
+            }
+            None => (),
+        }
+        let lowercase_text = text.to_lowercase();
+        for keyword in self.keywords.iter().filter(|keyword| lowercase_text.contains(keyword.as_str())) {
+            reasons.push(format!("keyword {}", keyword));
+        }
+        for regex in self.regexes.iter().filter(|regex| regex.is_match(&text)) {
+            reasons.push(format!("regex {}", regex.as_str()));
+        }
+        let host = item.url.as_ref().and_then(|url| get_host_from_link(url)).map(|host| host.to_lowercase());
+        match host {
+            Some(ref host) => {
+                for domain in self.domains.iter().filter(|domain| host_matches_domain(host, domain)) {
+                    reasons.push(format!("domain {}", domain));
+                }
+            }
+            None => (),
+        }
+        if self.authors.contains(&item.by) {
+            reasons.push(format!("author {}", item.by));
+        }
+        reasons
+    }
+}
+
+pub struct AlertMatch {
+    pub item_id: i32,
+    pub type_str: String,
+    pub by: String,
+    pub reasons: Vec<String>,
+    pub excerpt: String,
+}
+
+impl AlertMatch {
+    pub fn to_log_line(&self) -> String {
+        format!("[{}] {} {} by {} matched {}: {}\n",
+                chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                self.type_str,
+                self.item_id,
+                self.by,
+                self.reasons.join(", "),
+                self.excerpt)
+    }
+}
+
+///
+/// Evaluates every item newer than the checkpoint, writes the matches to the log and moves the checkpoint after each item.
+/// On errors the pass stops and the next one continues from the last item which was processed.
+///
+pub fn run_alert_pass(rules: &AlertRules,
+                      source: WalkSource,
+                      backfill: usize,
+                      checkpoint: &Checkpoint,
+                      log_path: &Path,
+                      app_domain: &mut AppDomain,
+                      state: &mut AppStateMachine)
+                      -> Result<Vec<AlertMatch>, Error> {
+    let ids = item_walker::get_unprocessed_ids(source, checkpoint.load(), backfill, app_domain, state)?;
+    let mut matches = Vec::new();
+    for id in ids {
+        let item = client::get_optional_item_by_id(&id.to_string(), app_domain, state)?;
+        match item {
+            Some(ref item) if !item.dead.unwrap_or(false) => {
+                let reasons = rules.matches(item, &app_domain.formatters);
+                if !reasons.is_empty() {
+                    let alert = AlertMatch {
+                        item_id: item.id,
+                        type_str: item.type_str.clone(),
+                        by: item.by.clone(),
+                        reasons: reasons,
+                        excerpt: create_excerpt(item, &app_domain.formatters),
+                    };
+                    if append_to_file(log_path, &alert.to_log_line()).is_err() {
+                        warn!("Could not write alert to {}", log_path.to_string_lossy());
+                    }
+                    matches.push(alert);
+                }
+            }
+            _ => (), // deleted, dead or missing items are skipped but still checkpointed
+        }
+        if checkpoint.save(id).is_err() {
+            warn!("Could not save alerts checkpoint {}", id);
+        }
+    }
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use formatting::formatter::Formatters;
+    use helpers::test_utils::read_item;
+
+    fn rules(keywords: Vec<&str>, regexes: Vec<&str>, domains: Vec<&str>, authors: Vec<&str>) -> AlertRules {
+        let to_strings = |v: Vec<&str>| v.into_iter().map(|s| String::from(s)).collect();
+        AlertRules::compile(AlertRulesFile {
+                keywords: to_strings(keywords),
+                regexes: to_strings(regexes),
+                domains: to_strings(domains),
+                authors: to_strings(authors),
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn alert_rules_match_story_test() {
+        let formatters = Formatters::new();
+        let story = read_item("res/test/item.json");
+        assert_eq!(vec!["keyword dropbox"], rules(vec!["Dropbox"], vec![], vec![], vec![]).matches(&story, &formatters));
+        assert_eq!(vec!["domain getdropbox.com"],
+                   rules(vec![], vec![], vec!["getdropbox.com"], vec![]).matches(&story, &formatters));
+        assert_eq!(vec!["author dhouston"],
+                   rules(vec![], vec![], vec![], vec!["dhouston"]).matches(&story, &formatters));
+        assert_eq!(vec!["regex USB\\s+drive"],
+                   rules(vec![], vec!["USB\\s+drive"], vec![], vec![]).matches(&story, &formatters));
+        assert!(rules(vec!["nothing like this"], vec![], vec!["dropbox.org"], vec!["pg"])
+            .matches(&story, &formatters)
+            .is_empty());
+    }
+
+    #[test]
+    fn alert_rules_match_comment_text_test() {
+        let formatters = Formatters::new();
+        let comment = read_item("res/test/children-item.json");
+        let reasons = rules(vec!["not a valid concern"], vec![], vec![], vec![]).matches(&comment, &formatters);
+        assert_eq!(1, reasons.len());
+    }
+
+    #[test]
+    fn alert_rules_load_test() {
+        let rules = AlertRules::load(Path::new("res/test/alert-rules.json")).unwrap();
+        assert!(!rules.is_empty());
+        assert_eq!(2, rules.keywords.len());
+        assert!(AlertRules::compile(AlertRulesFile {
+                keywords: vec![],
+                regexes: vec![String::from("(unclosed")],
+                domains: vec![],
+                authors: vec![],
+            })
+            .is_err());
+    }
+}
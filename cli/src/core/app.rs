@@ -1,7 +1,8 @@
 use std::io;
 use std::sync::Arc;
+use std::time::Instant;
 use std::fs::OpenOptions;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 
 use hyper::client::HttpConnector;
 use hyper::{Client};
@@ -13,7 +14,13 @@ use std;
 use fern;
 use chrono;
 use super::endpoint::HnNewsEndpoint;
-use super::models::{HnItem, HnListOfItems};
+use super::models::{HnFeed, HnItem, HnItemStats, HnListOfItems};
+use super::feed_view::FeedFilters;
+use super::config::AppConfig;
+use super::watch::WatchList;
+use super::killfile::Killfile;
+use super::text_index::TextIndex;
+use super::thread_search::ThreadSearch;
 use super::connector::HttpsConnector;
 use helpers::gen_utils::comment_has_kids;
 use formatting::formatter::Formatters;
@@ -26,6 +33,7 @@ pub struct AppDomain {
     pub endpoint: HnNewsEndpoint,
     pub client: Client<HttpsConnector>,
     pub formatters: Formatters,
+    pub config: AppConfig,
 }
 
 impl AppDomain {
@@ -35,35 +43,111 @@ impl AppDomain {
         let handle = core.handle();
         let client = configure_client(&handle);
         let endpoint = HnNewsEndpoint::build_default();
-        let formatters = Formatters::new();
+        let config = AppConfig::load();
+        let formatters = create_formatters(&config, true);
         AppDomain {
             core: core,
             endpoint: endpoint,
             client: client,
             formatters: formatters,
+            config: config,
+        }
+    }
+
+    ///
+    /// Formatters of the configured stages for the servers, which can't use the ones of the domain
+    /// while their sources borrow it. Without wrapping for clients which wrap the text themselves
+    ///
+    pub fn create_formatters(&self, wrapping: bool) -> Formatters {
+        create_formatters(&self.config, wrapping)
+    }
+}
+
+fn create_formatters(config: &AppConfig, wrapping: bool) -> Formatters {
+    let formatters = if wrapping {
+        Formatters::from_config(&config.format_stages)
+    } else {
+        Formatters::from_config_without_wrapping(&config.format_stages)
+    };
+    match formatters {
+        Ok(formatters) => formatters,
+        Err(reason) => {
+            warn!("Could not use format_stages of the config, using the default ones: {}", reason);
+            if wrapping { Formatters::new() } else { Formatters::without_wrapping() }
         }
     }
 }
 
 pub struct AppCache {
-    pub retrieved_top_stories: Option<HnListOfItems>,
+    pub retrieved_stories: Option<HnListOfItems>,
+    pub filtered_stories: Option<Vec<i32>>, // stories of the feed after feed filters, if any are active
     pub retrieved_best_stories: Option<HnListOfItems>,
     pub retrieved_new_stories: Option<HnListOfItems>,
     pub last_retrieved_item: Option<HnItem>,
     pub last_parent_items: VecDeque<HnItem>, // this does not need to be optional
     pub last_retrieved_comments: Option<Vec<HnItem>>,
+    pub thread_search: Option<ThreadSearch>, // search over last_retrieved_comments
+    pub items: HashMap<i32, HnItem>,
+    pub search_hits: HashMap<i32, HnItem>, // items of the listing mapped from search results, kept apart as they lack kids
+    pub listing_highlights: Vec<String>, // words highlighted in the listing of search results
+    pub text_index: TextIndex,
+    pub previous_stats: HashMap<i32, HnItemStats>,
+    pub watch_list: WatchList,
+    pub killfile: Killfile,
 }
 
 impl AppCache {
     pub fn new() -> AppCache {
         AppCache {
-            retrieved_top_stories: None,
+            retrieved_stories: None,
+            filtered_stories: None,
             retrieved_best_stories: None,
             retrieved_new_stories: None,
             last_retrieved_item: None,
             last_parent_items: VecDeque::new(),
             last_retrieved_comments: None,
+            thread_search: None,
+            items: HashMap::new(),
+            search_hits: HashMap::new(),
+            listing_highlights: Vec::new(),
+            text_index: TextIndex::new(),
+            previous_stats: HashMap::new(),
+            watch_list: WatchList::new(),
+            killfile: Killfile::new(),
+        }
+    }
+
+    pub fn get_cached_item(&self, id: i32) -> Option<&HnItem> {
+        self.items.get(&id)
+    }
+
+    pub fn cache_item(&mut self, item: HnItem) {
+        self.items.insert(item.id, item);
+    }
+
+    ///
+    /// Drops the given items from cache and remembers their stats so the next print can show what moved,
+    /// returns how many of the ids were cached
+    ///
+    pub fn invalidate_items(&mut self, ids: &Vec<i32>) -> usize {
+        let mut invalidated = 0;
+        for id in ids {
+            match self.items.remove(id) {
+                Some(item) => {
+                    self.previous_stats.insert(*id, item.stats());
+                    invalidated += 1;
+                }
+                None => (),
+            }
         }
+        invalidated
+    }
+
+    ///
+    /// Returns the stats the item had before it was last invalidated, only once
+    ///
+    pub fn take_previous_stats(&mut self, id: i32) -> Option<HnItemStats> {
+        self.previous_stats.remove(&id)
     }
     pub fn get_comment(&mut self, numb: usize) -> Option<HnItem> {
         match self.last_retrieved_comments {
@@ -85,13 +169,24 @@ impl AppCache {
         }
     }
 
-    pub fn stories_len(&self) -> Option<usize> {
-        match self.retrieved_top_stories {
-            Some(ref top_stories) => Some(top_stories.values.len()),
-            None => None,
+    ///
+    /// Story ids which are paged through, the filtered view if there is one
+    ///
+    pub fn listing(&self) -> Option<&Vec<i32>> {
+        match self.filtered_stories {
+            Some(ref filtered) => Some(filtered),
+            None => self.retrieved_stories.as_ref().map(|stories| &stories.values),
         }
     }
 
+    pub fn story_id_at(&self, numb: usize) -> Option<i32> {
+        self.listing().and_then(|listing| listing.get(numb).cloned())
+    }
+
+    pub fn stories_len(&self) -> Option<usize> {
+        self.listing().map(|listing| listing.len())
+    }
+
     pub fn comments_len(&self) -> Option<usize> {
         match self.last_retrieved_comments {
             Some(ref comments) => Some(comments.len()),
@@ -125,6 +220,14 @@ pub struct AppStateMachine {
     pub last_opened_item_id: String,
     pub current_state: AppStates,
     pub previous_command: AppPreviousCommand,
+    pub live_updates: bool,
+    pub last_watch_check: Instant,
+    pub current_feed: HnFeed,
+    pub feed_filters: FeedFilters,
+    /// How many scripts are being run inside each other
+    pub script_depth: usize,
+    /// Comments are shown as the api returns them without the format stages
+    pub raw_comments: bool,
 }
 
 impl AppStateMachine {
@@ -136,6 +239,12 @@ impl AppStateMachine {
             last_opened_item_id: String::from(""),
             current_state: AppStates::Starting,
             previous_command: AppPreviousCommand::NoPrevious,
+            live_updates: false,
+            last_watch_check: Instant::now(),
+            current_feed: HnFeed::Top,
+            feed_filters: FeedFilters::new(),
+            script_depth: 0,
+            raw_comments: false,
         }
     }
 
@@ -157,6 +266,9 @@ impl AppStateMachine {
     pub fn viewing_comments(&self) -> bool {
         self.previous_command == AppPreviousCommand::ExpandedComment || self.previous_command == AppPreviousCommand::ViewingComments
     }
+    pub fn enable_live_updates(&mut self, enabled: bool) {
+        self.live_updates = enabled;
+    }
     pub fn viewing_stories(&self) -> bool {
         // we can just use negation of viewing comments currently
         !self.viewing_comments()
@@ -0,0 +1,46 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use helpers::io_utils::{read_file, write_file_atomic};
+
+///
+/// Last processed item id persisted to a file so long running modes can continue after restart
+///
+pub struct Checkpoint {
+    path: PathBuf,
+}
+
+impl Checkpoint {
+    pub fn new(path: &Path) -> Checkpoint {
+        Checkpoint { path: path.to_path_buf() }
+    }
+
+    pub fn load(&self) -> Option<i32> {
+        read_file(&self.path.to_string_lossy()).and_then(|contents| contents.trim().parse::<i32>().ok())
+    }
+
+    pub fn save(&self, id: i32) -> io::Result<()> {
+        write_file_atomic(&self.path, &id.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn checkpoint_test() {
+        let path = env::temp_dir().join("hncli-checkpoint-test");
+        let checkpoint = Checkpoint::new(&path);
+        let _ = fs::remove_file(&path);
+        assert!(checkpoint.load().is_none());
+        checkpoint.save(14080934).unwrap();
+        assert_eq!(14080934, checkpoint.load().unwrap());
+        checkpoint.save(14080935).unwrap();
+        assert_eq!(14080935, checkpoint.load().unwrap());
+        assert!(!env::temp_dir().join(".hncli-checkpoint-test.tmp").exists());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -16,6 +16,7 @@ use helpers::logging_utils::{log_response_status, log_written_file};
 use helpers::path_utils::{generate_filename_for_hnitem};
 use super::models::*;
 use super::endpoint::HnNewsEndpoint;
+use super::search::{SearchQuery, SearchResults};
 use super::app::{AppDomain, AppStates, AppStateMachine};
 
 pub fn get_top_story_ids(app_domain: &mut AppDomain,
@@ -40,6 +41,101 @@ pub fn get_top_story_ids(app_domain: &mut AppDomain,
     result
 }
 
+///
+/// Gets ids of the stories in the feed in ranked order
+///
+pub fn get_feed_story_ids(feed: HnFeed,
+                          app_domain: &mut AppDomain,
+                          state: &mut AppStateMachine)
+                          -> Result<HnListOfItems, Error> {
+    let path = app_domain.endpoint.get_feed_path(feed);
+    get_and_deserialize::<HnListOfItems>(&path, app_domain, state)
+}
+
+///
+/// Gets ids of the newest stories
+///
+pub fn get_new_story_ids(app_domain: &mut AppDomain,
+                         state: &mut AppStateMachine)
+                         -> Result<HnListOfItems, Error> {
+    let path = app_domain.endpoint.get_new_stories_path();
+    get_and_deserialize::<HnListOfItems>(&path, app_domain, state)
+}
+
+///
+/// Gets HnItem or None if the id does not have an item (api returns null), wrapped in Result
+///
+pub fn get_optional_item_by_id(item: &str,
+                               app_domain: &mut AppDomain,
+                               state: &mut AppStateMachine)
+                               -> Result<Option<HnItem>, Error> {
+    let path = app_domain.endpoint.get_item_path(item);
+    get_and_deserialize::<Option<HnItem>>(&path, app_domain, state)
+}
+
+///
+/// Gets the largest item id currently known by the api
+///
+pub fn get_max_item_id(app_domain: &mut AppDomain,
+                       state: &mut AppStateMachine)
+                       -> Result<i32, Error> {
+    let path = app_domain.endpoint.get_max_item_path();
+    get_and_deserialize::<i32>(&path, app_domain, state)
+}
+
+///
+/// Gets the items and profiles which have changed recently
+///
+pub fn get_updates(app_domain: &mut AppDomain,
+                   state: &mut AppStateMachine)
+                   -> Result<HnUpdates, Error> {
+    let path = app_domain.endpoint.get_updates_path();
+    get_and_deserialize::<HnUpdates>(&path, app_domain, state)
+}
+
+///
+/// Gets HnUser with the username wrapped in Result
+///
+pub fn get_user_by_id(user: &str,
+                      app_domain: &mut AppDomain,
+                      state: &mut AppStateMachine)
+                      -> Result<HnUser, Error> {
+    let path = app_domain.endpoint.get_user_path(user);
+    get_and_deserialize::<HnUser>(&path, app_domain, state)
+}
+
+///
+/// Gets HnUser or None if there's no user with the username (api returns null), wrapped in Result
+///
+pub fn get_optional_user_by_id(user: &str,
+                               app_domain: &mut AppDomain,
+                               state: &mut AppStateMachine)
+                               -> Result<Option<HnUser>, Error> {
+    let path = app_domain.endpoint.get_user_path(user);
+    get_and_deserialize::<Option<HnUser>>(&path, app_domain, state)
+}
+
+fn get_and_deserialize<T: DeserializeOwned>(path: &str,
+                                            app_domain: &mut AppDomain,
+                                            state: &mut AppStateMachine)
+                                            -> Result<T, Error> {
+    let client = &app_domain.client;
+    state.current_state = AppStates::RetrievingResults;
+    let work = create_get_request(parse_url_from_str(path), &client)
+        .and_then(|res| {
+            log_response_status(&String::from(path), &res.status().to_string());
+            res.body()
+                .fold(Vec::new(), |mut v, chunk| {
+                    v.extend(&chunk[..]);
+                    future::ok::<_, Error>(v)
+                })
+        })
+        .map(|chunks| deserialize::<T>(chunks));
+    let result = app_domain.core.run(work);
+    state.current_state = AppStates::DoingLocalWork;
+    result
+}
+
 fn deserialize<T: DeserializeOwned>(chunks: Vec<u8>) -> T {
     let s = String::from_utf8(chunks).unwrap();
     let deserialized: T = serde_json::from_str(&s).unwrap();
@@ -117,7 +213,22 @@ pub fn get_comments_for_item(item: &HnItem,
     }
 }
 
-
+///
+/// Gets all comments under the item breadth first, parents always come before their replies
+///
+pub fn get_comment_tree(item: &HnItem,
+                        app_domain: &mut AppDomain,
+                        state: &mut AppStateMachine)
+                        -> Vec<HnItem> {
+    let mut tree = get_comments_for_item(item, app_domain, state).unwrap_or(Vec::new());
+    let mut index = 0;
+    while index < tree.len() {
+        let replies = get_comments_for_item(&tree[index], app_domain, state).unwrap_or(Vec::new());
+        tree.extend(replies);
+        index += 1;
+    }
+    tree
+}
 
 fn request_top_story_ids(client: &Client<HttpsConnector>,
                          endpoints: &HnNewsEndpoint)
@@ -170,6 +281,44 @@ pub fn download_page_from_item(item: &HnItem,
 
 }
 
+///
+/// Searches stories and comments from the HN Search api in config, the search api is not part of
+/// the firebase endpoint so it's requested with curl which works for http stand-ins as well
+///
+pub fn search(query: &SearchQuery,
+              app_domain: &mut AppDomain,
+              state: &mut AppStateMachine)
+              -> Result<SearchResults, String> {
+    let url = query.to_url(&app_domain.config.search_base_url, app_domain.config.search_hits_per_page)?;
+    state.current_state = AppStates::RetrievingResults;
+    let result = get_search_results(&url);
+    state.current_state = AppStates::DoingLocalWork;
+    result
+}
+
+fn get_search_results(url: &str) -> Result<SearchResults, String> {
+    let mut body: Vec<u8> = Vec::new();
+    let mut easy = Easy::new();
+    easy.get(true).map_err(|e| e.to_string())?;
+    easy.url(url).map_err(|e| e.to_string())?;
+    easy.useragent("rs-hackernews-cli").map_err(|e| e.to_string())?;
+    {
+        let mut transfer = easy.transfer();
+        transfer.write_function(|data| {
+                body.extend_from_slice(data);
+                Ok(data.len())
+            })
+            .map_err(|e| e.to_string())?;
+        transfer.perform().map_err(|e| format!("search request failed {}", e))?;
+    }
+    let code = easy.response_code().map_err(|e| e.to_string())?;
+    log_response_status(&String::from(url), &code.to_string());
+    if code != 200 {
+        return Err(format!("search api responded with {}", code));
+    }
+    serde_json::from_slice(&body).map_err(|e| format!("invalid search response {}", e))
+}
+
 fn curl_req(url: &String) -> Vec<u8> {
     let mut vecced: Vec<u8> = Vec::new();
     let mut easy = Easy::new();
@@ -206,6 +355,11 @@ fn parse_url_from_str(url_str: &str) -> Uri {
 #[cfg(test)]
 mod tests {
     use hyper::StatusCode;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+    use helpers::io_utils::read_file;
 
     use super::*;
 
@@ -255,6 +409,31 @@ mod tests {
         assert!(top_stories.values.len() != 0);
     }
 
+    #[test]
+    fn get_updates_test() {
+        let mut app_domain = AppDomain::new();
+        let mut app_sm = AppStateMachine::new();
+        let updates: HnUpdates = get_updates(&mut app_domain, &mut app_sm).unwrap();
+        assert!(updates.items.len() != 0);
+    }
+
+    #[test]
+    fn get_user_by_id_test() {
+        let mut app_domain = AppDomain::new();
+        let mut app_sm = AppStateMachine::new();
+        let user: HnUser = get_user_by_id("jl", &mut app_domain, &mut app_sm).unwrap();
+        assert_eq!("jl", user.id);
+        assert!(user.submitted.len() != 0);
+    }
+
+    #[test]
+    fn get_max_item_id_test() {
+        let mut app_domain = AppDomain::new();
+        let mut app_sm = AppStateMachine::new();
+        let max_item = get_max_item_id(&mut app_domain, &mut app_sm).unwrap();
+        assert!(max_item > 14080934);
+    }
+
     #[test]
     fn get_comments_test() {
         let mut app_domain = AppDomain::new();
@@ -266,6 +445,57 @@ mod tests {
         assert!(comments.len() != 0);
     }
     
+    ///
+    /// Serves the body once from a local stand-in for the search api, gives the base url and the request line
+    ///
+    fn serve_search_once(status: &'static str, body: String) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request_line = String::new();
+            {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                reader.read_line(&mut request_line).unwrap();
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+            }
+            write!(stream,
+                   "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                   status,
+                   body.len(),
+                   body)
+                .unwrap();
+            tx.send(request_line).unwrap();
+        });
+        (format!("http://{}/api/v1/", addr), rx)
+    }
+
+    #[test]
+    fn search_test() {
+        let body = read_file("res/test/search-results.json").unwrap();
+        let (base_url, requests) = serve_search_once("200 OK", body);
+        let mut app_domain = AppDomain::new();
+        let mut app_sm = AppStateMachine::new();
+        app_domain.config.search_base_url = base_url;
+        let words = vec![String::from("dropbox"), String::from("tag:story")];
+        let query = SearchQuery::parse(&words, 0).unwrap();
+        let results = search(&query, &mut app_domain, &mut app_sm).unwrap();
+        assert_eq!(3, results.to_items().len());
+        let request_line = requests.recv().unwrap();
+        assert!(request_line.starts_with("GET /api/v1/search?query=dropbox&"));
+        assert!(request_line.contains("tags=story"));
+    }
+
+    #[test]
+    fn search_error_status_test() {
+        let (base_url, _) = serve_search_once("503 Service Unavailable", String::from("{}"));
+        assert!(get_search_results(&format!("{}search?query=x", base_url)).is_err());
+    }
+
     #[test]
     fn parse_url_from_str_test() {
         let url = parse_url_from_str("http://www.google.fi");
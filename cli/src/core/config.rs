@@ -0,0 +1,281 @@
+use std::path::Path;
+use serde_json;
+
+use helpers::io_utils::read_file;
+use helpers::path_utils::get_data_file_path;
+
+const CONFIG_FILENAME: &'static str = "config.json";
+
+fn default_watch_interval() -> u64 {
+    300
+}
+
+fn default_watch_notify() -> Vec<String> {
+    vec![String::from("stdout")]
+}
+
+fn default_watch_digest_file() -> String {
+    String::from("watch-digest.txt")
+}
+
+fn default_alerts_interval() -> u64 {
+    60
+}
+
+fn default_alerts_source() -> String {
+    String::from("maxitem")
+}
+
+fn default_alerts_backfill() -> usize {
+    100
+}
+
+fn default_alerts_rules_file() -> String {
+    String::from("alerts.json")
+}
+
+fn default_alerts_log_file() -> String {
+    String::from("alerts.log")
+}
+
+fn default_alerts_checkpoint_file() -> String {
+    String::from("alerts.checkpoint")
+}
+
+fn default_stream_interval() -> u64 {
+    10
+}
+
+fn default_stream_backfill() -> usize {
+    20
+}
+
+fn default_stream_buffer() -> usize {
+    1000
+}
+
+fn default_stream_checkpoint_file() -> String {
+    String::from("stream.checkpoint")
+}
+
+fn default_format_stages() -> Vec<FormatStageConfig> {
+    vec![FormatStageConfig::built_in("tags"), FormatStageConfig::built_in("wrap")]
+}
+
+fn default_stage_enabled() -> bool {
+    true
+}
+
+fn default_highlight_code() -> bool {
+    true
+}
+
+fn default_feed_filter_stories() -> usize {
+    100
+}
+
+fn default_index_max_docs() -> usize {
+    20000
+}
+
+fn default_search_base_url() -> String {
+    String::from("https://hn.algolia.com/api/v1/")
+}
+
+fn default_search_hits_per_page() -> usize {
+    30
+}
+
+fn default_edit_mode() -> String {
+    String::from("emacs")
+}
+
+fn default_history_file() -> String {
+    String::from("history")
+}
+
+fn default_history_size() -> usize {
+    1000
+}
+
+fn default_nntp_stories() -> usize {
+    10
+}
+
+fn default_gemini_identity() -> String {
+    String::from("gemini.p12")
+}
+
+///
+/// Stage of the comment formatting pipeline, "tags" and "wrap" are built in and other stages
+/// replace a regex or pipe the text through a shell command
+///
+#[derive(Serialize, Deserialize)]
+pub struct FormatStageConfig {
+    pub name: String,
+    #[serde(default)]
+    pub regex: Option<String>,
+    /// Replacement of the regex matches, may refer to groups like $1
+    #[serde(default)]
+    pub replacement: String,
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default = "default_stage_enabled")]
+    pub enabled: bool,
+}
+
+impl FormatStageConfig {
+    pub fn built_in(name: &str) -> FormatStageConfig {
+        FormatStageConfig {
+            name: String::from(name),
+            regex: None,
+            replacement: String::new(),
+            command: None,
+            enabled: true,
+        }
+    }
+}
+
+///
+/// User configuration read from config.json in the data directory, every field is optional in the file
+///
+#[derive(Serialize, Deserialize)]
+pub struct AppConfig {
+    /// How often watched items and users are checked
+    #[serde(default = "default_watch_interval")]
+    pub watch_interval_secs: u64,
+    /// Where watch notifications go, any of "stdout", "digest" and "hook"
+    #[serde(default = "default_watch_notify")]
+    pub watch_notify: Vec<String>,
+    /// Digest file for watch notifications, relative paths are under the data directory
+    #[serde(default = "default_watch_digest_file")]
+    pub watch_digest_file: String,
+    /// Shell command run for each watch notification, gets HN_ITEM_ID, HN_WATCH and HN_EXCERPT in env
+    #[serde(default)]
+    pub watch_hook: Option<String>,
+    /// How often new items are evaluated against alert rules
+    #[serde(default = "default_alerts_interval")]
+    pub alerts_interval_secs: u64,
+    /// Where new items are discovered, "maxitem" for stories and comments or "newstories" for stories only
+    #[serde(default = "default_alerts_source")]
+    pub alerts_source: String,
+    /// How many of the newest items are evaluated when there's no checkpoint yet
+    #[serde(default = "default_alerts_backfill")]
+    pub alerts_backfill: usize,
+    #[serde(default = "default_alerts_rules_file")]
+    pub alerts_rules_file: String,
+    #[serde(default = "default_alerts_log_file")]
+    pub alerts_log_file: String,
+    #[serde(default = "default_alerts_checkpoint_file")]
+    pub alerts_checkpoint_file: String,
+    /// How often the stream looks for new items
+    #[serde(default = "default_stream_interval")]
+    pub stream_interval_secs: u64,
+    /// How many of the newest items are streamed when there's no checkpoint yet
+    #[serde(default = "default_stream_backfill")]
+    pub stream_backfill: usize,
+    /// How many events wait for a slow reader before retrieving pauses
+    #[serde(default = "default_stream_buffer")]
+    pub stream_buffer: usize,
+    #[serde(default = "default_stream_checkpoint_file")]
+    pub stream_checkpoint_file: String,
+    /// Stages comments are formatted with, in order
+    #[serde(default = "default_format_stages")]
+    pub format_stages: Vec<FormatStageConfig>,
+    /// Whether the language of code blocks is guessed and the code highlighted in a terminal
+    #[serde(default = "default_highlight_code")]
+    pub highlight_code: bool,
+    /// How many stories from the top of a feed are retrieved to filter or sort it, the rest are left out
+    #[serde(default = "default_feed_filter_stories")]
+    pub feed_filter_stories: usize,
+    /// How many items the local index for find keeps, the oldest are dropped first
+    #[serde(default = "default_index_max_docs")]
+    pub index_max_docs: usize,
+    /// Base url of an HN Search (Algolia) compatible api
+    #[serde(default = "default_search_base_url")]
+    pub search_base_url: String,
+    #[serde(default = "default_search_hits_per_page")]
+    pub search_hits_per_page: usize,
+    /// Key bindings of the prompt, "emacs" or "vi"
+    #[serde(default = "default_edit_mode")]
+    pub edit_mode: String,
+    #[serde(default = "default_history_file")]
+    pub history_file: String,
+    /// How many commands are kept in history
+    #[serde(default = "default_history_size")]
+    pub history_size: usize,
+    /// How many stories of the feed each newsgroup of the NNTP gateway has, with all of their comments
+    #[serde(default = "default_nntp_stories")]
+    pub nntp_stories: usize,
+    /// PKCS #12 archive with the certificate and key of the Gemini server, relative paths are under the data directory
+    #[serde(default = "default_gemini_identity")]
+    pub gemini_identity: String,
+    #[serde(default)]
+    pub gemini_identity_password: String,
+}
+
+impl AppConfig {
+    pub fn new() -> AppConfig {
+        serde_json::from_str("{}").unwrap()
+    }
+
+    pub fn load() -> AppConfig {
+        let path = get_data_file_path(CONFIG_FILENAME);
+        AppConfig::load_from(&path)
+    }
+
+    pub fn load_from(path: &Path) -> AppConfig {
+        match read_file(&path.to_string_lossy()) {
+            Some(contents) => {
+                match serde_json::from_str(&contents) {
+                    Ok(config) => config,
+                    Err(e) => {
+                        warn!("Invalid config in {}, using defaults {}", path.to_string_lossy(), e);
+                        AppConfig::new()
+                    }
+                }
+            }
+            None => AppConfig::new(),
+        }
+    }
+
+    pub fn notifies_with(&self, notifier: &str) -> bool {
+        self.watch_notify.iter().any(|n| n == notifier)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_test() {
+        let config = AppConfig::new();
+        assert_eq!(300, config.watch_interval_secs);
+        assert!(config.notifies_with("stdout"));
+        assert!(config.watch_hook.is_none());
+        assert_eq!("maxitem", config.alerts_source);
+        assert_eq!("alerts.json", config.alerts_rules_file);
+        assert_eq!("stream.checkpoint", config.stream_checkpoint_file);
+        assert_eq!(2, config.format_stages.len());
+        assert!(config.highlight_code);
+        assert_eq!(100, config.feed_filter_stories);
+        assert_eq!("https://hn.algolia.com/api/v1/", config.search_base_url);
+        assert_eq!("emacs", config.edit_mode);
+        assert_eq!(10, config.nntp_stories);
+        assert_eq!("gemini.p12", config.gemini_identity);
+        assert_eq!("", config.gemini_identity_password);
+    }
+
+    #[test]
+    fn load_config_test() {
+        let config = AppConfig::load_from(Path::new("res/test/config.json"));
+        assert_eq!(60, config.watch_interval_secs);
+        assert!(config.notifies_with("hook"));
+        assert!(!config.notifies_with("stdout"));
+        assert_eq!("notify-send \"$HN_WATCH\" \"$HN_EXCERPT\"", config.watch_hook.unwrap());
+
+        let missing = AppConfig::load_from(Path::new("res/test/no-such-config.json"));
+        assert_eq!(300, missing.watch_interval_secs);
+    }
+}
@@ -1,3 +1,5 @@
+use super::models::HnFeed;
+
 pub struct HnNewsEndpoint {
     base_url: String,
     top_news_suffix: String,
@@ -5,6 +7,11 @@ pub struct HnNewsEndpoint {
     max_item_suffix: String,
     new_items_suffix: String,
     best_items_suffix: String,
+    ask_items_suffix: String,
+    show_items_suffix: String,
+    job_items_suffix: String,
+    updates_suffix: String,
+    user_suffix: String,
     json_suffix: String,
 }
 
@@ -17,6 +24,11 @@ impl HnNewsEndpoint {
             max_item_suffix: String::from("maxitem"),
             new_items_suffix: String::from("newstories"),
             best_items_suffix: String::from("beststories"),
+            ask_items_suffix: String::from("askstories"),
+            show_items_suffix: String::from("showstories"),
+            job_items_suffix: String::from("jobstories"),
+            updates_suffix: String::from("updates"),
+            user_suffix: String::from("user/"),
             json_suffix: String::from(".json"),
         };
         e
@@ -31,12 +43,29 @@ impl HnNewsEndpoint {
     pub fn get_item_path(&self, id: &str) -> String {
         combine_strings(vec![&self.base_url, &self.item_suffix, id, &self.json_suffix])
     }
-    pub fn get_new_stories_path(&self, id: &str) -> String {
-        combine_strings(vec![&self.base_url, &self.new_items_suffix, id, &self.json_suffix])
+    pub fn get_new_stories_path(&self) -> String {
+        combine_strings(vec![&self.base_url, &self.new_items_suffix, &self.json_suffix])
     }
     pub fn get_best_stories_path(&self) -> String {
         combine_strings(vec![&self.base_url, &self.best_items_suffix, &self.json_suffix])
     }
+    pub fn get_feed_path(&self, feed: HnFeed) -> String {
+        let suffix = match feed {
+            HnFeed::Top => &self.top_news_suffix,
+            HnFeed::New => &self.new_items_suffix,
+            HnFeed::Best => &self.best_items_suffix,
+            HnFeed::Ask => &self.ask_items_suffix,
+            HnFeed::Show => &self.show_items_suffix,
+            HnFeed::Job => &self.job_items_suffix,
+        };
+        combine_strings(vec![&self.base_url, suffix, &self.json_suffix])
+    }
+    pub fn get_user_path(&self, id: &str) -> String {
+        combine_strings(vec![&self.base_url, &self.user_suffix, id, &self.json_suffix])
+    }
+    pub fn get_updates_path(&self) -> String {
+        combine_strings(vec![&self.base_url, &self.updates_suffix, &self.json_suffix])
+    }
 
 }
 
@@ -0,0 +1,261 @@
+use std::io::{self, Write};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+use chrono::Utc;
+use hyper::Error;
+use serde_json;
+
+use formatting::formatter::FormatStr;
+use super::app::{AppDomain, AppStateMachine};
+use super::checkpoint::Checkpoint;
+use super::client;
+use super::item_walker::{self, WalkSource};
+use super::models::HnItem;
+use super::watch::create_excerpt;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StreamFormat {
+    /// One JSON object per line
+    Ndjson,
+    /// One readable line per item
+    Text,
+}
+
+///
+/// Newly seen story or comment, the item as the api returned it and its text decoded to plain text
+///
+#[derive(Serialize)]
+pub struct StreamEvent {
+    pub id: i32,
+    #[serde(rename = "type")]
+    pub type_str: String,
+    /// Unix time the item was seen
+    pub seen: i64,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub text: Option<String>,
+    pub item: HnItem,
+    #[serde(skip_serializing)]
+    excerpt: String,
+}
+
+impl StreamEvent {
+    pub fn new(item: HnItem, formatter: &FormatStr, now: i64) -> StreamEvent {
+        let text = item.text_unescaped().map(|text| {
+            let lines: Vec<String> = formatter.format(&text).lines().map(|line| line.trim().to_string()).collect();
+            lines.join("\n").trim().to_string()
+        });
+        StreamEvent {
+            id: item.id,
+            type_str: item.type_str.clone(),
+            seen: now,
+            text: text,
+            excerpt: create_excerpt(&item, formatter),
+            item: item,
+        }
+    }
+
+    pub fn to_line(&self, format: StreamFormat) -> String {
+        match format {
+            StreamFormat::Ndjson => serde_json::to_string(self).unwrap(),
+            StreamFormat::Text => format!("[{}] {} by {}: {}", self.type_str, self.id, self.item.by, self.excerpt),
+        }
+    }
+}
+
+///
+/// Writes the events on its own thread so a slow reader of the output does not hold up retrieving.
+/// At most capacity events wait in the queue, after that sending blocks until the reader catches up.
+/// The checkpoint is saved only after the events before it are flushed so nothing is lost on restart
+///
+pub struct EventWriter {
+    sender: SyncSender<StreamEvent>,
+    handle: JoinHandle<io::Result<()>>,
+}
+
+impl EventWriter {
+    pub fn spawn<W: Write + Send + 'static>(output: W,
+                                             format: StreamFormat,
+                                             capacity: usize,
+                                             checkpoint: Option<Checkpoint>)
+                                             -> EventWriter {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let handle = thread::spawn(move || write_events(output, format, receiver, checkpoint));
+        EventWriter {
+            sender: sender,
+            handle: handle,
+        }
+    }
+
+    ///
+    /// Queues the event, fails when the writer has stopped like when the reader went away
+    ///
+    pub fn send(&self, event: StreamEvent) -> Result<(), String> {
+        self.sender.send(event).map_err(|_| String::from("the output was closed"))
+    }
+
+    ///
+    /// Waits until the queued events are written
+    ///
+    pub fn finish(self) -> io::Result<()> {
+        drop(self.sender);
+        self.handle.join().unwrap_or(Err(io::Error::new(io::ErrorKind::Other, "event writer panicked")))
+    }
+}
+
+fn write_events<W: Write>(output: W,
+                          format: StreamFormat,
+                          receiver: Receiver<StreamEvent>,
+                          checkpoint: Option<Checkpoint>)
+                          -> io::Result<()> {
+    let mut output = io::BufWriter::new(output);
+    while let Ok(event) = receiver.recv() {
+        let mut last = event.id;
+        writeln!(output, "{}", event.to_line(format))?;
+        // whatever is already queued goes out in the same flush
+        while let Ok(event) = receiver.try_recv() {
+            last = event.id;
+            writeln!(output, "{}", event.to_line(format))?;
+        }
+        output.flush()?;
+        match checkpoint {
+            Some(ref checkpoint) => {
+                if checkpoint.save(last).is_err() {
+                    warn!("Could not save stream checkpoint {}", last);
+                }
+            }
+            None => (),
+        }
+    }
+    Ok(())
+}
+
+pub enum StreamError {
+    /// Retrieving failed, the next pass continues from the last walked id
+    Retrieve(Error),
+    /// Nobody reads the output anymore
+    OutputClosed,
+}
+
+///
+/// Sends an event for every item after the last walked id, deleted and dead items are skipped.
+/// The last walked id is kept up to date also when the pass fails half way
+///
+pub fn run_stream_pass(source: WalkSource,
+                       last: &mut Option<i32>,
+                       backfill: usize,
+                       writer: &EventWriter,
+                       app_domain: &mut AppDomain,
+                       state: &mut AppStateMachine)
+                       -> Result<usize, StreamError> {
+    let ids = item_walker::get_unprocessed_ids(source, *last, backfill, app_domain, state)
+        .map_err(StreamError::Retrieve)?;
+    let mut sent = 0;
+    for id in ids {
+        let item = client::get_optional_item_by_id(&id.to_string(), app_domain, state)
+            .map_err(StreamError::Retrieve)?;
+        match item {
+            Some(item) => {
+                // deleted items come without an author
+                if !item.dead.unwrap_or(false) && item.by != "Undefined user" {
+                    let now = Utc::now().timestamp();
+                    writer.send(StreamEvent::new(item, &app_domain.formatters, now))
+                        .map_err(|_| StreamError::OutputClosed)?;
+                    sent += 1;
+                }
+            }
+            None => (),
+        }
+        *last = Some(id);
+    }
+    Ok(sent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+    use formatting::formatter::Formatters;
+    use helpers::test_utils::read_item;
+
+    #[derive(Clone)]
+    struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct ClosedOutput;
+
+    impl Write for ClosedOutput {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+        }
+    }
+
+    #[test]
+    fn stream_event_test() {
+        let event = StreamEvent::new(read_item("res/test/children-item.json"), &Formatters::without_wrapping(), 1500000000);
+        let line = event.to_line(StreamFormat::Ndjson);
+        assert!(!line.contains('\n'));
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(14626530, value["id"]);
+        assert_eq!("comment", value["type"]);
+        assert_eq!(1500000000, value["seen"]);
+        assert_eq!("2) is not a valid concern. Unless you are planning to melt the cookware down, trepan yourself \
+                    and inject the melted aluminum into your brain, you should be fine:\n\
+                    https://www.ncbi.nlm.nih.gov/pubmed/11259180",
+                   value["text"]);
+        assert_eq!("cholantesh", value["item"]["by"]);
+        assert!(value["item"]["text"].as_str().unwrap().contains("&#x2F;"));
+
+        let story = StreamEvent::new(read_item("res/test/item.json"), &Formatters::new(), 0);
+        assert!(story.to_line(StreamFormat::Text).starts_with("[story] 8863 by dhouston: "));
+        assert!(!story.to_line(StreamFormat::Ndjson).contains("\"text\""));
+    }
+
+    #[test]
+    fn event_writer_test() {
+        let path = env::temp_dir().join(format!("hncli-stream-test-{}", ::std::process::id()));
+        let output = SharedOutput(Arc::new(Mutex::new(Vec::new())));
+        let writer = EventWriter::spawn(output.clone(), StreamFormat::Ndjson, 2, Some(Checkpoint::new(&path)));
+        for id in 1..6 {
+            let mut item = read_item("res/test/item.json");
+            item.id = id;
+            writer.send(StreamEvent::new(item, &Formatters::new(), 0)).unwrap();
+        }
+        writer.finish().unwrap();
+        let written = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let ids: Vec<i64> = written.lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["id"].as_i64().unwrap())
+            .collect();
+        assert_eq!(vec![1, 2, 3, 4, 5], ids);
+        assert_eq!(Some(5), Checkpoint::new(&path).load());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn closed_output_test() {
+        let writer = EventWriter::spawn(ClosedOutput, StreamFormat::Text, 1, None);
+        let mut failed = false;
+        for _ in 0..10 {
+            if writer.send(StreamEvent::new(read_item("res/test/item.json"), &Formatters::new(), 0)).is_err() {
+                failed = true;
+                break;
+            }
+        }
+        assert!(failed);
+        assert!(writer.finish().is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use std::cmp::Ordering;
+
+use helpers::gen_utils::format_relative_age;
+use super::models::HnItem;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FeedSort {
+    /// Order of the feed itself
+    Rank,
+    Score,
+    Comments,
+    Time,
+}
+
+impl FeedSort {
+    pub fn parse(s: &str) -> Option<FeedSort> {
+        match s {
+            "rank" => Some(FeedSort::Rank),
+            "score" => Some(FeedSort::Score),
+            "comments" => Some(FeedSort::Comments),
+            "time" => Some(FeedSort::Time),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            FeedSort::Rank => "rank",
+            FeedSort::Score => "score",
+            FeedSort::Comments => "comments",
+            FeedSort::Time => "time",
+        }
+    }
+}
+
+///
+/// Thresholds and ordering applied to the stories of a feed before they're paged
+///
+#[derive(Clone)]
+pub struct FeedFilters {
+    pub min_score: Option<i32>,
+    pub max_age_secs: Option<i64>,
+    pub min_comments: Option<i32>,
+    pub sort: FeedSort,
+}
+
+impl FeedFilters {
+    pub fn new() -> FeedFilters {
+        FeedFilters {
+            min_score: None,
+            max_age_secs: None,
+            min_comments: None,
+            sort: FeedSort::Rank,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.min_score.is_some() || self.max_age_secs.is_some() || self.min_comments.is_some() ||
+        self.sort != FeedSort::Rank
+    }
+
+    pub fn accepts(&self, item: &HnItem, now: i64) -> bool {
+        let score_ok = self.min_score.map(|min| item.score.unwrap_or(0) >= min).unwrap_or(true);
+        let age_ok = self.max_age_secs.map(|max| now - (item.time as i64) <= max).unwrap_or(true);
+        let comments_ok = self.min_comments.map(|min| item.comment_count() >= min).unwrap_or(true);
+        score_ok && age_ok && comments_ok
+    }
+
+    ///
+    /// Filters and sorts the items which are given in feed order, returns the ids of the resulting view
+    ///
+    pub fn apply(&self, items: Vec<&HnItem>, now: i64) -> Vec<i32> {
+        let mut accepted: Vec<&HnItem> = items.into_iter().filter(|item| self.accepts(item, now)).collect();
+        match self.sort {
+            FeedSort::Rank => (),
+            FeedSort::Score => accepted.sort_by(|a, b| b.score.unwrap_or(0).cmp(&a.score.unwrap_or(0))),
+            FeedSort::Comments => accepted.sort_by(|a, b| b.comment_count().cmp(&a.comment_count())),
+            FeedSort::Time => accepted.sort_by(|a, b| b.time.partial_cmp(&a.time).unwrap_or(Ordering::Equal)),
+        }
+        accepted.iter().map(|item| item.id).collect()
+    }
+
+    pub fn describe(&self, now: i64) -> String {
+        let mut parts = Vec::new();
+        match self.min_score {
+            Some(min) => parts.push(format!("min-score {}", min)),
+            None => (),
+        }
+        match self.max_age_secs {
+            Some(max) => parts.push(format!("max-age {}", format_relative_age((now - max) as f64, now).replace(" ago", ""))),
+            None => (),
+        }
+        match self.min_comments {
+            Some(min) => parts.push(format!("min-comments {}", min)),
+            None => (),
+        }
+        parts.push(format!("sort {}", self.sort.name()));
+        parts.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+
+    fn story(id: i32, score: i32, descendants: i32, time: f64) -> HnItem {
+        let json = format!("{{\"by\":\"a\",\"id\":{},\"score\":{},\"descendants\":{},\"time\":{},\"type\":\"story\",\"title\":\"t\"}}",
+                           id,
+                           score,
+                           descendants,
+                           time);
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn feed_filters_apply_test() {
+        let now = 1000000;
+        let stories = vec![story(1, 150, 5, (now - 3600) as f64),
+                           story(2, 50, 40, (now - 600) as f64),
+                           story(3, 300, 90, (now - 200000) as f64)];
+        let mut filters = FeedFilters::new();
+        assert!(!filters.is_active());
+        assert_eq!(vec![1, 2, 3], filters.apply(stories.iter().collect(), now));
+
+        filters.min_score = Some(100);
+        assert_eq!(vec![1, 3], filters.apply(stories.iter().collect(), now));
+
+        filters.max_age_secs = Some(24 * 60 * 60);
+        assert_eq!(vec![1], filters.apply(stories.iter().collect(), now));
+
+        filters = FeedFilters::new();
+        filters.min_comments = Some(20);
+        filters.sort = FeedSort::Time;
+        assert!(filters.is_active());
+        assert_eq!(vec![2, 3], filters.apply(stories.iter().collect(), now));
+
+        filters.min_comments = None;
+        filters.sort = FeedSort::Score;
+        assert_eq!(vec![3, 1, 2], filters.apply(stories.iter().collect(), now));
+        filters.sort = FeedSort::Comments;
+        assert_eq!(vec![3, 2, 1], filters.apply(stories.iter().collect(), now));
+    }
+
+    #[test]
+    fn feed_filters_describe_test() {
+        let mut filters = FeedFilters::new();
+        filters.min_score = Some(100);
+        filters.max_age_secs = Some(24 * 60 * 60);
+        assert_eq!("min-score 100, max-age 1d, sort rank", filters.describe(1000000));
+    }
+}
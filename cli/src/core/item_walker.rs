@@ -0,0 +1,105 @@
+use std::cmp::min;
+use std::i32;
+use hyper::Error;
+
+use super::app::{AppDomain, AppStateMachine};
+use super::client;
+
+/// Most ids walked from maxitem in one pass, older ones are skipped when the checkpoint is further behind
+const MAX_CATCH_UP: i32 = 10000;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum WalkSource {
+    /// Every item id between the checkpoint and maxitem, stories and comments
+    MaxItem,
+    /// Ids in newstories which are newer than the checkpoint, stories only
+    NewStories,
+}
+
+impl WalkSource {
+    pub fn parse(s: &str) -> Option<WalkSource> {
+        match s {
+            "maxitem" => Some(WalkSource::MaxItem),
+            "newstories" => Some(WalkSource::NewStories),
+            _ => None,
+        }
+    }
+}
+
+///
+/// Returns the ids which are newer than the checkpoint in ascending order, so that saving the checkpoint
+/// after each processed id never skips or repeats items. Without a checkpoint only the newest `backfill` ids are walked.
+///
+pub fn get_unprocessed_ids(source: WalkSource,
+                           checkpoint: Option<i32>,
+                           backfill: usize,
+                           app_domain: &mut AppDomain,
+                           state: &mut AppStateMachine)
+                           -> Result<Vec<i32>, Error> {
+    match source {
+        WalkSource::MaxItem => {
+            let max_item = client::get_max_item_id(app_domain, state)?;
+            Ok(ids_after_checkpoint(max_item, checkpoint, backfill))
+        }
+        WalkSource::NewStories => {
+            let new_stories = client::get_new_story_ids(app_domain, state)?;
+            Ok(new_ids_in_listing(new_stories.values, checkpoint, backfill))
+        }
+    }
+}
+
+fn ids_after_checkpoint(max_item: i32, checkpoint: Option<i32>, backfill: usize) -> Vec<i32> {
+    let backfill = min(backfill, i32::MAX as usize) as i32;
+    let first = match checkpoint {
+        Some(id) => id.saturating_add(1),
+        None => max_item.saturating_sub(backfill).saturating_add(1),
+    };
+    let oldest = max_item.saturating_sub(MAX_CATCH_UP).saturating_add(1).max(1);
+    if first < oldest {
+        warn!("Skipping items {} to {}, more than {} items behind", first.max(1), oldest - 1, MAX_CATCH_UP);
+    }
+    (first.max(oldest)..max_item + 1).collect()
+}
+
+fn new_ids_in_listing(mut ids: Vec<i32>, checkpoint: Option<i32>, backfill: usize) -> Vec<i32> {
+    ids.sort();
+    match checkpoint {
+        Some(last) => ids.into_iter().filter(|id| *id > last).collect(),
+        None => {
+            let skipped = ids.len().saturating_sub(backfill);
+            ids.into_iter().skip(skipped).collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_after_checkpoint_test() {
+        assert_eq!(vec![98, 99, 100], ids_after_checkpoint(100, Some(97), 10));
+        assert!(ids_after_checkpoint(100, Some(100), 10).is_empty());
+        assert_eq!(vec![96, 97, 98, 99, 100], ids_after_checkpoint(100, None, 5));
+        assert_eq!(vec![1, 2, 3], ids_after_checkpoint(3, None, 5));
+
+        let caught_up = ids_after_checkpoint(1000000, Some(1), 10);
+        assert_eq!(MAX_CATCH_UP as usize, caught_up.len());
+        assert_eq!(Some(&(1000000 - MAX_CATCH_UP + 1)), caught_up.first());
+        assert_eq!(MAX_CATCH_UP as usize, ids_after_checkpoint(1000000, None, usize::max_value()).len());
+    }
+
+    #[test]
+    fn new_ids_in_listing_test() {
+        let listing = vec![14079998, 14078838, 14079547, 14079163];
+        assert_eq!(vec![14079547, 14079998], new_ids_in_listing(listing.clone(), Some(14079163), 10));
+        assert_eq!(vec![14079998], new_ids_in_listing(listing, None, 1));
+    }
+
+    #[test]
+    fn walk_source_parse_test() {
+        assert_eq!(Some(WalkSource::MaxItem), WalkSource::parse("maxitem"));
+        assert_eq!(Some(WalkSource::NewStories), WalkSource::parse("newstories"));
+        assert!(WalkSource::parse("topstories").is_none());
+    }
+}
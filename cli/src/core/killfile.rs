@@ -0,0 +1,224 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use regex::Regex;
+use serde_json;
+
+use helpers::io_utils::{read_file, write_file};
+use helpers::path_utils::{get_data_file_path, get_host_from_link, host_matches_domain};
+use super::models::HnItem;
+
+const KILLFILE_FILENAME: &'static str = "killfile.json";
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum KillRule {
+    /// Stories linking to the domain or its subdomains
+    Domain(String),
+    /// Stories with title matching the regex
+    Title(String),
+    /// Stories submitted by the user
+    Author(String),
+    /// Comments written by the user
+    CommentAuthor(String),
+}
+
+impl KillRule {
+    pub fn parse(kind: &str, value: &str) -> Option<KillRule> {
+        let value = String::from(value);
+        match kind {
+            "domain" => Some(KillRule::Domain(value.to_lowercase())),
+            "title" => Some(KillRule::Title(value)),
+            "author" => Some(KillRule::Author(value)),
+            "comment-author" => Some(KillRule::CommentAuthor(value)),
+            _ => None,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match *self {
+            KillRule::Domain(ref domain) => format!("domain {}", domain),
+            KillRule::Title(ref pattern) => format!("title {}", pattern),
+            KillRule::Author(ref author) => format!("author {}", author),
+            KillRule::CommentAuthor(ref author) => format!("comment-author {}", author),
+        }
+    }
+}
+
+///
+/// What is done to stories matching the killfile, comments are always hidden
+///
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KillAction {
+    Hide,
+    Collapse,
+}
+
+#[derive(Serialize, Deserialize)]
+struct KillfileContents {
+    rules: Vec<KillRule>,
+    story_action: KillAction,
+}
+
+///
+/// Persisted rules for filtering stories and comments out of listings
+///
+pub struct Killfile {
+    rules: Vec<KillRule>,
+    title_regexes: Vec<Option<Regex>>,
+    pub story_action: KillAction,
+}
+
+impl Killfile {
+    pub fn new() -> Killfile {
+        Killfile {
+            rules: Vec::new(),
+            title_regexes: Vec::new(),
+            story_action: KillAction::Hide,
+        }
+    }
+
+    pub fn default_path() -> PathBuf {
+        get_data_file_path(KILLFILE_FILENAME)
+    }
+
+    pub fn load() -> Killfile {
+        Killfile::load_from(&Killfile::default_path())
+    }
+
+    pub fn load_from(path: &Path) -> Killfile {
+        let contents: Option<KillfileContents> = read_file(&path.to_string_lossy())
+            .and_then(|contents| serde_json::from_str(&contents).ok());
+        let mut killfile = Killfile::new();
+        match contents {
+            Some(contents) => {
+                killfile.story_action = contents.story_action;
+                for rule in contents.rules {
+                    if let Err(e) = killfile.add(rule) {
+                        warn!("Skipped invalid killfile rule {}", e);
+                    }
+                }
+            }
+            None => (),
+        }
+        killfile
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        self.save_to(&Killfile::default_path())
+    }
+
+    pub fn save_to(&self, path: &Path) -> io::Result<()> {
+        let contents = KillfileContents {
+            rules: self.rules.clone(),
+            story_action: self.story_action,
+        };
+        write_file(path, &serde_json::to_string_pretty(&contents).unwrap())
+    }
+
+    pub fn rules(&self) -> &Vec<KillRule> {
+        &self.rules
+    }
+
+    pub fn add(&mut self, rule: KillRule) -> Result<(), String> {
+        let regex = match rule {
+            KillRule::Title(ref pattern) => {
+                Some(Regex::new(pattern).map_err(|e| format!("invalid title regex {} {}", pattern, e))?)
+            }
+            _ => None,
+        };
+        self.rules.push(rule);
+        self.title_regexes.push(regex);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<KillRule> {
+        if index < self.rules.len() {
+            self.title_regexes.remove(index);
+            Some(self.rules.remove(index))
+        } else {
+            None
+        }
+    }
+
+    ///
+    /// Returns the first rule which matches the story, None if the story should be shown
+    ///
+    pub fn matching_story_rule(&self, item: &HnItem) -> Option<&KillRule> {
+        let host = item.url.as_ref().and_then(|url| get_host_from_link(url)).map(|host| host.to_lowercase());
+        self.rules.iter().zip(self.title_regexes.iter()).find(|&(rule, regex)| {
+            match *rule {
+                KillRule::Domain(ref domain) => {
+                    host.as_ref().map(|host| host_matches_domain(host, domain)).unwrap_or(false)
+                }
+                KillRule::Title(_) => {
+                    match (item.title.as_ref(), regex.as_ref()) {
+                        (Some(title), Some(regex)) => regex.is_match(title),
+                        _ => false,
+                    }
+                }
+                KillRule::Author(ref author) => item.by == *author,
+                KillRule::CommentAuthor(_) => false,
+            }
+        }).map(|(rule, _)| rule)
+    }
+
+    pub fn hides_comment(&self, item: &HnItem) -> bool {
+        self.rules.iter().any(|rule| match *rule {
+            KillRule::CommentAuthor(ref author) => item.by == *author,
+            _ => false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use helpers::test_utils::read_item;
+
+    #[test]
+    fn killfile_story_rules_test() {
+        let story = read_item("res/test/item.json");
+        let mut killfile = Killfile::new();
+        assert!(killfile.matching_story_rule(&story).is_none());
+
+        killfile.add(KillRule::parse("domain", "GetDropbox.com").unwrap()).unwrap();
+        assert_eq!("domain getdropbox.com", killfile.matching_story_rule(&story).unwrap().describe());
+        killfile.remove(0);
+
+        killfile.add(KillRule::Title(String::from("(?i)usb drive$"))).unwrap();
+        assert!(killfile.matching_story_rule(&story).is_some());
+        killfile.remove(0);
+
+        killfile.add(KillRule::Author(String::from("dhouston"))).unwrap();
+        assert!(killfile.matching_story_rule(&story).is_some());
+        assert!(!killfile.hides_comment(&story));
+
+        assert!(killfile.add(KillRule::Title(String::from("(unclosed"))).is_err());
+        assert_eq!(1, killfile.rules().len());
+    }
+
+    #[test]
+    fn killfile_comment_rules_test() {
+        let comment = read_item("res/test/children-item.json");
+        let mut killfile = Killfile::new();
+        killfile.add(KillRule::Author(comment.by.clone())).unwrap();
+        assert!(!killfile.hides_comment(&comment));
+        killfile.add(KillRule::CommentAuthor(comment.by.clone())).unwrap();
+        assert!(killfile.hides_comment(&comment));
+    }
+
+    #[test]
+    fn killfile_persist_test() {
+        let path = env::temp_dir().join("hncli-killfile-test.json");
+        let mut killfile = Killfile::new();
+        killfile.story_action = KillAction::Collapse;
+        killfile.add(KillRule::Title(String::from("^Show HN"))).unwrap();
+        killfile.save_to(&path).unwrap();
+        let loaded = Killfile::load_from(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(KillAction::Collapse, loaded.story_action);
+        assert_eq!(KillRule::Title(String::from("^Show HN")), loaded.rules()[0]);
+        assert!(loaded.title_regexes[0].is_some());
+    }
+}
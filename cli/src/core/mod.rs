@@ -3,4 +3,16 @@ pub mod app;
 pub mod client;
 pub mod models;
 pub mod connector;
+pub mod config;
+pub mod watch;
+pub mod notifier;
+pub mod checkpoint;
+pub mod item_walker;
+pub mod event_stream;
+pub mod alerts;
+pub mod killfile;
+pub mod feed_view;
+pub mod search;
+pub mod text_index;
+pub mod thread_search;
 mod endpoint;
\ No newline at end of file
@@ -1,6 +1,7 @@
+use std::borrow::Cow;
 use serde::{Deserialize, Deserializer};
 use serde_json;
-use decoding::text_decoding::decode_html;
+use decoding::text_decoding::decode_html_lossy_cow;
 
 #[derive(Serialize)]
 pub struct HnListOfItems {
@@ -15,11 +16,63 @@ impl<'de> Deserialize<'de> for HnListOfItems {
     }
 }
 
+///
+/// Story listings offered by the api
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HnFeed {
+    Top,
+    New,
+    Best,
+    Ask,
+    Show,
+    Job,
+}
+
+impl HnFeed {
+    pub fn all() -> Vec<HnFeed> {
+        vec![HnFeed::Top, HnFeed::New, HnFeed::Best, HnFeed::Ask, HnFeed::Show, HnFeed::Job]
+    }
+
+    pub fn parse(s: &str) -> Option<HnFeed> {
+        HnFeed::all().into_iter().find(|feed| feed.name() == s)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            HnFeed::Top => "top",
+            HnFeed::New => "new",
+            HnFeed::Best => "best",
+            HnFeed::Ask => "ask",
+            HnFeed::Show => "show",
+            HnFeed::Job => "job",
+        }
+    }
+
+    ///
+    /// Page of the feed on the HackerNews site
+    ///
+    pub fn web_url(&self) -> String {
+        let page = match *self {
+            HnFeed::Top => "news",
+            HnFeed::New => "newest",
+            HnFeed::Best => "best",
+            HnFeed::Ask => "ask",
+            HnFeed::Show => "show",
+            HnFeed::Job => "jobs",
+        };
+        format!("{}{}", HN_WEB_URL, page)
+    }
+}
+
+const HN_WEB_URL: &'static str = "https://news.ycombinator.com/";
+const HN_ITEM_URL: &'static str = "https://news.ycombinator.com/item?id=";
+
 fn default_user() -> String {
     String::from("Undefined user")
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct HnItem {
     #[serde(default = "default_user")]
     pub by: String,
@@ -51,16 +104,72 @@ pub struct HnItemCommentMap {
     pub depth: usize,
 }
 
-impl HnItem {
-    pub fn text_unescaped(&self) -> Option<String> {
-        if self.text.is_some() {
-            let unescaped = decode_html(self.text.as_ref().unwrap());
-            if unescaped.is_ok() {
-                let unwrapped = unescaped.unwrap();
-                return Some(unwrapped);
+///
+/// Item with its replies nested under it, the shape of a thread when it's serialized
+///
+#[derive(Serialize)]
+pub struct HnItemTree {
+    pub item: HnItem,
+    pub replies: Vec<HnItemTree>,
+}
+
+impl HnItemTree {
+    ///
+    /// Nests the comments under the item by their parents, replies keep the order they have in kids.
+    /// Comments whose parent is not in comments are left out
+    ///
+    pub fn build(item: HnItem, comments: &Vec<HnItem>) -> HnItemTree {
+        let mut replies: Vec<HnItemTree> = comments.iter()
+            .filter(|comment| comment.parent == Some(item.id))
+            .map(|comment| HnItemTree::build(comment.clone(), comments))
+            .collect();
+        match item.kids {
+            Some(ref kids) => {
+                replies.sort_by_key(|reply| kids.iter().position(|kid| *kid == reply.item.id).unwrap_or(kids.len()))
             }
+            None => (),
+        }
+        HnItemTree {
+            item: item,
+            replies: replies,
         }
-        None
+    }
+
+    pub fn count(&self) -> usize {
+        1 + self.replies.iter().map(|reply| reply.count()).sum::<usize>()
+    }
+}
+
+impl HnItem {
+    ///
+    /// Text with the html entities decoded, references which can't be decoded are kept as they are
+    ///
+    pub fn text_unescaped(&self) -> Option<Cow<'_, str>> {
+        self.text.as_ref().map(|text| decode_html_lossy_cow(text))
+    }
+
+    pub fn stats(&self) -> HnItemStats {
+        HnItemStats {
+            score: self.score.unwrap_or(0),
+            comments: self.comment_count(),
+        }
+    }
+
+    ///
+    /// Number of comments in the whole thread for stories, direct replies for comments
+    ///
+    pub fn comment_count(&self) -> i32 {
+        match self.descendants {
+            Some(descendants) => descendants,
+            None => self.kids.as_ref().map(|kids| kids.len() as i32).unwrap_or(0),
+        }
+    }
+
+    ///
+    /// Page of the item on the HackerNews site, the discussion for stories
+    ///
+    pub fn web_url(&self) -> String {
+        format!("{}{}", HN_ITEM_URL, self.id)
     }
 
     pub fn to_json(&self) -> String {
@@ -71,12 +180,29 @@ impl HnItem {
     }
 }
 
+///
+/// Snapshot of the numbers shown in listings, used to detect rows which moved between refreshes
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct HnItemStats {
+    pub score: i32,
+    pub comments: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HnUpdates {
+    pub items: Vec<i32>,
+    pub profiles: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct HnUser {
+    #[serde(default)]
     pub about: String,
     pub created: f64,
     pub id: String,
     pub karma: i32,
+    #[serde(default)]
     pub submitted: Vec<i32>,
 }
 
@@ -148,6 +274,74 @@ mod tests {
         assert!(deserialized.submitted.len() > 3);
     }
 
+    #[test]
+    fn hn_updates_serde_test() {
+        use std::fs::File;
+        use std::io::prelude::*;
+        let mut contents = String::new();
+        File::open("res/test/updates.json")
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .unwrap();
+        let deserialized: HnUpdates = serde_json::from_str(&contents).unwrap();
+        assert!(deserialized.items.contains(&8423305));
+        assert_eq!("thefox", deserialized.profiles[0]);
+    }
+
+    #[test]
+    fn hn_item_stats_test() {
+        use std::fs::File;
+        use std::io::prelude::*;
+        let mut contents = String::new();
+        File::open("res/test/item.json")
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .unwrap();
+        let deserialized: HnItem = serde_json::from_str(&contents).unwrap();
+        let stats = deserialized.stats();
+        assert_eq!(111, stats.score);
+        // the same count as the listing shows, not only the direct replies
+        assert_eq!(71, stats.comments);
+        assert_eq!(deserialized.comment_count(), stats.comments);
+    }
+
+    #[test]
+    fn hn_feed_parse_test() {
+        for feed in HnFeed::all() {
+            assert_eq!(Some(feed), HnFeed::parse(feed.name()));
+        }
+        assert!(HnFeed::parse("worst").is_none());
+    }
+
+    #[test]
+    fn hn_item_tree_test() {
+        use std::fs::File;
+        use std::io::prelude::*;
+        let mut contents = String::new();
+        File::open("res/test/item.json")
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .unwrap();
+        let story: HnItem = serde_json::from_str(&contents).unwrap();
+        let mut first = story.clone();
+        first.id = 1;
+        first.parent = Some(story.id);
+        first.kids = None;
+        let mut second = first.clone();
+        second.id = story.kids.as_ref().unwrap()[0];
+        let mut reply = first.clone();
+        reply.id = 2;
+        reply.parent = Some(second.id);
+        let mut orphan = first.clone();
+        orphan.id = 3;
+        orphan.parent = Some(4);
+
+        let tree = HnItemTree::build(story, &vec![first, reply, second.clone(), orphan]);
+        assert_eq!(4, tree.count());
+        assert_eq!(vec![second.id, 1], tree.replies.iter().map(|r| r.item.id).collect::<Vec<i32>>());
+        assert_eq!(2, tree.replies[0].replies[0].item.id);
+        let json = serde_json::to_string(&tree).unwrap();
+        assert!(json.starts_with("{\"item\":{\"by\":\"dhouston\""));
+        assert!(json.contains("\"replies\":[{\"item\":"));
+    }
+
     #[test]
     fn dead_hnitem() {
         use std::fs::File;
@@ -0,0 +1,128 @@
+use std::path::PathBuf;
+use std::process::Command;
+use chrono;
+
+use helpers::io_utils::append_to_file;
+use helpers::path_utils::resolve_data_path;
+use ui::cli;
+use super::config::AppConfig;
+use super::watch::WatchNotification;
+
+pub trait Notify {
+    fn notify(&self, notifications: &Vec<WatchNotification>);
+}
+
+pub struct StdoutNotifier;
+
+impl Notify for StdoutNotifier {
+    fn notify(&self, notifications: &Vec<WatchNotification>) {
+        for notification in notifications {
+            cli::print_watch_notification(notification);
+        }
+    }
+}
+
+///
+/// Appends a section for each check with new items to a plain text digest file
+///
+pub struct DigestNotifier {
+    pub path: PathBuf,
+}
+
+impl Notify for DigestNotifier {
+    fn notify(&self, notifications: &Vec<WatchNotification>) {
+        let result = append_to_file(&self.path, &create_digest(notifications));
+        if result.is_err() {
+            warn!("Could not write watch digest to {}", self.path.to_string_lossy());
+        }
+    }
+}
+
+///
+/// Runs the configured shell command once per notification
+///
+pub struct HookNotifier {
+    pub command: String,
+}
+
+impl Notify for HookNotifier {
+    fn notify(&self, notifications: &Vec<WatchNotification>) {
+        for notification in notifications {
+            let status = Command::new("sh")
+                .arg("-c")
+                .arg(&self.command)
+                .env("HN_ITEM_ID", notification.item_id.to_string())
+                .env("HN_WATCH", &notification.watch)
+                .env("HN_EXCERPT", &notification.excerpt)
+                .status();
+            match status {
+                Ok(ref status) if status.success() => (),
+                Ok(status) => warn!("Watch hook exited with {} for item {}", status, notification.item_id),
+                Err(e) => warn!("Could not run watch hook {}", e),
+            }
+        }
+    }
+}
+
+pub struct Notifiers {
+    notifiers: Vec<Box<Notify>>,
+}
+
+impl Notifiers {
+    pub fn from_config(config: &AppConfig) -> Notifiers {
+        let mut notifiers: Vec<Box<Notify>> = Vec::new();
+        if config.notifies_with("stdout") {
+            notifiers.push(Box::new(StdoutNotifier));
+        }
+        if config.notifies_with("digest") {
+            let path = resolve_data_path(&config.watch_digest_file);
+            notifiers.push(Box::new(DigestNotifier { path: path }));
+        }
+        if config.notifies_with("hook") {
+            match config.watch_hook {
+                Some(ref command) => notifiers.push(Box::new(HookNotifier { command: command.clone() })),
+                None => warn!("Watch notifications to hook are enabled but there's no watch_hook configured"),
+            }
+        }
+        Notifiers { notifiers: notifiers }
+    }
+}
+
+impl Notify for Notifiers {
+    fn notify(&self, notifications: &Vec<WatchNotification>) {
+        if notifications.is_empty() {
+            return;
+        }
+        for notifier in self.notifiers.iter() {
+            notifier.notify(notifications);
+        }
+    }
+}
+
+fn create_digest(notifications: &Vec<WatchNotification>) -> String {
+    let mut digest = format!("== {} new items at {} ==\n",
+                             notifications.len(),
+                             chrono::Local::now().format("%Y-%m-%d %H:%M:%S"));
+    for notification in notifications {
+        digest.push_str(&format!("[{}] {}: {}\n", notification.item_id, notification.watch, notification.excerpt));
+    }
+    digest.push('\n');
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_digest_test() {
+        let notifications = vec![WatchNotification {
+                                     watch: String::from("item 8863"),
+                                     item_id: 9224,
+                                     excerpt: String::from("This is a new reply"),
+                                 }];
+        let digest = create_digest(&notifications);
+        assert!(digest.starts_with("== 1 new items at "));
+        assert!(digest.contains("[9224] item 8863: This is a new reply\n"));
+    }
+}
@@ -0,0 +1,311 @@
+use chrono::NaiveDate;
+use url::Url;
+
+use helpers::gen_utils::parse_duration_secs;
+use super::models::HnItem;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SearchTag {
+    Story,
+    Comment,
+    AskHn,
+    ShowHn,
+}
+
+impl SearchTag {
+    pub fn parse(s: &str) -> Option<SearchTag> {
+        match s {
+            "story" => Some(SearchTag::Story),
+            "comment" => Some(SearchTag::Comment),
+            "ask_hn" => Some(SearchTag::AskHn),
+            "show_hn" => Some(SearchTag::ShowHn),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SearchTag::Story => "story",
+            SearchTag::Comment => "comment",
+            SearchTag::AskHn => "ask_hn",
+            SearchTag::ShowHn => "show_hn",
+        }
+    }
+}
+
+///
+/// Query for an HN Search (Algolia) compatible api, built from the words of the search command
+/// where `tag:`, `author:`, `since:`, `until:`, `points:` and `sort:` words are filters
+///
+#[derive(Debug)]
+pub struct SearchQuery {
+    pub text: String,
+    pub tags: Vec<SearchTag>,
+    pub author: Option<String>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub min_points: Option<i32>,
+    pub by_date: bool,
+}
+
+impl SearchQuery {
+    pub fn parse(words: &Vec<String>, now: i64) -> Result<SearchQuery, String> {
+        let mut query = SearchQuery {
+            text: String::new(),
+            tags: Vec::new(),
+            author: None,
+            since: None,
+            until: None,
+            min_points: None,
+            by_date: false,
+        };
+        let mut text_words = Vec::new();
+        for word in words {
+            let (key, value) = match word.find(':') {
+                Some(i) => (&word[..i], &word[i + 1..]),
+                None => ("", word.as_str()),
+            };
+            match key {
+                "tag" => {
+                    for tag in value.split(',') {
+                        query.tags.push(SearchTag::parse(tag).ok_or(format!("unknown tag {}", tag))?);
+                    }
+                }
+                "author" => query.author = Some(String::from(value)),
+                "since" => query.since = Some(parse_date_bound(value, now, false)?),
+                "until" => query.until = Some(parse_date_bound(value, now, true)?),
+                "points" => query.min_points = Some(value.parse::<i32>().map_err(|_| format!("invalid points {}", value))?),
+                "sort" => {
+                    query.by_date = match value {
+                        "date" => true,
+                        "relevance" => false,
+                        _ => return Err(format!("unknown sort {}", value)),
+                    }
+                }
+                _ => text_words.push(word.as_str()),
+            }
+        }
+        query.text = text_words.join(" ");
+        if query.text.is_empty() && query.tags.is_empty() && query.author.is_none() {
+            return Err(String::from("nothing to search"));
+        }
+        Ok(query)
+    }
+
+    ///
+    /// Url of the query under the api base url, like https://hn.algolia.com/api/v1/
+    ///
+    pub fn to_url(&self, base_url: &str, hits_per_page: usize) -> Result<String, String> {
+        let endpoint = if self.by_date { "search_by_date" } else { "search" };
+        let separator = if base_url.ends_with('/') { "" } else { "/" };
+        let mut url = Url::parse(&format!("{}{}{}", base_url, separator, endpoint))
+            .map_err(|e| format!("invalid search url {} {}", base_url, e))?;
+        {
+            let mut pairs = url.query_pairs_mut();
+            pairs.append_pair("query", &self.text);
+            pairs.append_pair("hitsPerPage", &hits_per_page.to_string());
+            let tags = self.tag_filter();
+            if !tags.is_empty() {
+                pairs.append_pair("tags", &tags);
+            }
+            let numeric = self.numeric_filters();
+            if !numeric.is_empty() {
+                pairs.append_pair("numericFilters", &numeric);
+            }
+        }
+        Ok(url.into_string())
+    }
+
+    /// Tags are ANDed with commas and ORed inside parentheses
+    fn tag_filter(&self) -> String {
+        let mut filters = Vec::new();
+        match self.tags.len() {
+            0 => (),
+            1 => filters.push(String::from(self.tags[0].name())),
+            _ => {
+                let names: Vec<&str> = self.tags.iter().map(|tag| tag.name()).collect();
+                filters.push(format!("({})", names.join(",")));
+            }
+        }
+        match self.author {
+            Some(ref author) => filters.push(format!("author_{}", author)),
+            None => (),
+        }
+        filters.join(",")
+    }
+
+    fn numeric_filters(&self) -> String {
+        let mut filters = Vec::new();
+        match self.since {
+            Some(since) => filters.push(format!("created_at_i>={}", since)),
+            None => (),
+        }
+        match self.until {
+            Some(until) => filters.push(format!("created_at_i<{}", until)),
+            None => (),
+        }
+        match self.min_points {
+            Some(points) => filters.push(format!("points>={}", points)),
+            None => (),
+        }
+        filters.join(",")
+    }
+}
+
+///
+/// Dates are YYYY-MM-DD in UTC or ages like 7d counted back from now, `until` dates include the whole day
+///
+fn parse_date_bound(value: &str, now: i64, end_of_day: bool) -> Result<i64, String> {
+    match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(date) => {
+            let start = date.and_hms(0, 0, 0).timestamp();
+            Ok(if end_of_day { start + 24 * 60 * 60 } else { start })
+        }
+        Err(_) => parse_duration_secs(value).map(|secs| now - secs).ok_or(format!("invalid date {}", value)),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SearchHit {
+    #[serde(rename = "objectID")]
+    pub object_id: String,
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub points: Option<i32>,
+    #[serde(default)]
+    pub num_comments: Option<i32>,
+    #[serde(default)]
+    pub story_text: Option<String>,
+    #[serde(default)]
+    pub comment_text: Option<String>,
+    #[serde(default)]
+    pub story_title: Option<String>,
+    #[serde(default)]
+    pub parent_id: Option<i32>,
+    pub created_at_i: i64,
+    #[serde(default, rename = "_tags")]
+    pub tags: Vec<String>,
+}
+
+impl SearchHit {
+    ///
+    /// Maps the hit into an item which can be listed like stories, comments get the title of their story.
+    /// Hits with non numeric ids are not items and give None.
+    ///
+    pub fn to_item(&self) -> Option<HnItem> {
+        let id = match self.object_id.parse::<i32>() {
+            Ok(id) => id,
+            Err(_) => return None,
+        };
+        let is_comment = self.tags.iter().any(|tag| tag == "comment");
+        let title = if is_comment {
+            Some(format!("Re: {}", self.story_title.as_ref().map(|t| t.as_str()).unwrap_or("")))
+        } else {
+            self.title.clone()
+        };
+        Some(HnItem {
+            by: self.author.clone().unwrap_or(String::from("Undefined user")),
+            parent: self.parent_id,
+            descendants: self.num_comments,
+            id: id,
+            kids: None,
+            title: title,
+            score: self.points,
+            text: if is_comment { self.comment_text.clone() } else { self.story_text.clone() },
+            time: self.created_at_i as f64,
+            type_str: String::from(if is_comment { "comment" } else { "story" }),
+            url: self.url.clone().and_then(|url| if url.is_empty() { None } else { Some(url) }),
+            dead: None,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SearchResults {
+    pub hits: Vec<SearchHit>,
+    #[serde(default, rename = "nbHits")]
+    pub total_hits: usize,
+}
+
+impl SearchResults {
+    pub fn to_items(&self) -> Vec<HnItem> {
+        self.hits.iter().filter_map(|hit| hit.to_item()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+    use helpers::io_utils::read_file;
+
+    fn words(s: &str) -> Vec<String> {
+        s.split_whitespace().map(|w| String::from(w)).collect()
+    }
+
+    #[test]
+    fn search_query_parse_test() {
+        let query = SearchQuery::parse(&words("rust async tag:story,show_hn author:pg points:100 since:2017-01-01 until:2017-01-31"),
+                                       0)
+            .unwrap();
+        assert_eq!("rust async", query.text);
+        assert_eq!(vec![SearchTag::Story, SearchTag::ShowHn], query.tags);
+        assert_eq!(Some(String::from("pg")), query.author);
+        assert_eq!(Some(100), query.min_points);
+        assert_eq!(Some(1483228800), query.since);
+        assert_eq!(Some(1485907200), query.until);
+
+        let relative = SearchQuery::parse(&words("dropbox since:7d sort:date"), 1000000).unwrap();
+        assert_eq!(Some(1000000 - 7 * 24 * 60 * 60), relative.since);
+        assert!(relative.by_date);
+
+        assert!(SearchQuery::parse(&words("rust tag:poll"), 0).is_err());
+        assert!(SearchQuery::parse(&words("rust points:many"), 0).is_err());
+        assert!(SearchQuery::parse(&words("since:7d"), 0).is_err());
+    }
+
+    #[test]
+    fn search_query_url_test() {
+        let query = SearchQuery::parse(&words("usb drive tag:story,comment author:dhouston points:10 since:2007-04-01"), 0).unwrap();
+        let url = Url::parse(&query.to_url("http://127.0.0.1:8080/api/v1", 20).unwrap()).unwrap();
+        assert_eq!("/api/v1/search", url.path());
+        let pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        assert!(pairs.contains(&(String::from("query"), String::from("usb drive"))));
+        assert!(pairs.contains(&(String::from("hitsPerPage"), String::from("20"))));
+        assert!(pairs.contains(&(String::from("tags"), String::from("(story,comment),author_dhouston"))));
+        assert!(pairs.contains(&(String::from("numericFilters"),
+                                 String::from("created_at_i>=1175385600,points>=10"))));
+
+        let by_date = SearchQuery::parse(&words("rust sort:date"), 0).unwrap();
+        assert!(by_date.to_url("https://hn.algolia.com/api/v1/", 30).unwrap().starts_with("https://hn.algolia.com/api/v1/search_by_date?"));
+    }
+
+    #[test]
+    fn search_results_to_items_test() {
+        let results: SearchResults = read_file("res/test/search-results.json")
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap();
+        assert_eq!(3, results.total_hits);
+        let items = results.to_items();
+        assert_eq!(3, items.len());
+
+        let story = &items[0];
+        assert_eq!(8863, story.id);
+        assert_eq!("My YC app: Dropbox - Throw away your USB drive", story.title.as_ref().unwrap());
+        assert_eq!(Some(111), story.score);
+        assert_eq!(71, story.comment_count());
+        assert_eq!("story", story.type_str);
+
+        let comment = &items[1];
+        assert_eq!("comment", comment.type_str);
+        assert_eq!("Re: My YC app: Dropbox - Throw away your USB drive", comment.title.as_ref().unwrap());
+        assert!(comment.text.is_some());
+        assert!(comment.url.is_none());
+        assert_eq!(Some(8863), comment.parent);
+    }
+}
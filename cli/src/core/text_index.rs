@@ -0,0 +1,650 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde_json;
+
+use formatting::formatter::FormatStr;
+use helpers::io_utils::{read_file, write_file_atomic};
+use helpers::path_utils::get_data_file_path;
+use super::models::HnItem;
+
+const INDEX_FILENAME: &'static str = "index.json";
+/// How many items are kept when no other limit is set, the oldest are dropped first
+pub const DEFAULT_MAX_DOCS: usize = 20000;
+/// Offset between positions of fields so phrases don't match across title, text and author
+const FIELD_OFFSET: usize = 1000000;
+
+///
+/// Searchable contents of an item, text is decoded and formatted
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct IndexedDoc {
+    pub id: i32,
+    pub by: String,
+    pub title: Option<String>,
+    pub text: String,
+    pub time: f64,
+    pub type_str: String,
+    pub url: Option<String>,
+    pub score: Option<i32>,
+    pub descendants: Option<i32>,
+    pub parent: Option<i32>,
+}
+
+impl IndexedDoc {
+    pub fn from_item(item: &HnItem, format: &FormatStr) -> IndexedDoc {
+        IndexedDoc {
+            id: item.id,
+            by: item.by.clone(),
+            title: item.title.clone(),
+            text: item.text_unescaped().map(|text| format.format(&text)).unwrap_or(String::new()),
+            time: item.time,
+            type_str: item.type_str.clone(),
+            url: item.url.clone(),
+            score: item.score,
+            descendants: item.descendants,
+            parent: item.parent,
+        }
+    }
+
+    ///
+    /// Item for listings, comments are titled with the part of their text where the first term matched
+    ///
+    pub fn to_item(&self, terms: &Vec<String>) -> HnItem {
+        let title = match self.title {
+            Some(ref title) => title.clone(),
+            None => format!("Comment: {}", create_snippet(&self.text, terms, 60)),
+        };
+        HnItem {
+            by: self.by.clone(),
+            parent: self.parent,
+            descendants: self.descendants,
+            id: self.id,
+            kids: None,
+            title: Some(title),
+            score: self.score,
+            text: None,
+            time: self.time,
+            type_str: self.type_str.clone(),
+            url: self.url.clone(),
+            dead: None,
+        }
+    }
+
+    fn positioned_terms(&self) -> Vec<(String, usize)> {
+        let mut terms = Vec::new();
+        let fields = vec![self.title.as_ref().map(|t| t.as_str()).unwrap_or(""), self.text.as_str(), self.by.as_str()];
+        for (field_index, field) in fields.iter().enumerate() {
+            for (position, (_, token)) in tokenize(field).into_iter().enumerate() {
+                terms.push((token, field_index * FIELD_OFFSET + position));
+            }
+        }
+        terms
+    }
+}
+
+///
+/// Lowercase alphanumeric words of the text with their byte offsets in the text
+///
+pub fn tokenize(text: &str) -> Vec<(usize, String)> {
+    let mut tokens = Vec::new();
+    let mut start: Option<usize> = None;
+    for (i, c) in text.char_indices() {
+        if c.is_alphanumeric() {
+            if start.is_none() {
+                start = Some(i);
+            }
+        } else if let Some(s) = start.take() {
+            tokens.push((s, text[s..i].to_lowercase()));
+        }
+    }
+    if let Some(s) = start {
+        tokens.push((s, text[s..].to_lowercase()));
+    }
+    tokens
+}
+
+///
+/// Byte ranges of the words in text which are any of the terms
+///
+pub fn find_term_ranges(text: &str, terms: &Vec<String>) -> Vec<(usize, usize)> {
+    tokenize(text)
+        .into_iter()
+        .filter(|&(_, ref token)| terms.contains(token))
+        .map(|(start, _)| {
+            // lowercasing can change the length so the end is searched from the original
+            let end = text[start..].char_indices()
+                .find(|&(_, c)| !c.is_alphanumeric())
+                .map(|(i, _)| start + i)
+                .unwrap_or(text.len());
+            (start, end)
+        })
+        .collect()
+}
+
+fn create_snippet(text: &str, terms: &Vec<String>, max_chars: usize) -> String {
+    let start = find_term_ranges(text, terms).first().map(|&(start, _)| start).unwrap_or(0);
+    // start three words before the match so it has some context
+    let context_start = text[..start].rmatch_indices(' ').nth(3).map(|(i, _)| i + 1).unwrap_or(0);
+    let snippet: String = text[context_start..].chars().take(max_chars).collect();
+    let snippet = snippet.replace('\n', " ");
+    if context_start > 0 { format!("...{}", snippet) } else { snippet }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum IndexQuery {
+    Term(String),
+    Phrase(Vec<String>),
+    And(Box<IndexQuery>, Box<IndexQuery>),
+    Or(Box<IndexQuery>, Box<IndexQuery>),
+    Not(Box<IndexQuery>),
+}
+
+impl IndexQuery {
+    ///
+    /// Parses queries like `rust AND (async OR tokio) NOT "web framework"`, words next to each other are ANDed,
+    /// AND binds tighter than OR and `-word` is the same as `NOT word`
+    ///
+    pub fn parse(query: &str) -> Result<IndexQuery, String> {
+        let tokens = lex_query(query)?;
+        if tokens.is_empty() {
+            return Err(String::from("empty query"));
+        }
+        let mut parser = QueryParser { tokens: tokens, position: 0 };
+        let parsed = parser.parse_or()?;
+        match parser.tokens.get(parser.position) {
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Ok(parsed),
+        }
+    }
+
+    ///
+    /// Words which have to be found for the query to match, used for ranking and highlighting
+    ///
+    pub fn positive_terms(&self) -> Vec<String> {
+        match *self {
+            IndexQuery::Term(ref term) => vec![term.clone()],
+            IndexQuery::Phrase(ref words) => words.clone(),
+            IndexQuery::And(ref left, ref right) |
+            IndexQuery::Or(ref left, ref right) => {
+                let mut terms = left.positive_terms();
+                terms.extend(right.positive_terms());
+                terms
+            }
+            IndexQuery::Not(_) => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum QueryToken {
+    Word(String),
+    Phrase(Vec<String>),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn lex_query(query: &str) -> Result<Vec<QueryToken>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let phrase: String = chars.by_ref().take_while(|c| *c != '"').collect();
+                let words: Vec<String> = tokenize(&phrase).into_iter().map(|(_, word)| word).collect();
+                if !words.is_empty() {
+                    tokens.push(QueryToken::Phrase(words));
+                }
+            }
+            '(' => tokens.push(QueryToken::Open),
+            ')' => tokens.push(QueryToken::Close),
+            '-' => tokens.push(QueryToken::Not),
+            c if c.is_whitespace() => (),
+            c => {
+                let mut word = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || next == '(' || next == ')' || next == '"' {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+                match word.as_str() {
+                    "AND" => tokens.push(QueryToken::And),
+                    "OR" => tokens.push(QueryToken::Or),
+                    "NOT" => tokens.push(QueryToken::Not),
+                    _ => {
+                        let words: Vec<String> = tokenize(&word).into_iter().map(|(_, word)| word).collect();
+                        match words.len() {
+                            0 => return Err(format!("nothing to search in {}", word)),
+                            1 => tokens.push(QueryToken::Word(words[0].clone())),
+                            _ => tokens.push(QueryToken::Phrase(words)), // like don't or node.js
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct QueryParser {
+    tokens: Vec<QueryToken>,
+    position: usize,
+}
+
+impl QueryParser {
+    fn peek(&self) -> Option<&QueryToken> {
+        self.tokens.get(self.position)
+    }
+
+    fn parse_or(&mut self) -> Result<IndexQuery, String> {
+        let mut left = self.parse_and()?;
+        while self.peek() == Some(&QueryToken::Or) {
+            self.position += 1;
+            let right = self.parse_and()?;
+            left = IndexQuery::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<IndexQuery, String> {
+        let mut left = self.parse_unary()?;
+        loop {
+            match self.peek() {
+                Some(&QueryToken::And) => self.position += 1,
+                Some(&QueryToken::Or) | Some(&QueryToken::Close) | None => break,
+                _ => (), // implicit AND
+            }
+            let right = self.parse_unary()?;
+            left = IndexQuery::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<IndexQuery, String> {
+        let token = self.peek().cloned();
+        self.position += 1;
+        match token {
+            Some(QueryToken::Not) => Ok(IndexQuery::Not(Box::new(self.parse_unary()?))),
+            Some(QueryToken::Word(word)) => Ok(IndexQuery::Term(word)),
+            Some(QueryToken::Phrase(words)) => Ok(IndexQuery::Phrase(words)),
+            Some(QueryToken::Open) => {
+                let inner = self.parse_or()?;
+                match self.peek() {
+                    Some(&QueryToken::Close) => {
+                        self.position += 1;
+                        Ok(inner)
+                    }
+                    _ => Err(String::from("missing )")),
+                }
+            }
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err(String::from("query ends too early")),
+        }
+    }
+}
+
+pub struct IndexHit {
+    pub id: i32,
+    pub score: f64,
+}
+
+///
+/// Inverted index over title, text and author of items the client has retrieved, persisted in the data directory.
+/// Only the documents are stored, postings are rebuilt when the index is loaded.
+///
+pub struct TextIndex {
+    docs: HashMap<i32, IndexedDoc>,
+    /// term -> item id -> positions of the term in the item
+    postings: HashMap<String, HashMap<i32, Vec<usize>>>,
+    dirty: bool,
+    max_docs: usize,
+}
+
+impl TextIndex {
+    pub fn new() -> TextIndex {
+        TextIndex {
+            docs: HashMap::new(),
+            postings: HashMap::new(),
+            dirty: false,
+            max_docs: DEFAULT_MAX_DOCS,
+        }
+    }
+
+    pub fn default_path() -> PathBuf {
+        get_data_file_path(INDEX_FILENAME)
+    }
+
+    pub fn load() -> TextIndex {
+        TextIndex::load_from(&TextIndex::default_path())
+    }
+
+    ///
+    /// An index which can't be parsed is moved aside so saving doesn't overwrite it
+    ///
+    pub fn load_from(path: &Path) -> TextIndex {
+        let docs: Vec<IndexedDoc> = match read_file(&path.to_string_lossy()) {
+            Some(contents) => {
+                match serde_json::from_str(&contents) {
+                    Ok(docs) => docs,
+                    Err(e) => {
+                        let broken = path.with_extension("json.broken");
+                        warn!("Invalid index in {}, starting a new one and keeping the old in {} {}",
+                              path.to_string_lossy(),
+                              broken.to_string_lossy(),
+                              e);
+                        let _ = fs::rename(path, &broken);
+                        Vec::new()
+                    }
+                }
+            }
+            None => Vec::new(),
+        };
+        let mut index = TextIndex::new();
+        for doc in docs {
+            index.add_doc(doc);
+        }
+        index.dirty = false;
+        index
+    }
+
+    pub fn save(&mut self) -> io::Result<()> {
+        let path = TextIndex::default_path();
+        self.save_to(&path)
+    }
+
+    pub fn save_to(&mut self, path: &Path) -> io::Result<()> {
+        self.prune();
+        let docs: Vec<&IndexedDoc> = self.docs.values().collect();
+        write_file_atomic(path, &serde_json::to_string(&docs).unwrap())?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    pub fn set_max_docs(&mut self, max_docs: usize) {
+        self.max_docs = max_docs;
+    }
+
+    ///
+    /// Drops the oldest items over the limit
+    ///
+    fn prune(&mut self) {
+        if self.docs.len() <= self.max_docs {
+            return;
+        }
+        let mut by_age: Vec<(f64, i32)> = self.docs.values().map(|doc| (doc.time, doc.id)).collect();
+        by_age.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let excess = by_age.len() - self.max_docs;
+        for &(_, id) in by_age.iter().take(excess) {
+            self.remove(id);
+        }
+        self.dirty = true;
+    }
+
+    /// Whether there are additions which are not saved yet
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    pub fn doc(&self, id: i32) -> Option<&IndexedDoc> {
+        self.docs.get(&id)
+    }
+
+    pub fn add(&mut self, item: &HnItem, format: &FormatStr) {
+        if item.dead.unwrap_or(false) {
+            return;
+        }
+        self.add_doc(IndexedDoc::from_item(item, format));
+    }
+
+    fn add_doc(&mut self, doc: IndexedDoc) {
+        self.remove(doc.id); // items are re-indexed when they're retrieved again
+        for (term, position) in doc.positioned_terms() {
+            self.postings.entry(term).or_insert(HashMap::new()).entry(doc.id).or_insert(Vec::new()).push(position);
+        }
+        self.docs.insert(doc.id, doc);
+        self.dirty = true;
+    }
+
+    fn remove(&mut self, id: i32) {
+        match self.docs.remove(&id) {
+            Some(doc) => {
+                for (term, _) in doc.positioned_terms() {
+                    let now_empty = match self.postings.get_mut(&term) {
+                        Some(docs) => {
+                            docs.remove(&id);
+                            docs.is_empty()
+                        }
+                        None => false,
+                    };
+                    if now_empty {
+                        self.postings.remove(&term);
+                    }
+                }
+            }
+            None => (),
+        }
+    }
+
+    ///
+    /// Items matching the query ranked by tf-idf of the query terms, newer items are boosted
+    ///
+    pub fn search(&self, query: &IndexQuery, now: i64) -> Vec<IndexHit> {
+        let matching = self.evaluate(query);
+        let terms = query.positive_terms();
+        let mut hits: Vec<IndexHit> = matching.into_iter()
+            .map(|id| {
+                IndexHit {
+                    id: id,
+                    score: self.relevance(id, &terms) * recency_boost(self.docs[&id].time, now),
+                }
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap().then(b.id.cmp(&a.id)));
+        hits
+    }
+
+    fn evaluate(&self, query: &IndexQuery) -> HashSet<i32> {
+        match *query {
+            IndexQuery::Term(ref term) => {
+                self.postings.get(term).map(|docs| docs.keys().cloned().collect()).unwrap_or(HashSet::new())
+            }
+            IndexQuery::Phrase(ref words) => self.evaluate_phrase(words),
+            IndexQuery::And(ref left, ref right) => {
+                match **right {
+                    // NOT only makes sense as a difference, the complement of everything is rarely wanted
+                    IndexQuery::Not(ref excluded) => &self.evaluate(left) - &self.evaluate(excluded),
+                    _ => &self.evaluate(left) & &self.evaluate(right),
+                }
+            }
+            IndexQuery::Or(ref left, ref right) => &self.evaluate(left) | &self.evaluate(right),
+            IndexQuery::Not(ref excluded) => {
+                let all: HashSet<i32> = self.docs.keys().cloned().collect();
+                &all - &self.evaluate(excluded)
+            }
+        }
+    }
+
+    fn evaluate_phrase(&self, words: &Vec<String>) -> HashSet<i32> {
+        let postings: Vec<&HashMap<i32, Vec<usize>>> = match words.iter().map(|word| self.postings.get(word)).collect() {
+            Some(postings) => postings,
+            None => return HashSet::new(),
+        };
+        postings[0]
+            .iter()
+            .filter(|&(id, first_positions)| {
+                first_positions.iter().any(|start| {
+                    postings.iter().enumerate().skip(1).all(|(offset, docs)| {
+                        docs.get(id).map(|positions| positions.contains(&(start + offset))).unwrap_or(false)
+                    })
+                })
+            })
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    fn relevance(&self, id: i32, terms: &Vec<String>) -> f64 {
+        let doc_count = self.docs.len() as f64;
+        terms.iter()
+            .filter_map(|term| self.postings.get(term))
+            .map(|docs| {
+                let tf = docs.get(&id).map(|positions| 1.0 + (positions.len() as f64).ln()).unwrap_or(0.0);
+                let idf = (1.0 + doc_count / docs.len() as f64).ln();
+                tf * idf
+            })
+            .sum()
+    }
+}
+
+/// Twice the relevance for items from today, fading towards no boost over weeks
+fn recency_boost(time: f64, now: i64) -> f64 {
+    let age_days = ((now as f64 - time) / (24.0 * 60.0 * 60.0)).max(0.0);
+    1.0 + 1.0 / (1.0 + age_days / 7.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use helpers::io_utils::write_file;
+    use formatting::formatter::Formatters;
+
+    fn doc(id: i32, title: Option<&str>, text: &str, by: &str, time: f64) -> IndexedDoc {
+        IndexedDoc {
+            id: id,
+            by: String::from(by),
+            title: title.map(|t| String::from(t)),
+            text: String::from(text),
+            time: time,
+            type_str: String::from(if title.is_some() { "story" } else { "comment" }),
+            url: None,
+            score: None,
+            descendants: None,
+            parent: None,
+        }
+    }
+
+    fn test_index() -> TextIndex {
+        let mut index = TextIndex::new();
+        index.add_doc(doc(1, Some("Async Rust in practice"), "", "alice", 1000.0));
+        index.add_doc(doc(2, None, "I moved from Go to Rust and never looked back", "bob", 2000.0));
+        index.add_doc(doc(3, Some("Go generics are here"), "", "carol", 3000.0));
+        index.add_doc(doc(4, None, "Practice makes rust go away, or so they say", "dave", 4000.0));
+        index
+    }
+
+    fn ids(index: &TextIndex, query: &str) -> Vec<i32> {
+        let mut ids: Vec<i32> = index.search(&IndexQuery::parse(query).unwrap(), 4000).iter().map(|hit| hit.id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn index_query_parse_test() {
+        assert_eq!(IndexQuery::And(Box::new(IndexQuery::Term(String::from("rust"))),
+                                   Box::new(IndexQuery::Not(Box::new(IndexQuery::Term(String::from("go")))))),
+                   IndexQuery::parse("Rust -go").unwrap());
+        assert_eq!(IndexQuery::Or(Box::new(IndexQuery::Term(String::from("a"))),
+                                  Box::new(IndexQuery::And(Box::new(IndexQuery::Term(String::from("b"))),
+                                                           Box::new(IndexQuery::Term(String::from("c")))))),
+                   IndexQuery::parse("a OR b AND c").unwrap());
+        assert_eq!(IndexQuery::Phrase(vec![String::from("usb"), String::from("drive")]),
+                   IndexQuery::parse("\"USB drive\"").unwrap());
+        assert!(IndexQuery::parse("(rust").is_err());
+        assert!(IndexQuery::parse("rust AND").is_err());
+        assert!(IndexQuery::parse("").is_err());
+    }
+
+    #[test]
+    fn text_index_boolean_search_test() {
+        let index = test_index();
+        assert_eq!(vec![1, 2, 4], ids(&index, "rust"));
+        assert_eq!(vec![2, 4], ids(&index, "rust AND go"));
+        assert_eq!(vec![1], ids(&index, "rust NOT go"));
+        assert_eq!(vec![1, 3], ids(&index, "async OR generics"));
+        assert_eq!(vec![1, 4], ids(&index, "(async OR away) practice"));
+        assert_eq!(vec![3], ids(&index, "NOT rust"));
+        assert_eq!(vec![2], ids(&index, "bob"));
+    }
+
+    #[test]
+    fn text_index_phrase_search_test() {
+        let index = test_index();
+        assert_eq!(vec![1], ids(&index, "\"rust in practice\""));
+        assert_eq!(vec![2], ids(&index, "\"go to rust\""));
+        assert!(ids(&index, "\"practice rust\"").is_empty());
+        // title and author are not one phrase
+        assert!(ids(&index, "\"here carol\"").is_empty());
+    }
+
+    #[test]
+    fn text_index_ranking_test() {
+        let mut index = TextIndex::new();
+        index.add_doc(doc(1, Some("rust"), "rust rust rust", "a", 0.0));
+        index.add_doc(doc(2, Some("rust"), "", "b", 0.0));
+        index.add_doc(doc(3, Some("rust"), "", "c", 100.0 * 24.0 * 60.0 * 60.0));
+        let hits = index.search(&IndexQuery::parse("rust").unwrap(), 100 * 24 * 60 * 60);
+        let ranked: Vec<i32> = hits.iter().map(|hit| hit.id).collect();
+        assert_eq!(vec![1, 3, 2], ranked);
+    }
+
+    #[test]
+    fn text_index_reindex_and_persist_test() {
+        let formatters = Formatters::new();
+        let item: HnItem = read_file("res/test/item.json")
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap();
+        let mut index = test_index();
+        index.add(&item, &formatters);
+        index.add(&item, &formatters);
+        assert_eq!(5, index.len());
+        assert_eq!(vec![item.id], ids(&index, "dropbox \"usb drive\""));
+
+        let path = env::temp_dir().join("hncli-index-test.json");
+        index.save_to(&path).unwrap();
+        assert!(!index.is_dirty());
+        let loaded = TextIndex::load_from(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(5, loaded.len());
+        assert_eq!(vec![2, 4], ids(&loaded, "rust go"));
+    }
+
+    #[test]
+    fn text_index_limits_test() {
+        let path = env::temp_dir().join("hncli-index-limit-test.json");
+        let mut index = test_index();
+        index.set_max_docs(2);
+        index.save_to(&path).unwrap();
+        let loaded = TextIndex::load_from(&path);
+        assert_eq!(2, loaded.len());
+        assert!(loaded.doc(1).is_none() && loaded.doc(2).is_none() && loaded.doc(4).is_some());
+
+        write_file(&path, "[{\"id\": 1, \"by\"").unwrap();
+        assert_eq!(0, TextIndex::load_from(&path).len());
+        let broken = path.with_extension("json.broken");
+        assert!(broken.exists() && !path.exists());
+        fs::remove_file(&broken).unwrap();
+    }
+
+    #[test]
+    fn find_term_ranges_test() {
+        let text = "Rust, rustacean and RUST!";
+        assert_eq!(vec![(0, 4), (20, 24)], find_term_ranges(text, &vec![String::from("rust")]));
+        let comment = doc(2, None, "I moved from Go to Rust and never looked back", "bob", 0.0);
+        assert_eq!("Comment: ...from Go to Rust and never looked back",
+                   comment.to_item(&vec![String::from("rust")]).title.unwrap());
+        let long = doc(5, None, "one two three four five six seven rust", "x", 0.0);
+        assert_eq!("Comment: ...five six seven rust",
+                   long.to_item(&vec![String::from("rust")]).title.unwrap());
+    }
+}
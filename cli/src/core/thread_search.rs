@@ -0,0 +1,156 @@
+use regex::Regex;
+
+use formatting::formatter::FormatStr;
+use super::killfile::Killfile;
+use super::models::HnItem;
+
+///
+/// Search over the decoded text of the loaded comments, hits are indexes in the loaded comments
+///
+pub struct ThreadSearch {
+    pattern: Regex,
+    hits: Vec<usize>,
+    current: Option<usize>,
+}
+
+impl ThreadSearch {
+    ///
+    /// Patterns are regular expressions, all lowercase patterns match case insensitively
+    ///
+    pub fn new(pattern: &str) -> Result<ThreadSearch, String> {
+        let case_insensitive = pattern.chars().all(|c| !c.is_uppercase());
+        let full_pattern = if case_insensitive { format!("(?i){}", pattern) } else { String::from(pattern) };
+        let regex = Regex::new(&full_pattern).map_err(|e| format!("invalid pattern {} {}", pattern, e))?;
+        Ok(ThreadSearch {
+            pattern: regex,
+            hits: Vec::new(),
+            current: None,
+        })
+    }
+
+    ///
+    /// Finds the comments which match, comments hidden by the killfile are skipped.
+    /// Returns how many comments matched.
+    ///
+    pub fn run(&mut self, comments: &Vec<HnItem>, format: &FormatStr, killfile: &Killfile) -> usize {
+        self.hits = comments.iter()
+            .enumerate()
+            .filter(|&(_, comment)| !killfile.hides_comment(comment))
+            .filter(|&(_, comment)| {
+                comment.text_unescaped().map(|text| self.pattern.is_match(&format.format(&text))).unwrap_or(false)
+            })
+            .map(|(index, _)| index)
+            .collect();
+        self.current = None;
+        self.hits.len()
+    }
+
+    pub fn hit_count(&self) -> usize {
+        self.hits.len()
+    }
+
+    /// Index of the comment the search is at
+    pub fn current_hit(&self) -> Option<usize> {
+        self.current.map(|current| self.hits[current])
+    }
+
+    /// Position of the current hit counting from 1
+    pub fn current_position(&self) -> Option<usize> {
+        self.current.map(|current| current + 1)
+    }
+
+    ///
+    /// Moves to the first hit at or after the comment index, wrapping to the first hit
+    ///
+    pub fn jump_from(&mut self, index: usize) -> Option<usize> {
+        if self.hits.is_empty() {
+            return None;
+        }
+        self.current = Some(self.hits.iter().position(|hit| *hit >= index).unwrap_or(0));
+        self.current_hit()
+    }
+
+    /// Moves to the next hit, wraps around after the last one
+    pub fn next(&mut self) -> Option<usize> {
+        if self.hits.is_empty() {
+            return None;
+        }
+        self.current = Some(match self.current {
+            Some(current) => (current + 1) % self.hits.len(),
+            None => 0,
+        });
+        self.current_hit()
+    }
+
+    /// Moves to the previous hit, wraps around before the first one
+    pub fn previous(&mut self) -> Option<usize> {
+        if self.hits.is_empty() {
+            return None;
+        }
+        self.current = Some(match self.current {
+            Some(0) | None => self.hits.len() - 1,
+            Some(current) => current - 1,
+        });
+        self.current_hit()
+    }
+
+    pub fn match_ranges(&self, text: &str) -> Vec<(usize, usize)> {
+        self.pattern
+            .find_iter(text)
+            .filter(|m| m.start() != m.end())
+            .map(|m| (m.start(), m.end()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+    use formatting::formatter::Formatters;
+    use helpers::io_utils::read_file;
+    use core::killfile::KillRule;
+
+    fn comments() -> Vec<HnItem> {
+        vec!["res/test/children-item.json", "res/test/item-with-html.json", "res/test/children-item.json"]
+            .into_iter()
+            .map(|path| read_file(path).and_then(|content| serde_json::from_str(&content).ok()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn thread_search_navigation_test() {
+        let formatters = Formatters::new();
+        let mut search = ThreadSearch::new("valid concern").unwrap();
+        assert_eq!(2, search.run(&comments(), &formatters, &Killfile::new()));
+        assert!(search.current_hit().is_none());
+        assert_eq!(Some(2), search.jump_from(1));
+        assert_eq!(Some(2), search.current_position());
+        assert_eq!(Some(0), search.next());
+        assert_eq!(Some(2), search.previous());
+        assert_eq!(Some(0), search.previous());
+        assert_eq!(Some(0), search.jump_from(3));
+    }
+
+    #[test]
+    fn thread_search_case_and_killfile_test() {
+        let formatters = Formatters::new();
+        let comments = comments();
+        let mut killfile = Killfile::new();
+        killfile.add(KillRule::CommentAuthor(comments[0].by.clone())).unwrap();
+        let mut search = ThreadSearch::new("valid concern").unwrap();
+        assert_eq!(0, search.run(&comments, &formatters, &killfile));
+        assert!(search.next().is_none());
+
+        let mut case_sensitive = ThreadSearch::new("Valid Concern").unwrap();
+        assert_eq!(0, case_sensitive.run(&comments, &formatters, &Killfile::new()));
+        assert!(ThreadSearch::new("(unclosed").is_err());
+    }
+
+    #[test]
+    fn thread_search_match_ranges_test() {
+        let search = ThreadSearch::new("rust").unwrap();
+        assert_eq!(vec![(0, 4), (9, 13)], search.match_ranges("Rust and RUST"));
+        assert!(ThreadSearch::new("x*").unwrap().match_ranges("abc").is_empty());
+    }
+}
@@ -0,0 +1,284 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use hyper::Error;
+use serde_json;
+
+use formatting::formatter::FormatStr;
+use helpers::io_utils::{read_file, write_file};
+use helpers::path_utils::get_data_file_path;
+use super::app::{AppDomain, AppStateMachine};
+use super::client;
+use super::models::{HnItem, HnUser};
+
+const WATCHES_FILENAME: &'static str = "watches.json";
+const EXCERPT_LEN: usize = 140;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum WatchTarget {
+    Item(i32),
+    User(String),
+}
+
+///
+/// Watched story, comment or user with the ids which have already been reported
+///
+#[derive(Serialize, Deserialize)]
+pub struct Watch {
+    pub target: WatchTarget,
+    pub seen: Vec<i32>,
+}
+
+impl WatchTarget {
+    pub fn describe(&self) -> String {
+        match *self {
+            WatchTarget::Item(id) => format!("item {}", id),
+            WatchTarget::User(ref user) => format!("user {}", user),
+        }
+    }
+}
+
+impl Watch {
+    pub fn describe(&self) -> String {
+        self.target.describe()
+    }
+}
+
+///
+/// Persisted list of watches, stored as json in the data directory
+///
+#[derive(Serialize, Deserialize)]
+pub struct WatchList {
+    pub watches: Vec<Watch>,
+}
+
+impl WatchList {
+    pub fn new() -> WatchList {
+        WatchList { watches: Vec::new() }
+    }
+
+    pub fn default_path() -> PathBuf {
+        get_data_file_path(WATCHES_FILENAME)
+    }
+
+    pub fn load() -> WatchList {
+        WatchList::load_from(&WatchList::default_path())
+    }
+
+    pub fn load_from(path: &Path) -> WatchList {
+        read_file(&path.to_string_lossy())
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or(WatchList::new())
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        self.save_to(&WatchList::default_path())
+    }
+
+    pub fn save_to(&self, path: &Path) -> io::Result<()> {
+        let contents = serde_json::to_string_pretty(&self).unwrap();
+        write_file(path, &contents)
+    }
+
+    pub fn contains(&self, target: &WatchTarget) -> bool {
+        self.watches.iter().any(|watch| watch.target == *target)
+    }
+
+    ///
+    /// Adds watch if the target is not watched already
+    ///
+    pub fn add(&mut self, watch: Watch) -> bool {
+        if self.contains(&watch.target) {
+            false
+        } else {
+            self.watches.push(watch);
+            true
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Watch> {
+        if index < self.watches.len() {
+            Some(self.watches.remove(index))
+        } else {
+            None
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watches.is_empty()
+    }
+}
+
+///
+/// New descendant or submission found for a watch
+///
+pub struct WatchNotification {
+    pub watch: String,
+    pub item_id: i32,
+    pub excerpt: String,
+}
+
+///
+/// Creates a watch which has everything currently under the target marked as seen
+///
+pub fn create_watch(target: WatchTarget,
+                    app_domain: &mut AppDomain,
+                    state: &mut AppStateMachine)
+                    -> Result<Watch, Error> {
+    let seen = match target {
+        WatchTarget::Item(id) => {
+            let item = client::get_item_by_id(&id.to_string(), app_domain, state)?;
+            let mut descendants = Vec::new();
+            collect_descendants(&item, app_domain, state, &mut descendants)?;
+            descendants.iter().map(|d| d.id).collect()
+        }
+        WatchTarget::User(ref user) => get_user(user, app_domain, state)?.submitted,
+    };
+    Ok(Watch {
+        target: target,
+        seen: seen,
+    })
+}
+
+///
+/// The api returns null for users which don't exist, like deleted accounts
+///
+fn get_user(user: &str, app_domain: &mut AppDomain, state: &mut AppStateMachine) -> Result<HnUser, Error> {
+    client::get_optional_user_by_id(user, app_domain, state)?
+        .ok_or(Error::from(io::Error::new(io::ErrorKind::NotFound, format!("no such user {}", user))))
+}
+
+///
+/// Checks every watch and marks the found items as seen, watches which fail are retried on the next check
+///
+pub fn check_watches(watch_list: &mut WatchList,
+                     app_domain: &mut AppDomain,
+                     state: &mut AppStateMachine)
+                     -> Vec<WatchNotification> {
+    let mut notifications = Vec::new();
+    for watch in watch_list.watches.iter_mut() {
+        match check_watch(watch, app_domain, state) {
+            Ok(mut found) => notifications.append(&mut found),
+            Err(e) => warn!("Could not check watch for {} {}", watch.describe(), e),
+        }
+    }
+    notifications
+}
+
+fn check_watch(watch: &mut Watch,
+               app_domain: &mut AppDomain,
+               state: &mut AppStateMachine)
+               -> Result<Vec<WatchNotification>, Error> {
+    let new_items: Vec<HnItem> = match watch.target {
+        WatchTarget::Item(id) => {
+            let item = client::get_item_by_id(&id.to_string(), app_domain, state)?;
+            let mut descendants = Vec::new();
+            collect_descendants(&item, app_domain, state, &mut descendants)?;
+            descendants.into_iter().filter(|d| !watch.seen.contains(&d.id)).collect()
+        }
+        WatchTarget::User(ref user) => {
+            let user = get_user(user, app_domain, state)?;
+            let mut submissions = Vec::new();
+            for id in user.submitted.iter().filter(|id| !watch.seen.contains(id)) {
+                submissions.push(client::get_item_by_id(&id.to_string(), app_domain, state)?);
+            }
+            submissions
+        }
+    };
+    let description = watch.describe();
+    let notifications = new_items.iter()
+        .filter(|item| !item.dead.unwrap_or(false))
+        .map(|item| {
+            WatchNotification {
+                watch: description.clone(),
+                item_id: item.id,
+                excerpt: create_excerpt(item, &app_domain.formatters),
+            }
+        })
+        .collect();
+    watch.seen.extend(new_items.iter().map(|item| item.id));
+    Ok(notifications)
+}
+
+fn collect_descendants(item: &HnItem,
+                       app_domain: &mut AppDomain,
+                       state: &mut AppStateMachine,
+                       descendants: &mut Vec<HnItem>)
+                       -> Result<(), Error> {
+    match item.kids {
+        Some(ref kids) => {
+            for kid in kids {
+                let kid_item = client::get_item_by_id(&kid.to_string(), app_domain, state)?;
+                collect_descendants(&kid_item, app_domain, state, descendants)?;
+                descendants.push(kid_item);
+            }
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+///
+/// Single line excerpt of the item, title for stories and formatted text for comments
+///
+pub fn create_excerpt(item: &HnItem, format: &FormatStr) -> String {
+    let text = match item.title {
+        Some(ref title) => title.clone(),
+        None => item.text_unescaped().map(|text| format.format(&text)).unwrap_or(String::new()),
+    };
+    let single_line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if single_line.chars().count() > EXCERPT_LEN {
+        let truncated: String = single_line.chars().take(EXCERPT_LEN).collect();
+        format!("{}...", truncated)
+    } else {
+        single_line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use formatting::formatter::Formatters;
+    use helpers::io_utils::read_file;
+
+    #[test]
+    fn watch_list_add_remove_test() {
+        let mut watch_list = WatchList::new();
+        assert!(watch_list.add(Watch { target: WatchTarget::Item(8863), seen: vec![1, 2] }));
+        assert!(!watch_list.add(Watch { target: WatchTarget::Item(8863), seen: vec![] }));
+        assert!(watch_list.add(Watch { target: WatchTarget::User(String::from("pg")), seen: vec![] }));
+        assert_eq!(2, watch_list.watches.len());
+        assert_eq!("user pg", watch_list.remove(1).unwrap().describe());
+        assert!(watch_list.remove(1).is_none());
+    }
+
+    #[test]
+    fn watch_list_persist_test() {
+        let path = env::temp_dir().join("hncli-watch-list-test.json");
+        let mut watch_list = WatchList::new();
+        watch_list.add(Watch { target: WatchTarget::Item(8863), seen: vec![9224, 8917] });
+        watch_list.save_to(&path).unwrap();
+        let loaded = WatchList::load_from(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(WatchTarget::Item(8863), loaded.watches[0].target);
+        assert_eq!(vec![9224, 8917], loaded.watches[0].seen);
+    }
+
+    #[test]
+    fn create_excerpt_test() {
+        let formatters = Formatters::new();
+        let story: HnItem = read_file("res/test/item.json")
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap();
+        assert_eq!("My YC app: Dropbox - Throw away your USB drive", create_excerpt(&story, &formatters));
+
+        let comment: HnItem = read_file("res/test/item-with-html.json")
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap();
+        let excerpt = create_excerpt(&comment, &formatters);
+        assert!(!excerpt.contains('\n'));
+        assert!(!excerpt.contains("<p>"));
+        assert!(excerpt.chars().count() <= EXCERPT_LEN + 3);
+    }
+}
@@ -1,4 +1,5 @@
 #![allow(dead_code)]
 pub mod text_decoding;
+pub mod text_encoding;
 mod text_decoding_entities;
 mod text_decoding_io;
\ No newline at end of file
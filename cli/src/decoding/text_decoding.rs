@@ -1,5 +1,8 @@
-use std::io::{self, Write, BufRead, Cursor};
+use std::borrow::Cow;
+use std::io::{self, Write, BufRead};
 use std::char;
+use std::cmp::min;
+use std::mem;
 use self::DecodeState::*;
 use self::DecodeErrKind::*;
 use super::text_decoding_io::{self, write_char, CharsError};
@@ -58,6 +61,17 @@ pub struct DecodeErr {
     pub kind: DecodeErrKind
 }
 
+///
+/// How references which can't be decoded are handled
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DecodeMode {
+    /// Unknown named references and malformed numeric references are errors
+    Strict,
+    /// Unknown and malformed references are left in the text as they are
+    Lossy,
+}
+
 #[derive(PartialEq, Eq)]
 enum DecodeState {
     Normal,
@@ -68,14 +82,6 @@ enum DecodeState {
     Dec
 }
 
-macro_rules! try_parse(
-    ($parse:expr, $pos:expr) => (
-        match $parse {
-            Err(reason) => return Err(DecodeErr{ position: $pos, kind: reason}),
-            Ok(res) => res
-        }
-    ););
-
 macro_rules! try_dec_io(
     ($io:expr, $pos:expr) => (
         match $io {
@@ -84,6 +90,199 @@ macro_rules! try_dec_io(
         }
     ););
 
+/// Incremental decoder which is given the input a chunk or a character at a time.
+///
+/// References may be split between chunks, the decoder keeps the unfinished reference
+/// until it sees how it ends. `finish` has to be called after the last chunk to write out
+/// a reference left open at the end of input.
+///
+/// Missing semicolons are handled as in the HTML standard: `&amp;`, `&amp` and `&#38` are
+/// all decoded, and the longest legacy name is taken from an unknown name so `&notit;`
+/// becomes `¬it;`. An `&` which isn't followed by a name or `#` is kept as it is.
+pub struct HtmlDecoder {
+    mode: DecodeMode,
+    state: DecodeState,
+    buf: String,
+    pos: usize,
+    good_pos: usize,
+}
+
+impl HtmlDecoder {
+    pub fn new(mode: DecodeMode) -> HtmlDecoder {
+        HtmlDecoder {
+            mode: mode,
+            state: Normal,
+            buf: String::with_capacity(8),
+            pos: 0,
+            good_pos: 0,
+        }
+    }
+
+    /// Decodes a chunk of the input to the writer
+    pub fn decode_chunk<W: Write>(&mut self, chunk: &str, writer: &mut W) -> Result<(), DecodeErr> {
+        let mut rest = chunk;
+        while !rest.is_empty() {
+            if self.state == Normal {
+                // text between references is copied as it is, find is a memchr for the '&'
+                let run = rest.find('&').unwrap_or(rest.len());
+                try_dec_io!(writer.write_all(rest[..run].as_bytes()), self.good_pos);
+                self.pos += rest[..run].chars().count();
+                self.good_pos = self.pos;
+                rest = &rest[run..];
+            }
+            match rest.chars().next() {
+                Some(c) => {
+                    self.push(c, writer)?;
+                    rest = &rest[c.len_utf8()..];
+                }
+                None => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes the next character of the input to the writer
+    pub fn push<W: Write>(&mut self, c: char, writer: &mut W) -> Result<(), DecodeErr> {
+        self.process(c, writer)?;
+        self.pos += 1;
+        if self.state == Normal {
+            self.good_pos = self.pos;
+        }
+        Ok(())
+    }
+
+    /// Writes out the reference left open at the end of input.
+    ///
+    /// # Errors
+    /// In strict mode a reference which can't be decoded fails with `PrematureEnd`.
+    pub fn finish<W: Write>(&mut self, writer: &mut W) -> Result<(), DecodeErr> {
+        let state = mem::replace(&mut self.state, Normal);
+        let res = match state {
+            Normal => Ok(()),
+            Entity => self.write_str(writer, "&"),
+            Named => self.resolve_named(writer, false),
+            Numeric | Hex | Dec => self.resolve_numeric(writer, false),
+        };
+        self.good_pos = self.pos;
+        match res {
+            Err(DecodeErr { position, kind: UnknownEntity }) |
+            Err(DecodeErr { position, kind: MalformedNumEscape }) => {
+                Err(DecodeErr { position: position, kind: PrematureEnd })
+            }
+            res => res,
+        }
+    }
+
+    fn process<W: Write>(&mut self, c: char, writer: &mut W) -> Result<(), DecodeErr> {
+        match self.state {
+            Normal if c == '&' => self.state = Entity,
+            Normal => try_dec_io!(write_char(writer, c), self.good_pos),
+            Entity if c == '#' => self.state = Numeric,
+            Entity if is_alphanumeric(c) => {
+                self.state = Named;
+                self.buf.push(c);
+            }
+            Entity => {
+                // not a reference at all like in "AT&T" or "& "
+                self.state = Normal;
+                self.write_str(writer, "&")?;
+                return self.process(c, writer);
+            }
+            Named if c == ';' => {
+                self.state = Normal;
+                self.resolve_named(writer, true)?;
+            }
+            Named if is_alphanumeric(c) && self.buf.len() < LONGEST_ENTITY_NAME => self.buf.push(c),
+            Named => {
+                self.state = Normal;
+                self.resolve_named(writer, false)?;
+                return self.process(c, writer);
+            }
+            Numeric if is_digit(c) => {
+                self.state = Dec;
+                self.buf.push(c);
+            }
+            Numeric if c == 'x' || c == 'X' => {
+                // the marker is kept to write the reference back as it was
+                self.state = Hex;
+                self.buf.push(c);
+            }
+            Hex if is_hex_digit(c) => self.buf.push(c),
+            Dec if is_digit(c) => self.buf.push(c),
+            Numeric | Hex | Dec if c == ';' => {
+                self.state = Normal;
+                self.resolve_numeric(writer, true)?;
+            }
+            Numeric | Hex | Dec => {
+                self.state = Normal;
+                self.resolve_numeric(writer, false)?;
+                return self.process(c, writer);
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_named<W: Write>(&mut self, writer: &mut W, semicolon: bool) -> Result<(), DecodeErr> {
+        let name = mem::replace(&mut self.buf, String::with_capacity(8));
+        if semicolon {
+            match find_named_entity(&name) {
+                Some(value) => return self.write_str(writer, value),
+                None => (),
+            }
+        }
+        match find_legacy_prefix(&name) {
+            Some(len) => {
+                self.write_str(writer, find_named_entity(&name[..len]).unwrap())?;
+                self.write_str(writer, &name[len..])?;
+            }
+            None if self.mode == DecodeMode::Strict => {
+                return Err(DecodeErr { position: self.good_pos, kind: UnknownEntity });
+            }
+            None => {
+                self.write_str(writer, "&")?;
+                self.write_str(writer, &name)?;
+            }
+        }
+        if semicolon {
+            self.write_str(writer, ";")?;
+        }
+        Ok(())
+    }
+
+    fn resolve_numeric<W: Write>(&mut self, writer: &mut W, semicolon: bool) -> Result<(), DecodeErr> {
+        let digits = mem::replace(&mut self.buf, String::with_capacity(8));
+        let (radix, number) = if digits.starts_with('x') || digits.starts_with('X') {
+            (16, &digits[1..])
+        } else {
+            (10, &digits[..])
+        };
+        if number.is_empty() {
+            if self.mode == DecodeMode::Strict {
+                return Err(DecodeErr { position: self.good_pos, kind: MalformedNumEscape });
+            }
+            self.write_str(writer, "&#")?;
+            self.write_str(writer, &digits)?;
+            if semicolon {
+                self.write_str(writer, ";")?;
+            }
+            return Ok(());
+        }
+        match decode_numeric(number, radix) {
+            Some(c) => try_dec_io!(write_char(writer, c), self.good_pos),
+            None if self.mode == DecodeMode::Strict => {
+                return Err(DecodeErr { position: self.good_pos, kind: InvalidCharacter });
+            }
+            None => try_dec_io!(write_char(writer, '\u{FFFD}'), self.good_pos),
+        }
+        Ok(())
+    }
+
+    fn write_str<W: Write>(&self, writer: &mut W, s: &str) -> Result<(), DecodeErr> {
+        try_dec_io!(writer.write_all(s.as_bytes()), self.good_pos);
+        Ok(())
+    }
+}
+
 /// Decodes an entity-encoded string from a reader to a writer.
 ///
 /// Similar to `decode_html`, except reading from a reader rather than a string, and
@@ -96,10 +295,20 @@ macro_rules! try_dec_io(
 /// # Errors
 /// Errors can be caused by IO errors, `reader` producing invalid UTF-8, or by syntax errors.
 pub fn decode_html_rw<R: BufRead, W: Write>(reader: R, writer: &mut W) -> Result<(), DecodeErr> {
-    let mut state: DecodeState = Normal;
-    let mut pos = 0;
-    let mut good_pos = 0;
-    let mut buf = String::with_capacity(8);
+    decode_rw(reader, writer, DecodeMode::Strict)
+}
+
+/// Decodes an entity-encoded string from a reader to a writer, leaving the references which
+/// can't be decoded as they are.
+///
+/// # Errors
+/// Errors can be caused only by IO errors or `reader` producing invalid UTF-8.
+pub fn decode_html_lossy_rw<R: BufRead, W: Write>(reader: R, writer: &mut W) -> Result<(), DecodeErr> {
+    decode_rw(reader, writer, DecodeMode::Lossy)
+}
+
+fn decode_rw<R: BufRead, W: Write>(reader: R, writer: &mut W, mode: DecodeMode) -> Result<(), DecodeErr> {
+    let mut decoder = HtmlDecoder::new(mode);
     for c in text_decoding_io::chars(reader) {
         let c = match c {
             Err(e) => {
@@ -107,57 +316,13 @@ pub fn decode_html_rw<R: BufRead, W: Write>(reader: R, writer: &mut W) -> Result
                     CharsError::NotUtf8   => EncodingError,
                     CharsError::Other(io) => IoError(io)
                 };
-                return Err(DecodeErr{ position: pos, kind: kind });
+                return Err(DecodeErr{ position: decoder.pos, kind: kind });
             }
             Ok(c) => c
         };
-        match state {
-            Normal if c == '&' => state = Entity,
-            Normal => try_dec_io!(write_char(writer, c), good_pos),
-            Entity if c == '#' => state = Numeric,
-            Entity if c == ';' => return Err(DecodeErr{ position: good_pos, kind: UnknownEntity }),
-            Entity => {
-                state = Named;
-                buf.push(c);
-            }
-            Named if c == ';' => {
-                state = Normal;
-                let ch = try_parse!(decode_named_entity(&buf), good_pos);
-                try_dec_io!(write_char(writer, ch), good_pos);
-                buf.clear();
-            }
-            Named => buf.push(c),
-            Numeric if is_digit(c) => {
-                state = Dec;
-                buf.push(c);
-            }
-            Numeric if c == 'x' => state = Hex,
-            Dec if c == ';' => {
-                state = Normal;
-                let ch = try_parse!(decode_numeric(&buf, 10), good_pos);
-                try_dec_io!(write_char(writer, ch), good_pos);
-                buf.clear();
-            }
-            Hex if c == ';' => {
-                state = Normal;
-                let ch = try_parse!(decode_numeric(&buf, 16), good_pos);
-                try_dec_io!(write_char(writer, ch), good_pos);
-                buf.clear();
-            }
-            Hex if is_hex_digit(c) => buf.push(c),
-            Dec if is_digit(c) => buf.push(c),
-            Numeric | Hex | Dec => return Err(DecodeErr{ position: good_pos, kind: MalformedNumEscape}),
-        }
-        pos += 1;
-        if state == Normal {
-            good_pos = pos;
-        }
-    }
-    if state != Normal {
-        Err(DecodeErr{ position: good_pos, kind: PrematureEnd})
-    } else {
-        Ok(())
+        decoder.push(c, writer)?;
     }
+    decoder.finish(writer)
 }
 
 /// Decodes an entity-encoded string.
@@ -165,6 +330,8 @@ pub fn decode_html_rw<R: BufRead, W: Write>(reader: R, writer: &mut W) -> Result
 /// Decodes an entity encoded string, replacing HTML entities (`&amp;`, `&#20;` ...) with the
 /// the corresponding character. Case matters for named entities, ie. `&Amp;` is invalid.
 /// Case does not matter for hex entities, so `&#x2E;` and `&#x2e;` are treated the same.
+/// Every named reference of HTML5 is known, and the legacy ones like `&amp` are decoded
+/// also without the semicolon.
 ///
 /// # Arguments
 /// - `s` - Entity-encoded string to decode.
@@ -172,48 +339,85 @@ pub fn decode_html_rw<R: BufRead, W: Write>(reader: R, writer: &mut W) -> Result
 /// # Failure
 /// The function will fail if input string contains invalid named entities (eg. `&nosuchentity;`),
 /// invalid hex entities (eg. `&#xRT;`), invalid decimal entities (eg. `&#-1;), unclosed entities
-/// (`s == "&amp hej och hå"`) or otherwise malformed entities.
+/// (`s == "&nosuchentity"`) or otherwise malformed entities.
 ///
 /// This function will never return errors with `kind` set to `IoError` or `EncodingError`.
 pub fn decode_html(s: &str) -> Result<String, DecodeErr> {
-    let mut writer = Vec::with_capacity(s.len());
-    let bytes = s.as_bytes();
-    let mut reader = Cursor::new(bytes);
-    let res = decode_html_rw(&mut reader, &mut writer);
-    match res {
-        Ok(_) => Ok(String::from_utf8(writer).unwrap()),
-        Err(err) => Err(err)
+    decode_str(s, DecodeMode::Strict)
+}
+
+/// Decodes an entity-encoded string like `decode_html` but never fails.
+///
+/// Unknown and malformed references are left as they are and numeric references which
+/// aren't valid characters become U+FFFD.
+pub fn decode_html_lossy(s: &str) -> String {
+    // lossy decoding of a string has no errors
+    decode_str(s, DecodeMode::Lossy).unwrap()
+}
+
+/// Decodes an entity-encoded string like `decode_html`, borrowing the input when it has no
+/// references so decoding plain text doesn't allocate.
+pub fn decode_html_cow(s: &str) -> Result<Cow<'_, str>, DecodeErr> {
+    if s.contains('&') {
+        decode_str(s, DecodeMode::Strict).map(Cow::Owned)
+    } else {
+        Ok(Cow::Borrowed(s))
     }
 }
 
+/// Decodes an entity-encoded string like `decode_html_lossy`, borrowing the input when it has
+/// no references.
+pub fn decode_html_lossy_cow(s: &str) -> Cow<'_, str> {
+    if s.contains('&') {
+        Cow::Owned(decode_html_lossy(s))
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+fn decode_str(s: &str, mode: DecodeMode) -> Result<String, DecodeErr> {
+    let mut writer = Vec::with_capacity(s.len());
+    let mut decoder = HtmlDecoder::new(mode);
+    decoder.decode_chunk(s, &mut writer)?;
+    decoder.finish(&mut writer)?;
+    Ok(String::from_utf8(writer).unwrap())
+}
+
 fn is_digit(c: char) -> bool { c >= '0' && c <= '9' }
 
 fn is_hex_digit(c: char) -> bool {
     is_digit(c) || (c >= 'a' && c <= 'f') || (c >= 'A' && c <= 'F')
 }
 
-fn decode_named_entity(entity: &str) -> Result<char, DecodeErrKind> {
-    match NAMED_ENTITIES.binary_search_by(|&(ent, _)| ent.cmp(entity)) {
-        Err(..) => Err(UnknownEntity),
-        Ok(idx) => {
-            let (_, c) = NAMED_ENTITIES[idx];
-            Ok(c)
-        }
-    }
+fn is_alphanumeric(c: char) -> bool {
+    is_digit(c) || (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z')
+}
+
+fn find_named_entity(name: &str) -> Option<&'static str> {
+    NAMED_ENTITIES.binary_search_by(|&(ent, _)| ent.cmp(name))
+        .ok()
+        .map(|idx| NAMED_ENTITIES[idx].1)
+}
+
+/// Length of the longest legacy name the name starts with
+fn find_legacy_prefix(name: &str) -> Option<usize> {
+    (1..min(name.len(), LONGEST_LEGACY_NAME) + 1)
+        .rev()
+        .find(|&len| LEGACY_ENTITIES.binary_search(&&name[..len]).is_ok())
 }
 
-fn decode_numeric(esc: &str, radix: u32) -> Result<char, DecodeErrKind> {
+/// Character of the numeric reference, None for code points which aren't allowed
+fn decode_numeric(esc: &str, radix: u32) -> Option<char> {
     match u32::from_str_radix(esc, radix) {
-        Ok(n) => match char::from_u32(n) {
-            Some(c) => Ok(c),
-            None => Err(InvalidCharacter)
-        },
-        Err(..) => Err(MalformedNumEscape)
+        Ok(0) => None,
+        Ok(n) if n >= 0x80 && n <= 0x9F => Some(C1_REPLACEMENTS[(n - 0x80) as usize]),
+        Ok(n) => char::from_u32(n),
+        Err(..) => None, // too many digits
     }
 }
 #[cfg(test)]
 mod test {
-    use super::decode_html;
+    use super::*;
     #[test]
     fn decoding_testing() {
         let mut s = String::from("Cats&#x20;&amp;&#32;dogs");
@@ -226,4 +430,118 @@ mod test {
         s_expect = String::from("/forward");
         assert_eq!(s_expect, decode_html(&s).unwrap());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn named_entities_test() {
+        assert_eq!("wait\u{2026} it\u{2019}s \u{201C}fine\u{201D}", decode_html("wait&hellip; it&rsquo;s &ldquo;fine&rdquo;").unwrap());
+        assert_eq!("\u{2242}\u{0338}", decode_html("&NotEqualTilde;").unwrap());
+        assert_eq!("\u{2233}", decode_html("&CounterClockwiseContourIntegral;").unwrap());
+        assert_eq!(Err(DecodeErr { position: 2, kind: UnknownEntity }), decode_html("a &nosuchentity; b"));
+        assert_eq!(Err(DecodeErr { position: 0, kind: PrematureEnd }), decode_html("&nosuchentity"));
+    }
+
+    #[test]
+    fn missing_semicolon_test() {
+        assert_eq!("\u{00A9} 2017", decode_html("&copy 2017").unwrap());
+        assert_eq!("<p>", decode_html("&ltp&gt").unwrap());
+        assert_eq!("\u{00AC}it;", decode_html("&notit;").unwrap());
+        assert_eq!("\u{2209}", decode_html("&notin;").unwrap());
+        assert_eq!("& hej och h\u{00E5}", decode_html("&amp hej och h&aring").unwrap());
+        assert_eq!("fish & chips &", decode_html("fish & chips &").unwrap());
+        assert_eq!("'s", decode_html("&#39s").unwrap());
+        assert_eq!("\u{2013}\u{FFFD}", decode_html_lossy("&#150;&#0;"));
+        assert_eq!(Err(DecodeErr { position: 0, kind: InvalidCharacter }), decode_html("&#xD800;"));
+    }
+
+    #[test]
+    fn lossy_decoding_test() {
+        assert_eq!("&nosuchentity; & \u{2026}", decode_html_lossy("&nosuchentity; &amp; &hellip;"));
+        assert_eq!("AT&T", decode_html_lossy("AT&T"));
+        assert_eq!("&#xRT; &#; &bogus", decode_html_lossy("&#xRT; &#; &bogus"));
+        assert_eq!("\u{FFFD}", decode_html_lossy("&#99999999999;"));
+        let mut writer = Vec::new();
+        decode_html_lossy_rw(&b"&foo;&#x2F;"[..], &mut writer).unwrap();
+        assert_eq!("&foo;/", String::from_utf8(writer).unwrap());
+    }
+
+    #[test]
+    fn chunked_decoding_test() {
+        let mut decoder = HtmlDecoder::new(DecodeMode::Strict);
+        let mut writer = Vec::new();
+        for chunk in ["It&rs", "quo;s &#x", "2F; &am", "p"].iter() {
+            decoder.decode_chunk(chunk, &mut writer).unwrap();
+        }
+        decoder.finish(&mut writer).unwrap();
+        assert_eq!("It\u{2019}s / &", String::from_utf8(writer).unwrap());
+    }
+
+    #[test]
+    fn entity_tables_test() {
+        assert_eq!(2125, NAMED_ENTITIES.len());
+        assert!(NAMED_ENTITIES.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(LEGACY_ENTITIES.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(LEGACY_ENTITIES.iter().all(|name| find_named_entity(name).is_some()));
+        assert_eq!(LONGEST_ENTITY_NAME, NAMED_ENTITIES.iter().map(|&(name, _)| name.len()).max().unwrap());
+    }
+
+    #[test]
+    fn decode_cow_test() {
+        match decode_html_cow("no references here").unwrap() {
+            Cow::Borrowed(s) => assert_eq!("no references here", s),
+            Cow::Owned(_) => panic!("plain text should be borrowed"),
+        }
+        assert_eq!("a & b", decode_html_cow("a &amp; b").unwrap());
+        assert!(decode_html_cow("&nosuchentity;").is_err());
+        assert_eq!("&foo; \u{2026}", decode_html_lossy_cow("&foo; &hellip;"));
+        let mut writer = Vec::new();
+        let mut decoder = HtmlDecoder::new(DecodeMode::Strict);
+        assert_eq!(Err(DecodeErr { position: 6, kind: UnknownEntity }),
+                   decoder.decode_chunk("\u{E5}\u{E4}\u{F6} a &foo;", &mut writer));
+    }
+}
+
+#[cfg(all(feature = "nightly", test))]
+mod bench {
+    use super::*;
+    use test::Bencher;
+    use helpers::bench_utils::{large_thread, thread_bytes};
+
+    /// Html of the comments in the thread
+    fn thread_texts() -> Vec<String> {
+        large_thread().into_iter().filter_map(|item| item.text).collect()
+    }
+
+    #[bench]
+    fn decode_thread(b: &mut Bencher) {
+        let thread = thread_texts();
+        b.bytes = thread_bytes(&large_thread());
+        b.iter(|| thread.iter().map(|text| decode_html(text).unwrap().len()).sum::<usize>());
+    }
+
+    #[bench]
+    fn decode_thread_reader(b: &mut Bencher) {
+        let thread = thread_texts();
+        b.bytes = thread_bytes(&large_thread());
+        b.iter(|| {
+            let mut writer = Vec::new();
+            for text in thread.iter() {
+                decode_html_rw(text.as_bytes(), &mut writer).unwrap();
+            }
+            writer.len()
+        });
+    }
+
+    #[bench]
+    fn decode_plain_text(b: &mut Bencher) {
+        let text = "Plain comment without any references in it. ".repeat(1000);
+        b.bytes = text.len() as u64;
+        b.iter(|| decode_html(&text).unwrap().len());
+    }
+
+    #[bench]
+    fn decode_plain_text_cow(b: &mut Bencher) {
+        let text = "Plain comment without any references in it. ".repeat(1000);
+        b.bytes = text.len() as u64;
+        b.iter(|| decode_html_cow(&text).unwrap().len());
+    }
+}
@@ -0,0 +1,193 @@
+use std::io::{self, Write};
+use super::text_decoding_entities::MINIMAL_ENTITIES;
+
+/**
+original: https://github.com/veddan/rust-htmlescape/
+*/
+
+enum Escape {
+    Named(&'static str),
+    Numeric,
+}
+
+/// Encodes the characters which have a meaning in HTML and XML, `& < > " ' /`, from a string
+/// to a writer.
+///
+/// Similar to `encode_minimal`, except writing to a writer rather than returning a `String`.
+pub fn encode_minimal_w<W: Write>(s: &str, writer: &mut W) -> io::Result<()> {
+    encode_w(s, writer, |c| minimal_entity(c).map(Escape::Named))
+}
+
+/// Encodes a string for element content and quoted attribute values.
+///
+/// Only `& < > " ' /` are replaced, so the result is safe in text and in attribute values
+/// quoted with either quote, but not in unquoted attribute values.
+///
+/// # Examples
+/// `encode_minimal("<a href=\"x\">")` is `&lt;a href=&quot;x&quot;&gt;`
+pub fn encode_minimal(s: &str) -> String {
+    to_string(s, encode_minimal_w)
+}
+
+/// Encodes every ASCII character except letters and digits from a string to a writer.
+///
+/// Similar to `encode_attribute`, except writing to a writer rather than returning a `String`.
+pub fn encode_attribute_w<W: Write>(s: &str, writer: &mut W) -> io::Result<()> {
+    encode_w(s, writer, |c| {
+        if (c as u32) < 0x80 && !is_alphanumeric(c) {
+            Some(minimal_entity(c).map(Escape::Named).unwrap_or(Escape::Numeric))
+        } else {
+            None
+        }
+    })
+}
+
+/// Encodes a string for attribute values, also unquoted ones.
+///
+/// Every ASCII character which isn't a letter or digit is replaced, with the named reference
+/// for `& < > "` and a hex reference for the rest. Other characters are kept as they are.
+/// NUL can't be represented by a reference in HTML and is decoded as U+FFFD.
+///
+/// # Examples
+/// `encode_attribute("a b=c")` is `a&#x20;b&#x3D;c`
+pub fn encode_attribute(s: &str) -> String {
+    to_string(s, encode_attribute_w)
+}
+
+/// Encodes `& < > " ' /` and every non-ASCII character from a string to a writer.
+///
+/// Similar to `encode_ascii`, except writing to a writer rather than returning a `String`.
+pub fn encode_ascii_w<W: Write>(s: &str, writer: &mut W) -> io::Result<()> {
+    encode_w(s, writer, |c| {
+        match minimal_entity(c) {
+            Some(entity) => Some(Escape::Named(entity)),
+            None if (c as u32) >= 0x80 => Some(Escape::Numeric),
+            None => None,
+        }
+    })
+}
+
+/// Encodes a string like `encode_minimal` and also replaces every non-ASCII character with
+/// a hex reference, for outputs which have to be 7-bit clean like mail bodies.
+/// The C1 controls U+0080 - U+009F are decoded as windows-1252 in HTML so they don't
+/// survive the round trip.
+///
+/// # Examples
+/// `encode_ascii("café")` is `caf&#xE9;`
+pub fn encode_ascii(s: &str) -> String {
+    to_string(s, encode_ascii_w)
+}
+
+/// Writes the runs of characters which aren't escaped as they are and the rest as references
+fn encode_w<W: Write, F: Fn(char) -> Option<Escape>>(s: &str, writer: &mut W, escape: F) -> io::Result<()> {
+    let mut start = 0;
+    for (idx, c) in s.char_indices() {
+        match escape(c) {
+            Some(escaped) => {
+                writer.write_all(s[start..idx].as_bytes())?;
+                match escaped {
+                    Escape::Named(entity) => writer.write_all(entity.as_bytes())?,
+                    Escape::Numeric => write!(writer, "&#x{:X};", c as u32)?,
+                }
+                start = idx + c.len_utf8();
+            }
+            None => (),
+        }
+    }
+    writer.write_all(s[start..].as_bytes())
+}
+
+fn to_string(s: &str, encode: fn(&str, &mut Vec<u8>) -> io::Result<()>) -> String {
+    let mut writer = Vec::with_capacity(s.len());
+    // writing to a Vec can't fail
+    encode(s, &mut writer).unwrap();
+    String::from_utf8(writer).unwrap()
+}
+
+fn minimal_entity(c: char) -> Option<&'static str> {
+    MINIMAL_ENTITIES.iter().find(|&&(ch, _)| ch == c).map(|&(_, entity)| entity)
+}
+
+fn is_alphanumeric(c: char) -> bool {
+    (c >= '0' && c <= '9') || (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use decoding::text_decoding::decode_html;
+
+    const ALPHABET: [char; 24] = ['a', 'Z', '0', '9', ' ', '\t', '\n', '&', '<', '>', '"', '\'', '/', ';', '#',
+                                  'x', '=', '`', 'é', 'å', '€', '\u{2026}', '\u{1F600}', '\u{7F}'];
+    const SNIPPETS: [&'static str; 6] = ["&amp;", "&amp", "&#x2F;", "&#39", "&notin;", "&hellip;"];
+
+    /// Deterministic strings mixing characters which need escaping with entity lookalikes
+    struct RandomStrings {
+        seed: u64,
+    }
+
+    impl RandomStrings {
+        fn next_number(&mut self, limit: usize) -> usize {
+            // constants of Knuth's MMIX
+            self.seed = self.seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((self.seed >> 33) as usize) % limit
+        }
+
+        fn next_string(&mut self) -> String {
+            let len = self.next_number(40);
+            let mut s = String::new();
+            for _ in 0..len {
+                if self.next_number(8) == 0 {
+                    let snippet = self.next_number(SNIPPETS.len());
+                    s.push_str(SNIPPETS[snippet]);
+                } else {
+                    let c = self.next_number(ALPHABET.len());
+                    s.push(ALPHABET[c]);
+                }
+            }
+            s
+        }
+    }
+
+    #[test]
+    fn encode_minimal_test() {
+        assert_eq!("&lt;a href=&quot;x&#x2F;y&quot;&gt;&amp;it&#x27;s&lt;&#x2F;a&gt;",
+                   encode_minimal("<a href=\"x/y\">&it's</a>"));
+        assert_eq!("caf\u{E9} \u{1F600}", encode_minimal("caf\u{E9} \u{1F600}"));
+        assert_eq!("", encode_minimal(""));
+    }
+
+    #[test]
+    fn encode_attribute_test() {
+        assert_eq!("a&#x20;b&#x3D;c&#x60;&quot;&lt;", encode_attribute("a b=c`\"<"));
+        assert_eq!("caf\u{E9}&#xA;", encode_attribute("caf\u{E9}\n"));
+    }
+
+    #[test]
+    fn encode_ascii_test() {
+        assert_eq!("caf&#xE9; &#x2026; &#x1F600; &amp;", encode_ascii("caf\u{E9} \u{2026} \u{1F600} &"));
+        assert!(encode_ascii("\u{E5}\u{E4}\u{F6}").is_ascii());
+    }
+
+    #[test]
+    fn encode_writer_test() {
+        let mut writer = Vec::new();
+        encode_minimal_w("<b>", &mut writer).unwrap();
+        encode_attribute_w(" ", &mut writer).unwrap();
+        encode_ascii_w("\u{E9}", &mut writer).unwrap();
+        assert_eq!("&lt;b&gt;&#x20;&#xE9;", String::from_utf8(writer).unwrap());
+    }
+
+    #[test]
+    fn round_trip_test() {
+        let mut strings = RandomStrings { seed: 1175714200 };
+        for _ in 0..1000 {
+            let s = strings.next_string();
+            assert_eq!(s, decode_html(&encode_minimal(&s)).unwrap());
+            assert_eq!(s, decode_html(&encode_ascii(&s)).unwrap());
+            let attribute = encode_attribute(&s);
+            assert!(!attribute.contains(|c: char| c == ' ' || c == '"' || c == '\'' || c == '=' || c == '<'));
+            assert_eq!(s, decode_html(&attribute).unwrap());
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use chrono::{TimeZone, Utc};
+use core::models::HnItem;
+use decoding::text_encoding::encode_minimal;
+use formatting::formatter::FormatStr;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+}
+
+impl FeedFormat {
+    pub fn parse(name: &str) -> Option<FeedFormat> {
+        match name {
+            "rss" => Some(FeedFormat::Rss),
+            "atom" => Some(FeedFormat::Atom),
+            _ => None,
+        }
+    }
+}
+
+///
+/// Title and link of the whole feed, like the HN page the listing came from
+///
+pub struct FeedInfo {
+    pub title: String,
+    pub link: String,
+}
+
+///
+/// Item of the listing with the comments included as its content
+///
+pub struct FeedEntry {
+    pub item: HnItem,
+    pub comments: Vec<HnItem>,
+}
+
+///
+/// Writes the entries as RSS 2.0 or Atom, now is used as the time the feed was updated
+///
+pub fn write_feed(format: FeedFormat,
+                  info: &FeedInfo,
+                  entries: &Vec<FeedEntry>,
+                  formatter: &FormatStr,
+                  now: i64)
+                  -> String {
+    match format {
+        FeedFormat::Rss => write_rss(info, entries, formatter, now),
+        FeedFormat::Atom => write_atom(info, entries, formatter, now),
+    }
+}
+
+fn write_rss(info: &FeedInfo, entries: &Vec<FeedEntry>, formatter: &FormatStr, now: i64) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<rss version=\"2.0\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n<channel>\n");
+    xml.push_str(&format!("<title>{}</title>\n", encode_minimal(&info.title)));
+    xml.push_str(&format!("<link>{}</link>\n", encode_minimal(&info.link)));
+    xml.push_str(&format!("<description>{}</description>\n", encode_minimal(&info.title)));
+    xml.push_str(&format!("<lastBuildDate>{}</lastBuildDate>\n", Utc.timestamp(now, 0).to_rfc2822()));
+    for entry in entries {
+        let item = &entry.item;
+        let discussion = item.web_url();
+        xml.push_str("<item>\n");
+        xml.push_str(&format!("<title>{}</title>\n", encode_minimal(&entry_title(item))));
+        xml.push_str(&format!("<link>{}</link>\n", encode_minimal(item.url.as_ref().unwrap_or(&discussion))));
+        xml.push_str(&format!("<comments>{}</comments>\n", encode_minimal(&discussion)));
+        xml.push_str(&format!("<guid isPermaLink=\"true\">{}</guid>\n", encode_minimal(&discussion)));
+        xml.push_str(&format!("<dc:creator>{}</dc:creator>\n", encode_minimal(&item.by)));
+        xml.push_str(&format!("<pubDate>{}</pubDate>\n", Utc.timestamp(item.time as i64, 0).to_rfc2822()));
+        xml.push_str(&format!("<description>{}</description>\n", encode_minimal(&create_content(entry, formatter))));
+        xml.push_str("</item>\n");
+    }
+    xml.push_str("</channel>\n</rss>\n");
+    xml
+}
+
+fn write_atom(info: &FeedInfo, entries: &Vec<FeedEntry>, formatter: &FormatStr, now: i64) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str(&format!("<title>{}</title>\n", encode_minimal(&info.title)));
+    xml.push_str(&format!("<link href=\"{}\"/>\n", encode_minimal(&info.link)));
+    xml.push_str(&format!("<id>{}</id>\n", encode_minimal(&info.link)));
+    xml.push_str(&format!("<updated>{}</updated>\n", Utc.timestamp(now, 0).to_rfc3339()));
+    for entry in entries {
+        let item = &entry.item;
+        let discussion = item.web_url();
+        let time = Utc.timestamp(item.time as i64, 0).to_rfc3339();
+        xml.push_str("<entry>\n");
+        xml.push_str(&format!("<title>{}</title>\n", encode_minimal(&entry_title(item))));
+        xml.push_str(&format!("<link rel=\"alternate\" href=\"{}\"/>\n",
+                              encode_minimal(item.url.as_ref().unwrap_or(&discussion))));
+        xml.push_str(&format!("<link rel=\"replies\" type=\"text/html\" href=\"{}\"/>\n", encode_minimal(&discussion)));
+        xml.push_str(&format!("<id>{}</id>\n", encode_minimal(&discussion)));
+        xml.push_str(&format!("<author><name>{}</name></author>\n", encode_minimal(&item.by)));
+        xml.push_str(&format!("<published>{}</published>\n<updated>{}</updated>\n", time, time));
+        xml.push_str(&format!("<content type=\"html\">{}</content>\n", encode_minimal(&create_content(entry, formatter))));
+        xml.push_str("</entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+fn entry_title(item: &HnItem) -> String {
+    match item.title {
+        Some(ref title) => title.clone(),
+        None => format!("Comment by {}", item.by),
+    }
+}
+
+///
+/// Html content of the entry, the text of the item and its comments go through the formatter like in the terminal
+///
+fn create_content(entry: &FeedEntry, formatter: &FormatStr) -> String {
+    let item = &entry.item;
+    let mut html = String::new();
+    match item.text_unescaped() {
+        Some(ref text) => html.push_str(&format!("<p>{}</p>\n", format_text(text, formatter))),
+        None => (),
+    }
+    html.push_str(&format!("<p>{} points by {}, <a href=\"{}\">{} comments</a></p>\n",
+                           item.score.unwrap_or(0),
+                           encode_minimal(&item.by),
+                           encode_minimal(&item.web_url()),
+                           item.comment_count()));
+    for comment in entry.comments.iter() {
+        match comment.text_unescaped() {
+            Some(ref text) => {
+                html.push_str(&format!("<blockquote><p><b>{}</b></p><p>{}</p></blockquote>\n",
+                                       encode_minimal(&comment.by),
+                                       format_text(text, formatter)))
+            }
+            None => (),
+        }
+    }
+    html
+}
+
+///
+/// Formatted text as escaped html lines, empty lines are left out
+///
+pub fn format_text(text: &str, formatter: &FormatStr) -> String {
+    let lines: Vec<String> = formatter.format(text)
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| encode_minimal(line))
+        .collect();
+    lines.join("<br/>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use formatting::formatter::Formatters;
+    use helpers::test_utils::read_item;
+
+    fn create_entries() -> Vec<FeedEntry> {
+        vec![FeedEntry {
+                 item: read_item("res/test/item.json"),
+                 comments: vec![read_item("res/test/children-item.json")],
+             }]
+    }
+
+    fn create_info() -> FeedInfo {
+        FeedInfo {
+            title: String::from("Hacker News top"),
+            link: String::from("https://news.ycombinator.com/news"),
+        }
+    }
+
+    #[test]
+    fn write_rss_test() {
+        let rss = write_feed(FeedFormat::Rss, &create_info(), &create_entries(), &Formatters::new(), 1500000000);
+        assert!(rss.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rss version=\"2.0\""));
+        assert!(rss.contains("<title>My YC app: Dropbox - Throw away your USB drive</title>"));
+        assert!(rss.contains("<comments>https:&#x2F;&#x2F;news.ycombinator.com&#x2F;item?id=8863</comments>"));
+        assert!(rss.contains("<dc:creator>dhouston</dc:creator>"));
+        assert!(rss.contains("<pubDate>Wed, 4 Apr 2007 19:16:40 +0000</pubDate>"));
+        assert!(rss.contains("&lt;blockquote&gt;&lt;p&gt;&lt;b&gt;cholantesh"));
+        assert!(rss.ends_with("</channel>\n</rss>\n"));
+    }
+
+    #[test]
+    fn write_atom_test() {
+        let atom = write_feed(FeedFormat::Atom, &create_info(), &create_entries(), &Formatters::new(), 1500000000);
+        assert!(atom.contains("<feed xmlns=\"http://www.w3.org/2005/Atom\">"));
+        assert!(atom.contains("<updated>2017-07-14T02:40:00+00:00</updated>"));
+        assert!(atom.contains("<link rel=\"alternate\" href=\"http:&#x2F;&#x2F;www.getdropbox.com&#x2F;u&#x2F;2&#x2F;screencast.html\"/>"));
+        assert!(atom.contains("<author><name>dhouston</name></author>"));
+        assert!(atom.contains("<content type=\"html\">&lt;p&gt;111 points by dhouston"));
+        assert!(atom.ends_with("</feed>\n"));
+    }
+
+}
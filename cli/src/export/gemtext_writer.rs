@@ -0,0 +1,160 @@
+use chrono::{TimeZone, Utc};
+use core::models::{HnItem, HnItemTree};
+use formatting::formatter::FormatStr;
+use formatting::code_block::{is_fence, CODE_FENCE};
+use helpers::path_utils::{find_links, get_host_from_link};
+
+///
+/// Stories of a listing as gemtext, numbered from first_index with links to their threads under /items/.
+/// Gemini clients wrap lines themselves so the formatter should not wrap the text
+///
+pub fn write_listing(title: &str, items: &Vec<HnItem>, first_index: usize, next_page: Option<&str>) -> String {
+    let mut gemtext = format!("# {}\n\n", title);
+    for (index, item) in items.iter().enumerate() {
+        gemtext.push_str(&format!("=> {} {}. {}", link_of(item), first_index + index, one_line(&title_of(item))));
+        match item.url.as_ref().and_then(|url| get_host_from_link(url)) {
+            Some(domain) => gemtext.push_str(&format!(" ({})", domain)),
+            None => (),
+        }
+        gemtext.push_str(&format!("\n{}\n=> {} {} comments\n\n", byline(item), thread_path(item), item.comment_count()));
+    }
+    match next_page {
+        Some(path) => gemtext.push_str(&format!("=> {} Next page\n", path)),
+        None => (),
+    }
+    gemtext
+}
+
+///
+/// The story or comment with its replies, a heading for each comment with » for every level of nesting
+///
+pub fn write_thread(tree: &HnItemTree, formatter: &FormatStr) -> String {
+    let item = &tree.item;
+    let mut gemtext = format!("# {}\n", one_line(&title_of(item)));
+    match item.url {
+        Some(ref url) => gemtext.push_str(&format!("=> {} {}\n", url, get_host_from_link(url).unwrap_or(url.clone()))),
+        None => (),
+    }
+    gemtext.push_str(&format!("{}\n=> {} Discuss on Hacker News\n\n", byline(item), item.web_url()));
+    push_text(&mut gemtext, item, formatter);
+    gemtext.push_str(&format!("## {} comments\n\n", tree.count() - 1));
+    for reply in tree.replies.iter() {
+        push_comment(&mut gemtext, reply, 0, formatter);
+    }
+    gemtext
+}
+
+fn push_comment(gemtext: &mut String, comment: &HnItemTree, depth: usize, formatter: &FormatStr) {
+    gemtext.push_str(&format!("### {}{}, {}\n",
+                              "» ".repeat(depth),
+                              comment.item.by,
+                              format_time(comment.item.time)));
+    push_text(gemtext, &comment.item, formatter);
+    for reply in comment.replies.iter() {
+        push_comment(gemtext, reply, depth + 1, formatter);
+    }
+}
+
+///
+/// Paragraphs of the text followed by the links in it as link lines
+///
+fn push_text(gemtext: &mut String, item: &HnItem, formatter: &FormatStr) {
+    let text = match item.text_unescaped() {
+        Some(text) => text,
+        None => return,
+    };
+    let mut in_code = false;
+    for line in formatter.format(&text).lines() {
+        // code blocks are preformatted text in gemtext too, the lines are kept as they are
+        if in_code || is_fence(line) {
+            if is_fence(line) {
+                in_code = !in_code;
+            }
+            gemtext.push_str(if is_fence(line) { CODE_FENCE } else { line });
+            gemtext.push('\n');
+            continue;
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        // lines starting with these would be read as gemtext line types
+        if line.starts_with("=>") || line.starts_with('#') || line.starts_with('*') || line.starts_with("```") {
+            gemtext.push(' ');
+        }
+        gemtext.push_str(line);
+        gemtext.push('\n');
+    }
+    for link in find_links(&text) {
+        gemtext.push_str(&format!("=> {}\n", link));
+    }
+    gemtext.push('\n');
+}
+
+pub fn thread_path(item: &HnItem) -> String {
+    format!("/items/{}", item.id)
+}
+
+fn link_of(item: &HnItem) -> String {
+    item.url.clone().unwrap_or(thread_path(item))
+}
+
+fn title_of(item: &HnItem) -> String {
+    match item.title {
+        Some(ref title) => title.clone(),
+        None => format!("Comment by {}", item.by),
+    }
+}
+
+fn one_line(text: &str) -> String {
+    text.replace('\r', " ").replace('\n', " ")
+}
+
+fn byline(item: &HnItem) -> String {
+    format!("{} points by {}, {}", item.score.unwrap_or(0), item.by, format_time(item.time))
+}
+
+fn format_time(time: f64) -> String {
+    Utc.timestamp(time as i64, 0).format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use formatting::formatter::Formatters;
+    use helpers::test_utils::read_item;
+
+    #[test]
+    fn write_listing_test() {
+        let mut ask = read_item("res/test/item.json");
+        ask.id = 1;
+        ask.url = None;
+        let gemtext = write_listing("Hacker News top", &vec![read_item("res/test/item.json"), ask], 11, Some("/feeds/top/3"));
+        assert!(gemtext.starts_with("# Hacker News top\n\n\
+                                     => http://www.getdropbox.com/u/2/screencast.html 11. My YC app: Dropbox - Throw \
+                                     away your USB drive (www.getdropbox.com)\n\
+                                     111 points by dhouston, 2007-04-04 19:16 UTC\n\
+                                     => /items/8863 71 comments\n\n"));
+        assert!(gemtext.contains("=> /items/1 12. My YC app"));
+        assert!(gemtext.ends_with("=> /feeds/top/3 Next page\n"));
+    }
+
+    #[test]
+    fn write_thread_test() {
+        let story = read_item("res/test/item.json");
+        let mut comment = read_item("res/test/children-item.json");
+        comment.parent = Some(story.id);
+        let mut reply = comment.clone();
+        reply.id = 1;
+        reply.parent = Some(comment.id);
+        reply.by = String::from("replier");
+        reply.text = Some(String::from("# not a heading<p><pre><code>  # kept\n</code></pre>"));
+        let gemtext = write_thread(&HnItemTree::build(story, &vec![comment, reply]), &Formatters::without_wrapping());
+        assert!(gemtext.starts_with("# My YC app: Dropbox - Throw away your USB drive\n\
+                                     => http://www.getdropbox.com/u/2/screencast.html www.getdropbox.com\n"));
+        assert!(gemtext.contains("=> https://news.ycombinator.com/item?id=8863 Discuss on Hacker News\n"));
+        assert!(gemtext.contains("## 2 comments\n\n### cholantesh, 2017-06-24 17:38 UTC\n2) is not a valid concern."));
+        assert!(gemtext.contains("=> https://www.ncbi.nlm.nih.gov/pubmed/11259180\n"));
+        assert!(gemtext.contains("### » replier, 2017-06-24 17:38 UTC\n # not a heading\n```\n  # kept\n```\n"));
+    }
+}
@@ -0,0 +1,178 @@
+use chrono::{TimeZone, Utc};
+use core::models::{HnItem, HnItemTree};
+use formatting::formatter::FormatStr;
+use helpers::path_utils::{find_links, get_host_from_link};
+
+///
+/// Host and port written into the menu lines which point back to the server
+///
+pub struct GopherHost {
+    pub host: String,
+    pub port: u16,
+}
+
+///
+/// Stories of a listing as a gophermap menu, numbered from first_index with the threads as submenus under /items/
+///
+pub fn write_listing(title: &str,
+                     items: &Vec<HnItem>,
+                     first_index: usize,
+                     next_page: Option<&str>,
+                     host: &GopherHost)
+                     -> String {
+    let mut map = info_line(title);
+    map.push_str(&info_line(""));
+    for (index, item) in items.iter().enumerate() {
+        let mut display = format!("{}. {}", first_index + index, title_of(item));
+        match item.url.as_ref().and_then(|url| get_host_from_link(url)) {
+            Some(domain) => display.push_str(&format!(" ({})", domain)),
+            None => (),
+        }
+        match item.url {
+            Some(ref url) => map.push_str(&url_line(&display, url, host)),
+            None => map.push_str(&menu_line('1', &display, &thread_selector(item), host)),
+        }
+        map.push_str(&menu_line('1',
+                                &format!("    {}, {} comments", byline(item), item.comment_count()),
+                                &thread_selector(item),
+                                host));
+    }
+    match next_page {
+        Some(selector) => {
+            map.push_str(&info_line(""));
+            map.push_str(&menu_line('1', "Next page", selector, host));
+        }
+        None => (),
+    }
+    map.push_str(".\r\n");
+    map
+}
+
+///
+/// The story or comment with its replies as info lines indented by nesting, links in the text become html lines
+///
+pub fn write_thread(tree: &HnItemTree, formatter: &FormatStr, host: &GopherHost) -> String {
+    let item = &tree.item;
+    let mut map = info_line(&title_of(item));
+    match item.url {
+        Some(ref url) => map.push_str(&url_line(url, url, host)),
+        None => (),
+    }
+    map.push_str(&info_line(&byline(item)));
+    map.push_str(&url_line("Discuss on Hacker News", &item.web_url(), host));
+    map.push_str(&info_line(""));
+    push_text(&mut map, item, "", formatter, host);
+    map.push_str(&info_line(&format!("{} comments", tree.count() - 1)));
+    map.push_str(&info_line(""));
+    for reply in tree.replies.iter() {
+        push_comment(&mut map, reply, 0, formatter, host);
+    }
+    map.push_str(".\r\n");
+    map
+}
+
+fn push_comment(map: &mut String, comment: &HnItemTree, depth: usize, formatter: &FormatStr, host: &GopherHost) {
+    let indent = "  ".repeat(depth);
+    map.push_str(&info_line(&format!("{}{}, {}", indent, comment.item.by, format_time(comment.item.time))));
+    push_text(map, &comment.item, &indent, formatter, host);
+    for reply in comment.replies.iter() {
+        push_comment(map, reply, depth + 1, formatter, host);
+    }
+}
+
+fn push_text(map: &mut String, item: &HnItem, indent: &str, formatter: &FormatStr, host: &GopherHost) {
+    let text = match item.text_unescaped() {
+        Some(text) => text,
+        None => return,
+    };
+    for line in formatter.format(&text).lines().map(|line| line.trim()).filter(|line| !line.is_empty()) {
+        map.push_str(&info_line(&format!("{}{}", indent, line)));
+    }
+    for link in find_links(&text) {
+        map.push_str(&url_line(&format!("{}{}", indent, link), &link, host));
+    }
+    map.push_str(&info_line(""));
+}
+
+pub fn thread_selector(item: &HnItem) -> String {
+    format!("/items/{}", item.id)
+}
+
+///
+/// Line of the menu, tabs and line breaks are not allowed in the fields
+///
+pub fn menu_line(item_type: char, display: &str, selector: &str, host: &GopherHost) -> String {
+    format!("{}{}\t{}\t{}\t{}\r\n", item_type, clean(display), clean(selector), host.host, host.port)
+}
+
+pub fn info_line(text: &str) -> String {
+    format!("i{}\t\terror.host\t1\r\n", clean(text))
+}
+
+///
+/// Link to the web, clients which understand the URL: selector open it in a browser
+///
+pub fn url_line(display: &str, url: &str, host: &GopherHost) -> String {
+    menu_line('h', display, &format!("URL:{}", url), host)
+}
+
+fn clean(text: &str) -> String {
+    text.replace('\t', " ").replace('\r', " ").replace('\n', " ")
+}
+
+fn title_of(item: &HnItem) -> String {
+    match item.title {
+        Some(ref title) => title.clone(),
+        None => format!("Comment by {}", item.by),
+    }
+}
+
+fn byline(item: &HnItem) -> String {
+    format!("{} points by {}, {}", item.score.unwrap_or(0), item.by, format_time(item.time))
+}
+
+fn format_time(time: f64) -> String {
+    Utc.timestamp(time as i64, 0).format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use formatting::formatter::Formatters;
+    use helpers::test_utils::read_item;
+
+    fn create_host() -> GopherHost {
+        GopherHost {
+            host: String::from("localhost"),
+            port: 7070,
+        }
+    }
+
+    #[test]
+    fn write_listing_test() {
+        let map = write_listing("Hacker News top", &vec![read_item("res/test/item.json")], 1, Some("/feeds/top/2"), &create_host());
+        assert!(map.starts_with("iHacker News top\t\terror.host\t1\r\ni\t\terror.host\t1\r\n"));
+        assert!(map.contains("h1. My YC app: Dropbox - Throw away your USB drive (www.getdropbox.com)\t\
+                              URL:http://www.getdropbox.com/u/2/screencast.html\tlocalhost\t7070\r\n"));
+        assert!(map.contains("1    111 points by dhouston, 2007-04-04 19:16 UTC, 71 comments\t/items/8863\tlocalhost\t7070\r\n"));
+        assert!(map.ends_with("1Next page\t/feeds/top/2\tlocalhost\t7070\r\n.\r\n"));
+    }
+
+    #[test]
+    fn write_thread_test() {
+        let story = read_item("res/test/item.json");
+        let mut comment = read_item("res/test/children-item.json");
+        comment.parent = Some(story.id);
+        let mut reply = comment.clone();
+        reply.id = 1;
+        reply.parent = Some(comment.id);
+        reply.by = String::from("replier");
+        reply.text = Some(String::from("tab\there"));
+        let map = write_thread(&HnItemTree::build(story, &vec![comment, reply]), &Formatters::new(), &create_host());
+        assert!(map.contains("i2 comments\t\terror.host\t1\r\n"));
+        assert!(map.contains("icholantesh, 2017-06-24 17:38 UTC\t\terror.host\t1\r\ni2) is not a valid concern."));
+        assert!(map.contains("hhttps://www.ncbi.nlm.nih.gov/pubmed/11259180\tURL:https://www.ncbi.nlm.nih.gov/pubmed/11259180\t"));
+        assert!(map.contains("i  replier, 2017-06-24 17:38 UTC\t\terror.host\t1\r\ni  tab here\t\terror.host\t1\r\n"));
+        assert!(map.ends_with(".\r\n"));
+    }
+}
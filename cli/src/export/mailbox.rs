@@ -0,0 +1,120 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use chrono::{TimeZone, Utc};
+use regex::Regex;
+use helpers::io_utils;
+use super::message::Message;
+
+///
+/// Appends the messages to the mbox file, lines starting with From are quoted like mboxrd does
+///
+pub fn write_mbox(path: &Path, messages: &Vec<Message>) -> io::Result<()> {
+    let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+    let from_line = from_line_pattern();
+    for message in messages {
+        file.write_all(create_mbox_entry(message, &from_line).as_bytes())?;
+    }
+    Ok(())
+}
+
+fn from_line_pattern() -> Regex {
+    Regex::new(r"^(>*From )").unwrap()
+}
+
+///
+/// The pattern matches the lines which get one more > so they aren't read as the start of a message
+///
+pub fn create_mbox_entry(message: &Message, from_line: &Regex) -> String {
+    let mut entry = format!("From {}@news.ycombinator.com {}\n",
+                            message.id,
+                            Utc.timestamp(message.time, 0).format("%a %b %e %T %Y"));
+    for line in message.to_string().lines() {
+        entry.push_str(&from_line.replace(line, ">$1"));
+        entry.push('\n');
+    }
+    entry.push('\n');
+    entry
+}
+
+///
+/// Delivers the messages into the Maildir, creating it if needed. The files are named after the item ids
+/// so exporting the same thread again replaces the earlier messages instead of duplicating them
+///
+pub fn write_maildir(path: &Path, messages: &Vec<Message>) -> io::Result<()> {
+    for dir in ["tmp", "new", "cur"].iter() {
+        fs::create_dir_all(path.join(dir))?;
+    }
+    for message in messages {
+        let name = format!("{}.hn{}.hncli", message.time, message.id);
+        let tmp = path.join("tmp").join(&name);
+        io_utils::write_file(&tmp, &message.to_string())?;
+        fs::rename(&tmp, path.join("new").join(&name))?;
+    }
+    Ok(())
+}
+
+///
+/// Directories and paths ending with / are Maildirs, other paths are mbox files
+///
+pub fn is_maildir_path(path: &str) -> bool {
+    path.ends_with('/') || Path::new(path).is_dir()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::File;
+    use std::io::prelude::*;
+
+    fn create_message(id: i32, body: &str) -> Message {
+        Message {
+            id: id,
+            time: 1175714200,
+            headers: vec![(String::from("Subject"), String::from("Dropbox")),
+                          (String::from("Message-ID"), format!("<{}@news.ycombinator.com>", id))],
+            body: String::from(body),
+        }
+    }
+
+    #[test]
+    fn create_mbox_entry_test() {
+        let entry = create_mbox_entry(&create_message(8863, "From the start\n>From quoted\nFrom: not a header\n"),
+                                      &from_line_pattern());
+        assert_eq!("From 8863@news.ycombinator.com Wed Apr  4 19:16:40 2007\n\
+                    Subject: Dropbox\n\
+                    Message-ID: <8863@news.ycombinator.com>\n\
+                    \n\
+                    >From the start\n\
+                    >>From quoted\n\
+                    From: not a header\n\n",
+                   entry);
+    }
+
+    #[test]
+    fn write_mbox_and_maildir_test() {
+        let dir = env::temp_dir().join(format!("hncli-mailbox-test-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let messages = vec![create_message(1, "first\n"), create_message(2, "second\n")];
+
+        let mbox = dir.join("thread.mbox");
+        write_mbox(&mbox, &messages).unwrap();
+        let mut contents = String::new();
+        File::open(&mbox).and_then(|mut file| file.read_to_string(&mut contents)).unwrap();
+        assert_eq!(2, contents.lines().filter(|line| line.starts_with("From ")).count());
+        assert!(contents.contains("Message-ID: <2@news.ycombinator.com>\n\nsecond\n\n"));
+
+        let maildir = dir.join("maildir");
+        write_maildir(&maildir, &messages).unwrap();
+        write_maildir(&maildir, &messages).unwrap();
+        assert_eq!(2, fs::read_dir(maildir.join("new")).unwrap().count());
+        assert_eq!(0, fs::read_dir(maildir.join("tmp")).unwrap().count());
+        assert!(maildir.join("cur").is_dir());
+        assert!(is_maildir_path(&maildir.to_string_lossy()));
+        assert!(!is_maildir_path(&mbox.to_string_lossy()));
+        assert!(is_maildir_path("archive/"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
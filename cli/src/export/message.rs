@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use chrono::{TimeZone, Utc};
+use core::models::HnItem;
+use formatting::formatter::FormatStr;
+
+const MESSAGE_ID_DOMAIN: &'static str = "news.ycombinator.com";
+
+///
+/// Item rendered as an RFC 5322 message, lines end with \n and are converted as the transport needs
+///
+pub struct Message {
+    /// Id of the item the message was created from
+    pub id: i32,
+    pub time: i64,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Message {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|&&(ref header, _)| header == name).map(|&(_, ref value)| value.as_str())
+    }
+
+    pub fn head_to_string(&self) -> String {
+        let mut head = String::new();
+        for &(ref name, ref value) in self.headers.iter() {
+            head.push_str(&format!("{}: {}\n", name, value));
+        }
+        head
+    }
+
+    pub fn to_string(&self) -> String {
+        format!("{}\n{}", self.head_to_string(), self.body)
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.body.lines().count()
+    }
+}
+
+pub fn message_id(id: i32) -> String {
+    format!("<{}@{}>", id, MESSAGE_ID_DOMAIN)
+}
+
+///
+/// Parses the item id back from message id created by message_id
+///
+pub fn parse_message_id(message_id: &str) -> Option<i32> {
+    let suffix = format!("@{}>", MESSAGE_ID_DOMAIN);
+    if message_id.starts_with('<') && message_id.ends_with(&suffix) {
+        message_id[1..message_id.len() - suffix.len()].parse::<i32>().ok()
+    } else {
+        None
+    }
+}
+
+///
+/// Ids of the ancestors of the item which are in items, the story first
+///
+pub fn find_references(id: i32, items: &BTreeMap<i32, HnItem>) -> Vec<i32> {
+    let mut references = Vec::new();
+    let mut parent = items.get(&id).and_then(|item| item.parent);
+    while let Some(parent_id) = parent {
+        match items.get(&parent_id) {
+            Some(item) => {
+                references.insert(0, parent_id);
+                parent = item.parent;
+            }
+            None => break,
+        }
+    }
+    references
+}
+
+///
+/// Messages of the story and all of its comments, threaded with References and In-Reply-To
+///
+pub fn create_thread_messages(story: &HnItem, comments: &Vec<HnItem>, formatter: &FormatStr) -> Vec<Message> {
+    let mut items: BTreeMap<i32, HnItem> = comments.iter().map(|comment| (comment.id, comment.clone())).collect();
+    items.insert(story.id, story.clone());
+    let thread_title = story.title.clone().unwrap_or(String::new());
+    vec![story].into_iter()
+        .chain(comments.iter())
+        .map(|item| create_message(item, &find_references(item.id, &items), &thread_title, None, formatter))
+        .collect()
+}
+
+///
+/// Renders the story or comment as a message, references are the ids of the ancestors starting from the story
+/// and thread_title is the title of the story which comments are replies to
+///
+pub fn create_message(item: &HnItem,
+                      references: &Vec<i32>,
+                      thread_title: &str,
+                      newsgroups: Option<&str>,
+                      formatter: &FormatStr)
+                      -> Message {
+    let subject = match item.title {
+        Some(ref title) => title.clone(),
+        None => format!("Re: {}", thread_title),
+    };
+    let mut headers = vec![(String::from("From"), format!("{} <{}@{}>", item.by, item.by, MESSAGE_ID_DOMAIN))];
+    match newsgroups {
+        Some(newsgroups) => headers.push((String::from("Newsgroups"), String::from(newsgroups))),
+        None => (),
+    }
+    headers.push((String::from("Subject"), clean_header(&subject)));
+    headers.push((String::from("Date"), Utc.timestamp(item.time as i64, 0).to_rfc2822()));
+    headers.push((String::from("Message-ID"), message_id(item.id)));
+    match references.last() {
+        Some(parent) => {
+            let ids: Vec<String> = references.iter().map(|id| message_id(*id)).collect();
+            headers.push((String::from("In-Reply-To"), message_id(*parent)));
+            headers.push((String::from("References"), ids.join(" ")));
+        }
+        None => (),
+    }
+    headers.push((String::from("Content-Type"), String::from("text/plain; charset=utf-8")));
+    headers.push((String::from("Content-Transfer-Encoding"), String::from("8bit")));
+    match item.score {
+        Some(score) => headers.push((String::from("X-HN-Score"), score.to_string())),
+        None => (),
+    }
+    headers.push((String::from("X-HN-URL"), item.web_url()));
+    Message {
+        id: item.id,
+        time: item.time as i64,
+        headers: headers,
+        body: create_body(item, formatter),
+    }
+}
+
+fn create_body(item: &HnItem, formatter: &FormatStr) -> String {
+    let mut body = String::new();
+    match item.url {
+        Some(ref url) => body.push_str(&format!("{}\n\n", url)),
+        None => (),
+    }
+    match item.text_unescaped() {
+        Some(ref text) => {
+            let formatted = formatter.format(text);
+            for line in formatted.lines() {
+                body.push_str(line.trim_right());
+                body.push('\n');
+            }
+            body.push('\n');
+        }
+        None => (),
+    }
+    body.push_str(&format!("-- \n{}\n", item.web_url()));
+    body
+}
+
+///
+/// Header values are kept on one line
+///
+fn clean_header(value: &str) -> String {
+    value.replace('\r', " ").replace('\n', " ").replace('\t', " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use formatting::formatter::Formatters;
+    use helpers::test_utils::read_item;
+
+    #[test]
+    fn create_story_message_test() {
+        let story = read_item("res/test/item.json");
+        let message = create_message(&story, &Vec::new(), "", Some("hn.top"), &Formatters::new());
+        assert_eq!(Some("My YC app: Dropbox - Throw away your USB drive"), message.header("Subject"));
+        assert_eq!(Some("dhouston <dhouston@news.ycombinator.com>"), message.header("From"));
+        assert_eq!(Some("<8863@news.ycombinator.com>"), message.header("Message-ID"));
+        assert_eq!(Some("Wed, 4 Apr 2007 19:16:40 +0000"), message.header("Date"));
+        assert!(message.header("References").is_none());
+        assert_eq!(Some("hn.top"), message.header("Newsgroups"));
+        assert!(message.body.starts_with("http://www.getdropbox.com/u/2/screencast.html\n\n"));
+        assert!(message.to_string().contains("X-HN-Score: 111\nX-HN-URL: https://news.ycombinator.com/item?id=8863\n\nhttp://www.getdropbox.com"));
+    }
+
+    #[test]
+    fn create_comment_message_test() {
+        let comment = read_item("res/test/children-item.json");
+        let message = create_message(&comment, &vec![14626000, 14626342], "Aluminum cookware", None, &Formatters::new());
+        assert_eq!(Some("Re: Aluminum cookware"), message.header("Subject"));
+        assert_eq!(Some("<14626000@news.ycombinator.com> <14626342@news.ycombinator.com>"),
+                   message.header("References"));
+        assert_eq!(Some("<14626342@news.ycombinator.com>"), message.header("In-Reply-To"));
+        assert!(message.header("Newsgroups").is_none());
+        assert!(message.body.contains("is not a valid concern"));
+        assert!(message.body.ends_with("-- \nhttps://news.ycombinator.com/item?id=14626530\n"));
+    }
+
+    #[test]
+    fn create_thread_messages_test() {
+        let mut story = read_item("res/test/item.json");
+        story.id = 14626342;
+        let comment = read_item("res/test/children-item.json");
+        let mut reply = comment.clone();
+        reply.id = 14626600;
+        reply.parent = Some(comment.id);
+        let messages = create_thread_messages(&story, &vec![comment, reply], &Formatters::new());
+        assert_eq!(vec![14626342, 14626530, 14626600], messages.iter().map(|message| message.id).collect::<Vec<i32>>());
+        assert!(messages[0].header("In-Reply-To").is_none());
+        assert_eq!(Some("<14626342@news.ycombinator.com> <14626530@news.ycombinator.com>"),
+                   messages[2].header("References"));
+        assert_eq!(Some("Re: My YC app: Dropbox - Throw away your USB drive"), messages[2].header("Subject"));
+    }
+
+    #[test]
+    fn message_id_test() {
+        assert_eq!(Some(8863), parse_message_id(&message_id(8863)));
+        assert!(parse_message_id("<8863@example.com>").is_none());
+        assert!(parse_message_id("8863").is_none());
+    }
+}
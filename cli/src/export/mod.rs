@@ -0,0 +1,8 @@
+#![allow(dead_code)]
+pub mod feed_writer;
+pub mod message;
+pub mod mailbox;
+pub mod site_writer;
+pub mod gemtext_writer;
+pub mod gophermap_writer;
+pub mod table_writer;
@@ -0,0 +1,186 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use chrono::{TimeZone, Utc};
+use core::models::{HnItem, HnItemTree};
+use decoding::text_encoding::encode_minimal;
+use formatting::formatter::FormatStr;
+use helpers::io_utils;
+use helpers::path_utils::get_host_from_link;
+use super::feed_writer::format_text;
+
+const INDEX_PAGE: &'static str = "index.html";
+
+/// Styles are inlined in every page so the pages can be copied anywhere one by one
+const STYLE: &'static str = "body { font-family: Verdana, Geneva, sans-serif; max-width: 50em; margin: 1em auto; padding: 0 1em; }\n\
+                             .meta, .domain { color: #828282; font-size: smaller; }\n\
+                             ol.stories li { margin-bottom: 0.6em; }\n\
+                             details { margin: 0.5em 0 0.5em 0.2em; padding-left: 0.8em; border-left: 1px solid #ddd; }\n\
+                             summary { cursor: pointer; color: #555; }\n\
+                             .text { margin: 0.3em 0; }\n";
+
+///
+/// Writes the digest as index.html and one page per story into dir, which is created if needed
+///
+pub fn write_site(dir: &Path,
+                  title: &str,
+                  stories: &Vec<HnItemTree>,
+                  formatter: &FormatStr,
+                  now: i64)
+                  -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    io_utils::write_file(&dir.join(INDEX_PAGE), &create_index_page(title, stories, now))?;
+    for story in stories {
+        io_utils::write_file(&dir.join(story_page_name(&story.item)), &create_story_page(title, story, formatter))?;
+    }
+    Ok(())
+}
+
+pub fn story_page_name(item: &HnItem) -> String {
+    format!("item-{}.html", item.id)
+}
+
+///
+/// List of the stories with their scores and domains, linking to the story pages for the comments
+///
+pub fn create_index_page(title: &str, stories: &Vec<HnItemTree>, now: i64) -> String {
+    let mut html = create_head(title);
+    html.push_str(&format!("<h1>{}</h1>\n", encode_minimal(title)));
+    html.push_str(&format!("<p class=\"meta\">Generated {}</p>\n", format_time(now as f64)));
+    html.push_str("<ol class=\"stories\">\n");
+    for story in stories {
+        let item = &story.item;
+        html.push_str(&format!("<li>{}", create_title_link(item)));
+        match item.url.as_ref().and_then(|url| get_host_from_link(url)) {
+            Some(domain) => html.push_str(&format!(" <span class=\"domain\">({})</span>", encode_minimal(&domain))),
+            None => (),
+        }
+        html.push_str(&format!("<br/>\n<span class=\"meta\">{} | <a href=\"{}\">{} comments</a></span></li>\n",
+                               create_byline(item),
+                               story_page_name(item),
+                               story.count() - 1));
+    }
+    html.push_str("</ol>\n</body>\n</html>\n");
+    html
+}
+
+///
+/// The story with its comment tree, every comment is a details element so subthreads collapse without scripts
+///
+pub fn create_story_page(site_title: &str, story: &HnItemTree, formatter: &FormatStr) -> String {
+    let item = &story.item;
+    let mut html = create_head(item.title.as_ref().map(|title| title.as_str()).unwrap_or(site_title));
+    html.push_str(&format!("<p class=\"meta\"><a href=\"{}\">{}</a></p>\n", INDEX_PAGE, encode_minimal(site_title)));
+    html.push_str(&format!("<h1>{}</h1>\n", create_title_link(item)));
+    html.push_str(&format!("<p class=\"meta\">{} | <a href=\"{}\">discuss on Hacker News</a></p>\n",
+                           create_byline(item),
+                           encode_minimal(&item.web_url())));
+    match item.text_unescaped() {
+        Some(ref text) => html.push_str(&format!("<div class=\"text\">{}</div>\n", format_text(text, formatter))),
+        None => (),
+    }
+    for reply in story.replies.iter() {
+        push_comment(&mut html, reply, formatter);
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn push_comment(html: &mut String, comment: &HnItemTree, formatter: &FormatStr) {
+    let item = &comment.item;
+    let replies = comment.count() - 1;
+    html.push_str(&format!("<details open>\n<summary><b>{}</b> <span class=\"meta\">{}{}</span></summary>\n",
+                           encode_minimal(&item.by),
+                           format_time(item.time),
+                           match replies {
+                               0 => String::new(),
+                               1 => String::from(", 1 reply"),
+                               n => format!(", {} replies", n),
+                           }));
+    let text = item.text_unescaped()
+        .map(|text| format_text(&text, formatter))
+        .unwrap_or(String::from("[deleted]"));
+    html.push_str(&format!("<div class=\"text\">{}</div>\n", text));
+    for reply in comment.replies.iter() {
+        push_comment(html, reply, formatter);
+    }
+    html.push_str("</details>\n");
+}
+
+fn create_head(title: &str) -> String {
+    format!("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+             <style>\n{}</style>\n</head>\n<body>\n",
+            encode_minimal(title),
+            STYLE)
+}
+
+fn create_title_link(item: &HnItem) -> String {
+    let discussion = item.web_url();
+    format!("<a href=\"{}\">{}</a>",
+            encode_minimal(item.url.as_ref().unwrap_or(&discussion)),
+            encode_minimal(item.title.as_ref().map(|title| title.as_str()).unwrap_or("")))
+}
+
+fn create_byline(item: &HnItem) -> String {
+    format!("{} points by {} at {}", item.score.unwrap_or(0), encode_minimal(&item.by), format_time(item.time))
+}
+
+fn format_time(time: f64) -> String {
+    Utc.timestamp(time as i64, 0).format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use formatting::formatter::Formatters;
+    use helpers::test_utils::read_item;
+
+    fn create_story() -> HnItemTree {
+        let story = read_item("res/test/item.json");
+        let mut comment = read_item("res/test/children-item.json");
+        comment.parent = Some(story.id);
+        let mut reply = comment.clone();
+        reply.id = 1;
+        reply.parent = Some(comment.id);
+        reply.text = None;
+        HnItemTree::build(story, &vec![comment, reply])
+    }
+
+    #[test]
+    fn create_index_page_test() {
+        let html = create_index_page("Hacker News top", &vec![create_story()], 1500000000);
+        assert!(html.starts_with("<!DOCTYPE html>\n"));
+        assert!(html.contains("<p class=\"meta\">Generated 2017-07-14 02:40 UTC</p>"));
+        assert!(html.contains("<li><a href=\"http:&#x2F;&#x2F;www.getdropbox.com&#x2F;u&#x2F;2&#x2F;screencast.html\">My YC app: Dropbox - Throw away \
+                               your USB drive</a> <span class=\"domain\">(www.getdropbox.com)</span>"));
+        assert!(html.contains("111 points by dhouston at 2007-04-04 19:16 UTC | <a href=\"item-8863.html\">2 comments</a>"));
+        assert!(!html.contains("<script"));
+
+        // &apos; isn't defined in HTML 4, quotes are escaped with references which work everywhere
+        let html = create_index_page("Ask HN: What's <new>?", &Vec::new(), 1500000000);
+        assert!(html.contains("<h1>Ask HN: What&#x27;s &lt;new&gt;?</h1>"));
+    }
+
+    #[test]
+    fn create_story_page_test() {
+        let html = create_story_page("Hacker News top", &create_story(), &Formatters::new());
+        assert!(html.contains("<title>My YC app: Dropbox - Throw away your USB drive</title>"));
+        assert!(html.contains("<a href=\"index.html\">Hacker News top</a>"));
+        assert!(html.contains("<details open>\n<summary><b>cholantesh</b>"));
+        assert!(html.contains(", 1 reply</span></summary>"));
+        assert!(html.contains("is not a valid concern"));
+        assert!(html.contains("<div class=\"text\">[deleted]</div>\n</details>\n</details>\n"));
+        assert_eq!(html.matches("<details").count(), html.matches("</details>").count());
+    }
+
+    #[test]
+    fn write_site_test() {
+        let dir = env::temp_dir().join(format!("hncli-site-test-{}", ::std::process::id()));
+        write_site(&dir.join("digest"), "Hacker News top", &vec![create_story()], &Formatters::new(), 1500000000)
+            .unwrap();
+        assert!(dir.join("digest").join("index.html").is_file());
+        assert!(dir.join("digest").join("item-8863.html").is_file());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
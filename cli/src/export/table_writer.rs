@@ -0,0 +1,212 @@
+use core::models::HnItem;
+use helpers::path_utils::get_host_from_link;
+
+pub const DEFAULT_FIELDS: &'static str = "rank,id,title,domain,by,score,descendants,time";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TableFormat {
+    Csv,
+    Tsv,
+}
+
+impl TableFormat {
+    pub fn parse(name: &str) -> Option<TableFormat> {
+        match name {
+            "csv" => Some(TableFormat::Csv),
+            "tsv" => Some(TableFormat::Tsv),
+            _ => None,
+        }
+    }
+}
+
+///
+/// Column of the table, the fields of HnItem and the ones derived from them
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TableField {
+    /// Position in the listing counting from 1
+    Rank,
+    Id,
+    By,
+    Parent,
+    Descendants,
+    /// Ids of the direct replies separated by spaces
+    Kids,
+    Title,
+    Score,
+    /// Text with html entities decoded, the tags are kept
+    Text,
+    /// Unix time of the item
+    Time,
+    Type,
+    Url,
+    Dead,
+    /// Host of the url
+    Domain,
+    /// Seconds since the item was posted
+    Age,
+    /// Comments in the thread for stories, replies for comments
+    Comments,
+    /// Page of the item on the HackerNews site
+    HnUrl,
+}
+
+const FIELD_NAMES: [(&'static str, TableField); 17] = [("rank", TableField::Rank),
+                                                       ("id", TableField::Id),
+                                                       ("by", TableField::By),
+                                                       ("parent", TableField::Parent),
+                                                       ("descendants", TableField::Descendants),
+                                                       ("kids", TableField::Kids),
+                                                       ("title", TableField::Title),
+                                                       ("score", TableField::Score),
+                                                       ("text", TableField::Text),
+                                                       ("time", TableField::Time),
+                                                       ("type", TableField::Type),
+                                                       ("url", TableField::Url),
+                                                       ("dead", TableField::Dead),
+                                                       ("domain", TableField::Domain),
+                                                       ("age", TableField::Age),
+                                                       ("comments", TableField::Comments),
+                                                       ("hn_url", TableField::HnUrl)];
+
+impl TableField {
+    pub fn parse(name: &str) -> Option<TableField> {
+        FIELD_NAMES.iter().find(|&&(field_name, _)| field_name == name).map(|&(_, field)| field)
+    }
+
+    pub fn name(&self) -> &'static str {
+        FIELD_NAMES.iter().find(|&&(_, field)| field == *self).map(|&(name, _)| name).unwrap()
+    }
+
+    ///
+    /// Value of the field for the item at rank, missing values are empty
+    ///
+    pub fn value(&self, rank: usize, item: &HnItem, now: i64) -> String {
+        match *self {
+            TableField::Rank => rank.to_string(),
+            TableField::Id => item.id.to_string(),
+            TableField::By => item.by.clone(),
+            TableField::Parent => item.parent.map(|parent| parent.to_string()).unwrap_or(String::new()),
+            TableField::Descendants => item.descendants.map(|n| n.to_string()).unwrap_or(String::new()),
+            TableField::Kids => {
+                item.kids
+                    .as_ref()
+                    .map(|kids| kids.iter().map(|kid| kid.to_string()).collect::<Vec<String>>().join(" "))
+                    .unwrap_or(String::new())
+            }
+            TableField::Title => item.title.clone().unwrap_or(String::new()),
+            TableField::Score => item.score.map(|score| score.to_string()).unwrap_or(String::new()),
+            TableField::Text => item.text_unescaped().map(|text| text.into_owned()).unwrap_or(String::new()),
+            TableField::Time => (item.time as i64).to_string(),
+            TableField::Type => item.type_str.clone(),
+            TableField::Url => item.url.clone().unwrap_or(String::new()),
+            TableField::Dead => item.dead.unwrap_or(false).to_string(),
+            TableField::Domain => item.url.as_ref().and_then(|url| get_host_from_link(url)).unwrap_or(String::new()),
+            TableField::Age => (now - item.time as i64).to_string(),
+            TableField::Comments => item.comment_count().to_string(),
+            TableField::HnUrl => item.web_url(),
+        }
+    }
+}
+
+///
+/// Parses comma separated field names like rank,id,title
+///
+pub fn parse_fields(names: &str) -> Result<Vec<TableField>, String> {
+    let fields = names.split(',')
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .map(|name| TableField::parse(name).ok_or(format!("unknown field {}", name)))
+        .collect::<Result<Vec<TableField>, String>>()?;
+    if fields.is_empty() {
+        return Err(String::from("no fields given"));
+    }
+    Ok(fields)
+}
+
+///
+/// Header row and a row for each item with its rank. CSV is quoted as in RFC 4180, TSV can't quote
+/// so tabs and line breaks in values become spaces
+///
+pub fn write_table(format: TableFormat, fields: &Vec<TableField>, rows: &Vec<(usize, HnItem)>, now: i64) -> String {
+    let mut table = String::new();
+    let header: Vec<String> = fields.iter().map(|field| String::from(field.name())).collect();
+    push_row(&mut table, format, header);
+    for &(rank, ref item) in rows.iter() {
+        push_row(&mut table, format, fields.iter().map(|field| field.value(rank, item, now)).collect());
+    }
+    table
+}
+
+fn push_row(table: &mut String, format: TableFormat, values: Vec<String>) {
+    match format {
+        TableFormat::Csv => {
+            let values: Vec<String> = values.iter().map(|value| quote_csv(value)).collect();
+            table.push_str(&values.join(","));
+            table.push_str("\r\n");
+        }
+        TableFormat::Tsv => {
+            let values: Vec<String> = values.iter()
+                .map(|value| value.replace("\r\n", " ").replace(|c| c == '\t' || c == '\r' || c == '\n', " "))
+                .collect();
+            table.push_str(&values.join("\t"));
+            table.push('\n');
+        }
+    }
+}
+
+pub fn quote_csv(value: &str) -> String {
+    if value.contains(|c| c == ',' || c == '"' || c == '\r' || c == '\n') || value.trim() != value {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        String::from(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use helpers::test_utils::read_item;
+
+    #[test]
+    fn parse_fields_test() {
+        assert_eq!(vec![TableField::Rank, TableField::Domain, TableField::HnUrl],
+                   parse_fields("rank, domain,hn_url").unwrap());
+        assert_eq!(8, parse_fields(DEFAULT_FIELDS).unwrap().len());
+        assert_eq!(Err(String::from("unknown field points")), parse_fields("id,points"));
+        assert!(parse_fields(",").is_err());
+        for &(name, field) in FIELD_NAMES.iter() {
+            assert_eq!(name, field.name());
+        }
+    }
+
+    #[test]
+    fn write_csv_test() {
+        let mut story = read_item("res/test/item.json");
+        story.title = Some(String::from("Dropbox, \"the\" USB killer"));
+        let fields = parse_fields(DEFAULT_FIELDS).unwrap();
+        let table = write_table(TableFormat::Csv, &fields, &vec![(3, story)], 1175714260);
+        assert_eq!("rank,id,title,domain,by,score,descendants,time\r\n\
+                    3,8863,\"Dropbox, \"\"the\"\" USB killer\",www.getdropbox.com,dhouston,111,71,1175714200\r\n",
+                   table);
+    }
+
+    #[test]
+    fn write_tsv_test() {
+        let comment = read_item("res/test/children-item.json");
+        let fields = parse_fields("id,parent,title,age,text").unwrap();
+        let table = write_table(TableFormat::Tsv, &fields, &vec![(1, comment)], 1498325992);
+        let rows: Vec<&str> = table.lines().collect();
+        assert_eq!(2, rows.len());
+        assert!(rows[1].starts_with("14626530\t14626342\t\t60\t2) is not a valid concern."));
+        assert_eq!(5, rows[1].split('\t').count());
+    }
+
+    #[test]
+    fn quote_csv_test() {
+        assert_eq!("plain", quote_csv("plain"));
+        assert_eq!("\"a\nb\"", quote_csv("a\nb"));
+        assert_eq!("\" padded\"", quote_csv(" padded"));
+        assert_eq!("", quote_csv(""));
+    }
+}
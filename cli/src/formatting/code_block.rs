@@ -0,0 +1,267 @@
+/// Line around the code blocks of formatted text, the lines between are kept as they were written
+pub const CODE_FENCE: &'static str = "```";
+
+pub fn is_fence(line: &str) -> bool {
+    line.trim() == CODE_FENCE
+}
+
+///
+/// Lines of the text which would be read as fences get a zero width space in front, so markdown
+/// pasted in comments shows the ``` without starting a code block
+///
+pub fn escape_fences(text: &str) -> String {
+    if !text.contains(CODE_FENCE) {
+        return String::from(text);
+    }
+    let lines: Vec<String> = text.split('\n')
+        .map(|line| if is_fence(line) { format!("\u{200B}{}", line) } else { String::from(line) })
+        .collect();
+    lines.join("\n")
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Token {
+    Plain,
+    Keyword,
+    Str,
+    Comment,
+    Number,
+}
+
+///
+/// What the highlighting needs to know of a language, and the snippets which give it away
+///
+pub struct Language {
+    pub name: &'static str,
+    keywords: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comments: bool,
+    quotes: &'static [char],
+    case_insensitive: bool,
+    signatures: &'static [&'static str],
+}
+
+pub static LANGUAGES: [Language; 8] = [
+    Language {
+        name: "rust",
+        keywords: &["as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn", "for",
+                    "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+                    "self", "Self", "static", "struct", "trait", "true", "type", "unsafe", "use", "where", "while"],
+        line_comments: &["//"],
+        block_comments: true,
+        quotes: &['"'],
+        case_insensitive: false,
+        signatures: &["fn ", "let mut ", "impl ", "pub fn", "&self", "&mut ", "println!", "::new(", "Option<",
+                      "Result<", "unwrap()", "use std::", "#[derive"],
+    },
+    Language {
+        name: "python",
+        keywords: &["and", "as", "assert", "break", "class", "continue", "def", "del", "elif", "else", "except",
+                    "False", "finally", "for", "from", "if", "import", "in", "is", "lambda", "None", "not", "or",
+                    "pass", "raise", "return", "True", "try", "while", "with", "yield"],
+        line_comments: &["#"],
+        block_comments: false,
+        quotes: &['"', '\''],
+        case_insensitive: false,
+        signatures: &["def ", "import ", "self.", "elif ", "print(", "None", "__init__", "lambda ", "):\n",
+                      "True", "False"],
+    },
+    Language {
+        name: "javascript",
+        keywords: &["async", "await", "break", "case", "catch", "class", "const", "continue", "default", "else",
+                    "export", "false", "for", "function", "if", "import", "let", "new", "null", "return",
+                    "switch", "this", "throw", "true", "try", "typeof", "undefined", "var", "while"],
+        line_comments: &["//"],
+        block_comments: true,
+        quotes: &['"', '\'', '`'],
+        case_insensitive: false,
+        signatures: &["function", "const ", "=> ", "console.log", "===", "var ", "require(", "document.",
+                      "undefined", "async ", "});"],
+    },
+    Language {
+        name: "go",
+        keywords: &["break", "case", "chan", "const", "continue", "default", "defer", "else", "false", "for",
+                    "func", "go", "if", "import", "interface", "map", "nil", "package", "range", "return",
+                    "select", "struct", "switch", "true", "type", "var"],
+        line_comments: &["//"],
+        block_comments: true,
+        quotes: &['"', '\'', '`'],
+        case_insensitive: false,
+        signatures: &["func ", ":= ", "package ", "fmt.", "chan ", "defer ", "err != nil", "interface{}"],
+    },
+    Language {
+        name: "c",
+        keywords: &["auto", "bool", "break", "case", "char", "class", "const", "continue", "default", "delete",
+                    "do", "double", "else", "enum", "extern", "float", "for", "if", "int", "long", "namespace",
+                    "new", "NULL", "return", "sizeof", "static", "struct", "switch", "template", "typedef",
+                    "unsigned", "void", "while"],
+        line_comments: &["//"],
+        block_comments: true,
+        quotes: &['"', '\''],
+        case_insensitive: false,
+        signatures: &["#include", "int ", "printf(", "void ", "char *", "->", "malloc(", "NULL", "std::",
+                      "sizeof(", "return 0;"],
+    },
+    Language {
+        name: "java",
+        keywords: &["abstract", "boolean", "break", "case", "catch", "class", "else", "extends", "false",
+                    "final", "for", "if", "implements", "import", "int", "interface", "new", "null", "private",
+                    "protected", "public", "return", "static", "this", "throw", "throws", "true", "try", "void",
+                    "while"],
+        line_comments: &["//"],
+        block_comments: true,
+        quotes: &['"', '\''],
+        case_insensitive: false,
+        signatures: &["public class", "public static", "System.out", "private ", "String[]", "@Override",
+                      "import java", "new "],
+    },
+    Language {
+        name: "shell",
+        keywords: &["case", "do", "done", "echo", "elif", "else", "esac", "export", "fi", "for", "function",
+                    "if", "in", "then", "while"],
+        line_comments: &["#"],
+        block_comments: false,
+        quotes: &['"', '\''],
+        case_insensitive: false,
+        signatures: &["$ ", "#!/bin/", "sudo ", "echo ", "| grep", "export ", "apt-get ", "cd ", "curl ",
+                      " && "],
+    },
+    Language {
+        name: "sql",
+        keywords: &["and", "as", "by", "create", "delete", "from", "group", "insert", "into", "join", "left",
+                    "not", "null", "on", "or", "order", "select", "set", "table", "update", "values", "where"],
+        line_comments: &["--"],
+        block_comments: true,
+        quotes: &['\''],
+        case_insensitive: true,
+        signatures: &["SELECT ", "select ", "FROM ", "WHERE ", "where ", "INSERT INTO", "CREATE TABLE",
+                      "JOIN ", "GROUP BY", "ORDER BY"],
+    },
+];
+
+///
+/// Guesses the language of the code by the snippets typical of each, at least two are needed
+///
+pub fn detect_language(code: &str) -> Option<&'static Language> {
+    let mut best: Option<(&'static Language, usize)> = None;
+    for language in LANGUAGES.iter() {
+        let score = language.signatures.iter().filter(|signature| code.contains(*signature)).count();
+        if score >= 2 && best.map(|(_, best_score)| score > best_score).unwrap_or(true) {
+            best = Some((language, score));
+        }
+    }
+    best.map(|(language, _)| language)
+}
+
+///
+/// Splits a line of code into tokens, comments and strings are only recognized within the line
+///
+pub fn tokenize<'a>(line: &'a str, language: &Language) -> Vec<(Token, &'a str)> {
+    let mut tokens: Vec<(Token, &'a str)> = Vec::new();
+    let mut start = 0;
+    while start < line.len() {
+        let rest = &line[start..];
+        let c = rest.chars().next().unwrap();
+        let (token, len) = if language.line_comments.iter().any(|comment| rest.starts_with(comment)) {
+            (Token::Comment, rest.len())
+        } else if language.block_comments && rest.starts_with("/*") {
+            (Token::Comment, rest[2..].find("*/").map(|end| end + 4).unwrap_or(rest.len()))
+        } else if language.quotes.contains(&c) {
+            (Token::Str, string_len(rest, c))
+        } else if c.is_digit(10) {
+            (Token::Number, word_len(rest, |c| c.is_alphanumeric() || c == '.' || c == '_'))
+        } else if c.is_alphabetic() || c == '_' {
+            let len = word_len(rest, |c| c.is_alphanumeric() || c == '_');
+            (if is_keyword(&rest[..len], language) { Token::Keyword } else { Token::Plain }, len)
+        } else {
+            (Token::Plain, c.len_utf8())
+        };
+        match tokens.last_mut() {
+            // plain runs are merged so they are written at once
+            Some(&mut (Token::Plain, ref mut text)) if token == Token::Plain => {
+                let text_start = start - text.len();
+                *text = &line[text_start..start + len];
+            }
+            _ => tokens.push((token, &line[start..start + len])),
+        }
+        start += len;
+    }
+    tokens
+}
+
+fn is_keyword(word: &str, language: &Language) -> bool {
+    if language.case_insensitive {
+        language.keywords.iter().any(|keyword| keyword.eq_ignore_ascii_case(word))
+    } else {
+        language.keywords.contains(&word)
+    }
+}
+
+fn word_len<F: Fn(char) -> bool>(s: &str, is_part: F) -> usize {
+    s.find(|c| !is_part(c)).unwrap_or(s.len())
+}
+
+/// Length of the string literal starting with the quote, to the end of line when it isn't closed
+fn string_len(s: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (idx, c) in s.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return idx + c.len_utf8();
+        }
+    }
+    s.len()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn language(name: &str) -> &'static Language {
+        LANGUAGES.iter().find(|language| language.name == name).unwrap()
+    }
+
+    #[test]
+    fn escape_fences_test() {
+        assert_eq!("a\n\u{200B}```\nb ``` c", escape_fences("a\n```\nb ``` c"));
+        assert!(!is_fence(&escape_fences(" ``` ")));
+        assert_eq!("no fences", escape_fences("no fences"));
+    }
+
+    #[test]
+    fn detect_language_test() {
+        let rust = "fn main() {\n    let mut v = Vec::new();\n    println!(\"{:?}\", v);\n}";
+        assert_eq!("rust", detect_language(rust).unwrap().name);
+        let python = "def parse(self):\n    return None";
+        assert_eq!("python", detect_language(python).unwrap().name);
+        let shell = "  $ curl -IA \"foo\" https://www.jwz.org/\n  HTTP/1.1 200 OK";
+        assert_eq!("shell", detect_language(shell).unwrap().name);
+        assert_eq!("sql", detect_language("SELECT id FROM items WHERE score > 10").unwrap().name);
+        assert!(detect_language("just some text, indented").is_none());
+    }
+
+    #[test]
+    fn tokenize_test() {
+        let tokens = tokenize("let n = 42; // \"answer\"", language("rust"));
+        assert_eq!(vec![(Token::Keyword, "let"),
+                        (Token::Plain, " n = "),
+                        (Token::Number, "42"),
+                        (Token::Plain, "; "),
+                        (Token::Comment, "// \"answer\"")],
+                   tokens);
+        let tokens = tokenize("print('it\\'s') # done", language("python"));
+        assert_eq!(vec![(Token::Plain, "print("),
+                        (Token::Str, "'it\\'s'"),
+                        (Token::Plain, ") "),
+                        (Token::Comment, "# done")],
+                   tokens);
+        let tokens = tokenize("select x /* all */ from t", language("sql"));
+        assert_eq!((Token::Keyword, "select"), tokens[0]);
+        assert_eq!((Token::Comment, "/* all */"), tokens[2]);
+        assert_eq!((Token::Keyword, "from"), tokens[4]);
+        assert_eq!(vec![(Token::Str, "\"open")], tokenize("\"open", language("c")));
+    }
+}
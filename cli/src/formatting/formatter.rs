@@ -1,28 +1,184 @@
+use core::config::FormatStageConfig;
 use super::tag_formatter::TagFormatter;
 use super::general_formatter::GeneralFormatter;
+use super::user_formatter::{CommandFormatter, RegexFormatter};
 
 pub trait FormatStr {
     fn format(&self, s: &str) -> String;
 }
 
+struct FormatStage {
+    name: String,
+    formatter: Box<FormatStr>,
+}
+
+///
+/// Ordered pipeline of named stages the text goes through, "tags" turns the html into text
+/// and "wrap" wraps the lines
+///
 pub struct Formatters {
-    formatters: Vec<Box<FormatStr>>,
+    stages: Vec<FormatStage>,
 }
 
 impl Formatters {
 
     pub fn new() -> Formatters {
         Formatters {
-            formatters: vec!(Box::new(TagFormatter), Box::new(GeneralFormatter))
+            stages: vec!(tags_stage(), wrap_stage())
+        }
+    }
+
+    ///
+    /// Formats tags but leaves the lines unwrapped, for clients which wrap the text themselves
+    ///
+    pub fn without_wrapping() -> Formatters {
+        Formatters {
+            stages: vec!(tags_stage())
+        }
+    }
+
+    ///
+    /// No stages at all, the text is kept as it is
+    ///
+    pub fn raw() -> Formatters {
+        Formatters {
+            stages: Vec::new()
+        }
+    }
+
+    ///
+    /// Pipeline of the enabled stages in the order configured, stages which aren't built in
+    /// need a regex or a command
+    ///
+    pub fn from_config(configs: &Vec<FormatStageConfig>) -> Result<Formatters, String> {
+        Formatters::build(configs, true)
+    }
+
+    ///
+    /// The configured pipeline without the built in wrap stage, for clients which wrap the text themselves
+    ///
+    pub fn from_config_without_wrapping(configs: &Vec<FormatStageConfig>) -> Result<Formatters, String> {
+        Formatters::build(configs, false)
+    }
+
+    fn build(configs: &Vec<FormatStageConfig>, wrapping: bool) -> Result<Formatters, String> {
+        let mut stages = Vec::new();
+        for config in configs.iter().filter(|config| config.enabled) {
+            let formatter: Box<FormatStr> = match (config.regex.as_ref(), config.command.as_ref()) {
+                (Some(regex), None) => Box::new(RegexFormatter::new(regex, &config.replacement)?),
+                (None, Some(command)) => Box::new(CommandFormatter::new(command)),
+                (Some(_), Some(_)) => return Err(format!("stage {} has both a regex and a command", config.name)),
+                (None, None) => {
+                    match config.name.as_str() {
+                        "tags" => Box::new(TagFormatter::new()),
+                        "wrap" if !wrapping => continue,
+                        "wrap" => Box::new(GeneralFormatter),
+                        _ => return Err(format!("unknown stage {}, give it a regex or a command", config.name)),
+                    }
+                }
+            };
+            stages.push(FormatStage {
+                name: config.name.clone(),
+                formatter: formatter,
+            });
         }
+        Ok(Formatters { stages: stages })
+    }
+
+    pub fn stage_names(&self) -> Vec<&str> {
+        self.stages.iter().map(|stage| stage.name.as_str()).collect()
+    }
+}
+
+fn tags_stage() -> FormatStage {
+    FormatStage {
+        name: String::from("tags"),
+        formatter: Box::new(TagFormatter::new()),
     }
 }
+
+fn wrap_stage() -> FormatStage {
+    FormatStage {
+        name: String::from("wrap"),
+        formatter: Box::new(GeneralFormatter),
+    }
+}
+
 impl FormatStr for Formatters {
     fn format(&self, s: &str) -> String {
-        let mut working_copy:String = self.formatters[0].format(s);
-        for f in self.formatters.iter().skip(1) {
-            working_copy = f.format(&working_copy);
+        let mut working_copy = String::from(s);
+        for stage in self.stages.iter() {
+            working_copy = stage.formatter.format(&working_copy);
         }
         working_copy
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json;
+
+    fn parse_stages(json: &str) -> Vec<FormatStageConfig> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn default_pipeline_test() {
+        assert_eq!(vec!["tags", "wrap"], Formatters::new().stage_names());
+        assert_eq!(vec!["tags"], Formatters::without_wrapping().stage_names());
+        assert_eq!("<p>kept &amp; as is", Formatters::raw().format("<p>kept &amp; as is"));
+        assert_eq!("para one\npara two ", Formatters::new().format("para one<p>para two"));
+    }
+
+    #[test]
+    fn configured_pipeline_test() {
+        let stages = parse_stages(r#"[{"name": "nbsp", "regex": "\u00a0", "replacement": " "},
+                                      {"name": "tags"},
+                                      {"name": "wrap", "enabled": false},
+                                      {"name": "upper", "command": "tr a-z A-Z"}]"#);
+        let formatters = Formatters::from_config(&stages).unwrap();
+        assert_eq!(vec!["nbsp", "tags", "upper"], formatters.stage_names());
+        assert_eq!("\nA B", formatters.format("<p>a\u{a0}<i>b</i>"));
+
+        let stages = parse_stages(r#"[{"name": "tags"}, {"name": "wrap"}, {"name": "upper", "command": "tr a-z A-Z"}]"#);
+        let unwrapped = Formatters::from_config_without_wrapping(&stages).unwrap();
+        assert_eq!(vec!["tags", "upper"], unwrapped.stage_names());
+    }
+
+    #[test]
+    fn invalid_pipeline_test() {
+        assert!(Formatters::from_config(&parse_stages(r#"[{"name": "pretty"}]"#)).is_err());
+        assert!(Formatters::from_config(&parse_stages(r#"[{"name": "bad", "regex": "("}]"#)).is_err());
+        assert!(Formatters::from_config(&parse_stages(r#"[{"name": "both", "regex": "a", "command": "cat"}]"#)).is_err());
+    }
+}
+
+#[cfg(all(feature = "nightly", test))]
+mod bench {
+    use super::*;
+    use test::Bencher;
+    use helpers::bench_utils::{large_thread, thread_bytes};
+
+    #[bench]
+    fn format_thread(b: &mut Bencher) {
+        let thread = large_thread();
+        let texts: Vec<String> = thread.iter().filter_map(|item| item.text_unescaped().map(|text| text.into_owned())).collect();
+        let formatters = Formatters::new();
+        b.bytes = thread_bytes(&thread);
+        b.iter(|| texts.iter().map(|text| formatters.format(text).len()).sum::<usize>());
+    }
+
+    #[bench]
+    fn decode_and_format_thread(b: &mut Bencher) {
+        let thread = large_thread();
+        let formatters = Formatters::new();
+        b.bytes = thread_bytes(&thread);
+        b.iter(|| {
+            thread.iter()
+                .filter_map(|item| item.text_unescaped())
+                .map(|text| formatters.format(&text).len())
+                .sum::<usize>()
+        });
+    }
+}
@@ -1,4 +1,6 @@
 use super::formatter::FormatStr;
+use super::quote_formatter::{is_quote, wrap_quote};
+use super::code_block::is_fence;
 
 pub struct GeneralFormatter;
 
@@ -12,7 +14,53 @@ impl FormatStr for GeneralFormatter {
 }
 
 impl GeneralFormatter {
+    ///
+    /// Wraps the text, quoted lines are wrapped on their own so every line of them keeps the >
+    /// and code blocks are left as they are
+    ///
     fn format_to_length(&self, s: &str) -> String {
+        if !s.lines().any(|line| is_quote(line) || is_fence(line)) {
+            return self.wrap_text(s);
+        }
+        let mut parts: Vec<String> = Vec::new();
+        let mut text_lines: Vec<&str> = Vec::new();
+        let mut in_code = false;
+        for line in s.split('\n') {
+            if in_code || is_fence(line) {
+                if !text_lines.is_empty() {
+                    parts.push(self.wrap_lines(&text_lines));
+                    text_lines.clear();
+                }
+                parts.push(String::from(line));
+                if is_fence(line) {
+                    in_code = !in_code;
+                }
+            } else if is_quote(line) {
+                if !text_lines.is_empty() {
+                    parts.push(self.wrap_lines(&text_lines));
+                    text_lines.clear();
+                }
+                parts.push(wrap_quote(line, LINE_LEN).join("\n"));
+            } else {
+                text_lines.push(line);
+            }
+        }
+        if !text_lines.is_empty() {
+            parts.push(self.wrap_lines(&text_lines));
+        }
+        parts.join("\n")
+    }
+
+    fn wrap_lines(&self, lines: &Vec<&str>) -> String {
+        let text = lines.join("\n");
+        if text.trim().is_empty() {
+            text
+        } else {
+            self.wrap_text(&text)
+        }
+    }
+
+    fn wrap_text(&self, s: &str) -> String {
         let r = s.to_owned();
         let words = r.split(" ");
         let mut result: String = String::new();
@@ -57,4 +105,26 @@ mod test {
         assert!(*count > 0);
 
     }
+
+    #[test]
+    fn test_quote_formatting() {
+        let f = GeneralFormatter;
+        let quote = "> Lorem ipsum dolor sit amet, consectetur adipiscing elit. Maecenas maximus eleifend nibh, \
+                     eget fringilla augue mattis ac.";
+        let r = f.format_to_length(&format!("\n{}\nI disagree.", quote));
+        let lines: Vec<&str> = r.lines().collect();
+        assert_eq!(4, lines.len());
+        assert_eq!("", lines[0]);
+        assert!(lines[1].starts_with("> Lorem ipsum") && lines[2].starts_with("> ") && lines[2].ends_with("mattis ac."));
+        assert_eq!("I disagree. ", lines[3]);
+        assert_eq!(f.wrap_text("no quotes\nhere"), f.format_to_length("no quotes\nhere"));
+    }
+
+    #[test]
+    fn test_code_formatting() {
+        let f = GeneralFormatter;
+        let code = format!("  let long = \"{}\";\n  > not a quote", "x".repeat(LINE_LEN));
+        let r = f.format_to_length(&format!("\n```\n{}\n```\nAfter   the code.", code));
+        assert_eq!(format!("\n```\n{}\n```\nAfter   the code. ", code), r);
+    }
 }
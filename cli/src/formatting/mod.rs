@@ -1,4 +1,7 @@
 #![allow(dead_code)]
 pub mod formatter;
 mod tag_formatter;
-mod general_formatter;
\ No newline at end of file
+mod general_formatter;
+mod user_formatter;
+pub mod quote_formatter;
+pub mod code_block;
\ No newline at end of file
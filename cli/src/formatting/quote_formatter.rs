@@ -0,0 +1,125 @@
+use super::formatter::FormatStr;
+use super::code_block::is_fence;
+
+const QUOTE_LINE_LEN: usize = 72;
+
+///
+/// Whether the line of formatted text is quoted, HN has no markup for quotes so people start the paragraph with >
+///
+pub fn is_quote(line: &str) -> bool {
+    line.trim_left().starts_with('>')
+}
+
+///
+/// How many levels the line is quoted with and the text after the markers, "> > text" and ">>text" are both two levels
+///
+pub fn split_quote(line: &str) -> (usize, &str) {
+    let mut depth = 0;
+    let mut rest = line.trim_left();
+    while rest.starts_with('>') {
+        depth += 1;
+        rest = rest[1..].trim_left();
+    }
+    (depth, rest.trim_right())
+}
+
+///
+/// Wraps a quoted line to the width keeping the markers at the start of every line
+///
+pub fn wrap_quote(line: &str, width: usize) -> Vec<String> {
+    let (depth, text) = split_quote(line);
+    let prefix = format!("{} ", ">".repeat(depth));
+    let lines = wrap_words(text, width.saturating_sub(prefix.len()));
+    if lines.is_empty() {
+        return vec![String::from(prefix.trim_right())];
+    }
+    lines.into_iter().map(|line| format!("{}{}", prefix, line)).collect()
+}
+
+fn wrap_words(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(line);
+            line = String::new();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+///
+/// Quotes text for replying, every paragraph gets one more > and is wrapped to 72 characters.
+/// Expects the text with tags formatted but not wrapped, one paragraph on each line.
+/// Code blocks are quoted line by line as they are
+///
+pub struct QuoteFormatter;
+
+impl FormatStr for QuoteFormatter {
+    fn format(&self, s: &str) -> String {
+        let mut quoted: Vec<String> = Vec::new();
+        let mut in_code = false;
+        for line in s.lines() {
+            if in_code {
+                in_code = !is_fence(line);
+                quoted.push(format!("> {}", line).trim_right().to_owned());
+                continue;
+            }
+            let paragraph = line.trim();
+            if paragraph.is_empty() {
+                continue;
+            }
+            if !quoted.is_empty() {
+                quoted.push(String::from(">"));
+            }
+            if is_fence(paragraph) {
+                in_code = true;
+                quoted.push(format!("> {}", paragraph));
+            } else {
+                quoted.extend(wrap_quote(&format!("> {}", paragraph), QUOTE_LINE_LEN));
+            }
+        }
+        let mut text = quoted.join("\n");
+        text.push('\n');
+        text
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn split_quote_test() {
+        assert!(is_quote("  > quoted"));
+        assert!(!is_quote("a > b"));
+        assert_eq!((1, "quoted"), split_quote("> quoted "));
+        assert_eq!((2, "nested"), split_quote("> >nested"));
+        assert_eq!((0, "plain"), split_quote("plain"));
+    }
+
+    #[test]
+    fn wrap_quote_test() {
+        let line = "> Lorem ipsum dolor sit amet, consectetur adipiscing elit. Maecenas maximus eleifend nibh.";
+        let wrapped = wrap_quote(line, 40);
+        assert_eq!(vec!["> Lorem ipsum dolor sit amet,", "> consectetur adipiscing elit. Maecenas", "> maximus eleifend nibh."],
+                   wrapped);
+        assert!(wrapped.iter().all(|line| line.len() <= 40));
+        assert_eq!(vec![">"], wrap_quote(">  ", 40));
+    }
+
+    #[test]
+    fn quote_formatter_test() {
+        let quoted = QuoteFormatter.format("First paragraph.\n> what they said\n\nLast one.");
+        assert_eq!("> First paragraph.\n>\n>> what they said\n>\n> Last one.\n", quoted);
+        let quoted = QuoteFormatter.format("Try\n```\n  if x {\n\n      y()\n```\nthen.");
+        assert_eq!("> Try\n>\n> ```\n>   if x {\n>\n>       y()\n> ```\n>\n> then.\n", quoted);
+    }
+}
@@ -1,83 +1,122 @@
 use regex::Regex;
 use super::formatter::FormatStr;
-
-pub struct TagFormatter;
+use super::code_block::{escape_fences, CODE_FENCE};
+
+///
+/// Turns the html of the comments into plain text, the patterns are compiled once when created
+///
+pub struct TagFormatter {
+    paragraph_opening: Regex,
+    paragraph_closing: Regex,
+    link_opening: Regex,
+    link_closing: Regex,
+    code_opening: Regex,
+    code_closing: Regex,
+    code_block: Regex,
+    any_tag: Regex,
+}
 
 impl FormatStr for TagFormatter {
     fn format(&self, s: &str) -> String {
-        let mut r = self.format_paragraphs(s);
-        // currently no special formatting for links so just format like other tags (remove)
-        r = self.format_code_tags(&r);
-        self.format_tags(&r)
+        if !s.contains("<pre>") {
+            return self.format_text(s);
+        }
+        // code blocks are kept verbatim between fences, the text around them is formatted as usual
+        let mut result = String::new();
+        let mut start = 0;
+        for captures in self.code_block.captures_iter(s) {
+            let block = captures.get(0).unwrap();
+            result.push_str(&self.format_text(&s[start..block.start()]));
+            if !result.is_empty() && !result.ends_with('\n') {
+                result.push('\n');
+            }
+            result.push_str(&format!("{}\n{}\n{}", CODE_FENCE, captures[1].trim_right_matches('\n'), CODE_FENCE));
+            start = block.end();
+            if !s[start..].starts_with('\n') {
+                result.push('\n');
+            }
+        }
+        result.push_str(&self.format_text(&s[start..]));
+        result
     }
 }
 
 impl TagFormatter {
+    pub fn new() -> TagFormatter {
+        TagFormatter {
+            paragraph_opening: Regex::new(r"( *<p> *)").unwrap(),
+            paragraph_closing: Regex::new(r"( *</p> *)").unwrap(),
+            link_opening: Regex::new(r"(<a+.*href=\x22.\x22*\s*?.*?\s*?> *)").unwrap(),
+            link_closing: Regex::new(r"( *</a> *)").unwrap(),
+            code_opening: Regex::new(r"( *<code>)").unwrap(),
+            code_closing: Regex::new(r"(</code> *)").unwrap(),
+            code_block: Regex::new(r"(?s)<pre><code>(.*?)</code></pre>").unwrap(),
+            // will just replace all tags and should be used after the others
+            any_tag: Regex::new(r"(</?\w+((\s+\w+(\s*=\s*(?:\x22.*?\x22|'.*?'|[\^'\x22>\s]+))?)+\s*|\s*)/?>)").unwrap(),
+        }
+    }
+
+    ///
+    /// Formats the text outside of code blocks, fences written by the user are escaped
+    ///
+    fn format_text(&self, s: &str) -> String {
+        let mut r = self.format_italic_quotes(s);
+        r = self.format_paragraphs(&r);
+        // currently no special formatting for links so just format like other tags (remove)
+        r = self.format_code_tags(&r);
+        escape_fences(&self.format_tags(&r))
+    }
+
+    ///
+    /// Paragraphs which are in italics as a whole are quotes by HN convention, they get a > like the other quotes
+    ///
+    pub fn format_italic_quotes(&self, s: &str) -> String {
+        if !s.contains("<i>") {
+            return String::from(s);
+        }
+        let paragraphs: Vec<String> = s.split("<p>")
+            .map(|paragraph| {
+                let content = paragraph.trim().trim_right_matches("</p>").trim_right();
+                let italics = content.matches("<i>").count() == 1 && content.starts_with("<i>") &&
+                              content.ends_with("</i>");
+                if italics {
+                    format!("> {}", &content[3..content.len() - 4].trim())
+                } else {
+                    String::from(paragraph)
+                }
+            })
+            .collect();
+        paragraphs.join("<p>")
+    }
+
     pub fn format_paragraphs(&self, s: &str) -> String {
         // todo split
-        let mut r = self.replace_paragraphs_opening_tags(&s, "\n");
-        r = self.replace_paragraphs_closing_tags(&r, "");
-        r
+        let r = self.paragraph_opening.replace_all(s, "\n");
+        self.paragraph_closing.replace_all(&r, "").into_owned()
     }
 
     pub fn format_links(&self, s: &str) -> String {
-        let mut r = self.replace_link_opening_tags(s, "");
-        r = self.replace_link_closing_tags(&r, "");
-        r
+        let r = self.link_opening.replace_all(s, "");
+        self.link_closing.replace_all(&r, "").into_owned()
     }
 
     pub fn format_tags(&self, s: &str) -> String {
-        self.replace_tags(s, "")
+        self.any_tag.replace_all(s, "").into_owned()
     }
 
     pub fn format_code_tags(&self, s:&str) -> String {
-        let r = self.replace_opening_code_tags(s, "\n");
-        self.replace_closing_code_tags(&r, "\n") // make code blocks stand out
-    }
-
-    fn replace_paragraphs_closing_tags(&self, s: &str, replacement: &str) -> String {
-        let re = Regex::new(r"( *</p> *)").unwrap();
-        re.replace_all(s, replacement).into_owned()
-    }
-
-    fn replace_paragraphs_opening_tags(&self, s: &str, replacement: &str) -> String {
-        let re = Regex::new(r"( *<p> *)").unwrap();
-        re.replace_all(s, replacement).into_owned()
-    }
-
-    fn replace_link_opening_tags(&self, s: &str, replacement: &str) -> String {
-        let re = Regex::new(r"(<a+.*href=\x22.\x22*\s*?.*?\s*?> *)").unwrap();
-        re.replace_all(s, replacement).into_owned()
-    }
-
-    fn replace_link_closing_tags(&self, s: &str, replacement: &str) -> String {
-        let re = Regex::new(r"( *</a> *)").unwrap();
-        re.replace_all(s, replacement).into_owned()
-    }
-
-    fn replace_tags(&self, s: &str, replacement: &str) -> String {
-        // will just replace all tags and should be called after other methods have run before
-        let re = Regex::new(r"(</?\w+((\s+\w+(\s*=\s*(?:\x22.*?\x22|'.*?'|[\^'\x22>\s]+))?)+\s*|\s*)/?>)").unwrap();
-        re.replace_all(s, replacement).into_owned()
-    }
-
-    fn replace_closing_code_tags(&self, s: &str, replacement: &str) -> String {
-        let re = Regex::new(r"(</code> *)").unwrap();
-        re.replace_all(s, replacement).into_owned()
-    }
-
-    fn replace_opening_code_tags(&self, s: &str, replacement: &str) -> String {
-        let re = Regex::new(r"( *<code>)").unwrap();
-        re.replace_all(s, replacement).into_owned()
+        let r = self.code_opening.replace_all(s, "\n");
+        self.code_closing.replace_all(&r, "\n").into_owned() // make code blocks stand out
     }
 }
 #[cfg(test)]
 mod test {
     use super::*;
+    use formatting::code_block::is_fence;
 
     #[test]
     fn test_replace_paragraphs() {
-        let tag_formatter = TagFormatter;
+        let tag_formatter = TagFormatter::new();
         let mut s: String = format!("aaaaaa zzz zzaaa <p> </p>");
         s = tag_formatter.format_paragraphs(&s);
         assert_eq!("aaaaaa zzz zzaaa\n", s);
@@ -89,14 +128,37 @@ mod test {
 
     #[test] 
     fn test_replace_codetags() {
-        let tag_formatter = TagFormatter;
+        let tag_formatter = TagFormatter::new();
         let mut s = format!("<code>System.out.println(\"Hello world\");</code>");
         s = tag_formatter.format_code_tags(&s);
         assert_eq!("\nSystem.out.println(\"Hello world\");\n", s);
     }
+
+    #[test]
+    fn test_code_blocks() {
+        let tag_formatter = TagFormatter::new();
+        assert_eq!("\n```\n  fn id<T>(t: T) -> T {\n      t\n  }\n```\nworks fine for me",
+                   tag_formatter.format("<p><pre><code>  fn id<T>(t: T) -> T {\n      t\n  }\n</code></pre>\nworks <i>fine</i> for me"));
+        assert_eq!("before\n```\n  a\n```\n```\n  b\n```\nafter",
+                   tag_formatter.format("before<pre><code>  a</code></pre><pre><code>  b</code></pre>after"));
+        // markdown fences typed in the comment don't start code blocks
+        let pasted = tag_formatter.format("Like this:<p>```<p>not code<p>```");
+        assert_eq!("Like this:\n\u{200B}```\nnot code\n\u{200B}```", pasted);
+        assert!(!pasted.lines().any(is_fence));
+    }
+
+    #[test]
+    fn test_italic_quotes() {
+        let tag_formatter = TagFormatter::new();
+        assert_eq!("<p>> quoted text<p>reply with <i>emphasis</i>",
+                   tag_formatter.format_italic_quotes("<p><i>quoted text</i></p><p>reply with <i>emphasis</i>"));
+        assert_eq!("> first\nreply", tag_formatter.format("<i>first</i><p>reply"));
+        assert_eq!("<i>a</i> and <i>b</i>", tag_formatter.format_italic_quotes("<i>a</i> and <i>b</i>"));
+    }
+
     #[test]
     fn test_replace_links() {
-        let tag_formatter = TagFormatter;
+        let tag_formatter = TagFormatter::new();
         let mut s: String = format!("this is <a href=\"http\"://www.link.com rel=\"nofollow\"> \
                                      http://www.link.com </a>");
         s = tag_formatter.format_links(&s);
@@ -105,7 +167,7 @@ mod test {
 
     #[test]
     fn test_replace_tags() {
-        let tag_formatter = TagFormatter;
+        let tag_formatter = TagFormatter::new();
         let mut s: String = format!("this is <a href=\"http\"://www.link.com \
                                      rel=\"nofollow\">http://www.link.com</a>");
         s = tag_formatter.format_tags(&s);
@@ -120,7 +182,7 @@ mod test {
         use serde_json;
         use core::models::HnItem;
 
-        let tag_formatter = TagFormatter;
+        let tag_formatter = TagFormatter::new();
 
         let unformatted_item:HnItem =  read_file("res/test/item-with-html.json")
             .and_then(|content| Some(serde_json::from_str(&content).unwrap()))
@@ -0,0 +1,101 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+use regex::Regex;
+use super::formatter::FormatStr;
+
+///
+/// Replaces every match of the pattern, the replacement can refer to groups like $1
+///
+pub struct RegexFormatter {
+    regex: Regex,
+    replacement: String,
+}
+
+impl RegexFormatter {
+    pub fn new(pattern: &str, replacement: &str) -> Result<RegexFormatter, String> {
+        Regex::new(pattern)
+            .map(|regex| {
+                RegexFormatter {
+                    regex: regex,
+                    replacement: String::from(replacement),
+                }
+            })
+            .map_err(|e| format!("invalid regex {}: {}", pattern, e))
+    }
+}
+
+impl FormatStr for RegexFormatter {
+    fn format(&self, s: &str) -> String {
+        self.regex.replace_all(s, self.replacement.as_str()).into_owned()
+    }
+}
+
+///
+/// Pipes the text through a shell command, the text is kept as it was if the command fails.
+/// Every comment starts its own process so a page of comments waits for one per comment,
+/// which adds up for slow starting commands
+///
+pub struct CommandFormatter {
+    command: String,
+}
+
+impl CommandFormatter {
+    pub fn new(command: &str) -> CommandFormatter {
+        CommandFormatter { command: String::from(command) }
+    }
+
+    fn run(&self, s: &str) -> Result<String, String> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| e.to_string())?;
+        // written from another thread so a command which outputs before reading everything can't block
+        let mut stdin = child.stdin.take().unwrap();
+        let input = String::from(s);
+        let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+        let output = child.wait_with_output().map_err(|e| e.to_string())?;
+        if writer.join().is_err() {
+            return Err(String::from("could not write the text"));
+        }
+        if !output.status.success() {
+            return Err(format!("exited with {}", output.status));
+        }
+        String::from_utf8(output.stdout).map_err(|_| String::from("output was not utf-8"))
+    }
+}
+
+impl FormatStr for CommandFormatter {
+    fn format(&self, s: &str) -> String {
+        match self.run(s) {
+            Ok(formatted) => formatted,
+            Err(reason) => {
+                warn!("Format command {} failed, {}", self.command, reason);
+                String::from(s)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn regex_formatter_test() {
+        let formatter = RegexFormatter::new(r"\bteh\b", "the").unwrap();
+        assert_eq!("the cat and the hat", formatter.format("teh cat and the hat"));
+        let formatter = RegexFormatter::new(r"(\w+)@(\w+)", "$1 at $2").unwrap();
+        assert_eq!("me at example", formatter.format("me@example"));
+        assert!(RegexFormatter::new("(", "").is_err());
+    }
+
+    #[test]
+    fn command_formatter_test() {
+        assert_eq!("SHOUTING\n", CommandFormatter::new("tr a-z A-Z").format("shouting\n"));
+        assert_eq!("kept", CommandFormatter::new("exit 3").format("kept"));
+    }
+}
@@ -0,0 +1,26 @@
+use serde_json;
+use core::models::HnItem;
+use super::io_utils::read_file;
+
+const THREAD_FIXTURES: [&'static str; 5] = ["res/test/children-item.json",
+                                            "res/test/item-with-children-1.json",
+                                            "res/test/item-with-children-2.json",
+                                            "res/test/item-with-html.json",
+                                            "res/test/item-with-preformatted-code.json"];
+
+///
+/// Comment fixtures repeated into a thread of about thousand comments
+///
+pub fn large_thread() -> Vec<HnItem> {
+    let comments: Vec<HnItem> = THREAD_FIXTURES.iter()
+        .map(|path| serde_json::from_str(&read_file(path).unwrap()).unwrap())
+        .collect();
+    (0..200).flat_map(|_| comments.clone()).collect()
+}
+
+///
+/// Bytes of html in the thread before decoding
+///
+pub fn thread_bytes(thread: &Vec<HnItem>) -> u64 {
+    thread.iter().map(|item| item.text.as_ref().map(|text| text.len()).unwrap_or(0) as u64).sum()
+}
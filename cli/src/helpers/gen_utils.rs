@@ -26,10 +26,89 @@ pub fn try_to_parse_number(s: Option<&str>) -> Option<usize> {
     }
 }
 
+///
+/// Parses durations like 30m, 24h or 7d into seconds, plain numbers are seconds
+///
+pub fn parse_duration_secs(s: &str) -> Option<i64> {
+    let s = s.trim();
+    let (number, multiplier) = match s.chars().last() {
+        Some('s') => (&s[..s.len() - 1], 1),
+        Some('m') => (&s[..s.len() - 1], 60),
+        Some('h') => (&s[..s.len() - 1], 60 * 60),
+        Some('d') => (&s[..s.len() - 1], 24 * 60 * 60),
+        Some('w') => (&s[..s.len() - 1], 7 * 24 * 60 * 60),
+        _ => (s, 1),
+    };
+    number.parse::<i64>().ok().map(|n| n * multiplier)
+}
+
+///
+/// Formats the age of unix timestamp like HackerNews does, "3 hours ago" becomes "3h ago"
+///
+pub fn format_relative_age(time: f64, now: i64) -> String {
+    let age = now - time as i64;
+    if age < 60 {
+        String::from("just now")
+    } else if age < 60 * 60 {
+        format!("{}m ago", age / 60)
+    } else if age < 24 * 60 * 60 {
+        format!("{}h ago", age / (60 * 60))
+    } else if age < 365 * 24 * 60 * 60 {
+        format!("{}d ago", age / (24 * 60 * 60))
+    } else {
+        format!("{}y ago", age / (365 * 24 * 60 * 60))
+    }
+}
+
+///
+/// Levenshtein distance between the strings counted in chars
+///
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..b.len() + 1).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn edit_distance_test() {
+        assert_eq!(0, edit_distance("open", "open"));
+        assert_eq!(1, edit_distance("coments", "comments"));
+        assert_eq!(2, edit_distance("exapnd", "expand"));
+        assert_eq!(4, edit_distance("", "next"));
+    }
+
+    #[test]
+    fn parse_duration_secs_test() {
+        assert_eq!(Some(24 * 60 * 60), parse_duration_secs("24h"));
+        assert_eq!(Some(30 * 60), parse_duration_secs("30m"));
+        assert_eq!(Some(2 * 7 * 24 * 60 * 60), parse_duration_secs("2w"));
+        assert_eq!(Some(45), parse_duration_secs("45"));
+        assert!(parse_duration_secs("h").is_none());
+        assert!(parse_duration_secs("abc").is_none());
+    }
+
+    #[test]
+    fn format_relative_age_test() {
+        let now = 1175714200;
+        assert_eq!("just now", format_relative_age(now as f64 - 5.0, now));
+        assert_eq!("5m ago", format_relative_age(now as f64 - 300.0, now));
+        assert_eq!("3h ago", format_relative_age(now as f64 - 3.0 * 3600.0, now));
+        assert_eq!("2d ago", format_relative_age(now as f64 - 2.0 * 86400.0, now));
+        assert_eq!("10y ago", format_relative_age(now as f64 - 3650.0 * 86400.0, now));
+    }
+
     #[test]
     fn combine_strings_test() {
         let a = "Abc";
@@ -1,5 +1,10 @@
-use std::fs::File;
+use std::fs::{self, File, OpenOptions};
+use std::io;
 use std::io::prelude::*;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::os::unix::io::AsRawFd;
+use libc;
 
 pub fn read_file(path: &str) -> Option<String>  {
     let mut contents = String::new();
@@ -14,3 +19,70 @@ pub fn read_file(path: &str) -> Option<String>  {
         None
     }
 }
+
+pub fn write_file(path: &Path, contents: &str) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(contents.as_bytes())
+}
+
+///
+/// Writes the contents to a temporary file next to the path and renames it over the path,
+/// so the file has either the old or the new contents even if writing is interrupted
+///
+pub fn write_file_atomic(path: &Path, contents: &str) -> io::Result<()> {
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or(String::new());
+    let tmp = path.with_file_name(format!(".{}.tmp", name));
+    {
+        let mut file = File::create(&tmp)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, path)
+}
+
+pub fn append_to_file(path: &Path, contents: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+    file.write_all(contents.as_bytes())
+}
+
+/// Clipboard tools of macOS, Wayland and X11 with the arguments to read the text from stdin
+const CLIPBOARD_COMMANDS: [(&'static str, &'static [&'static str]); 4] = [("pbcopy", &[]),
+                                                                         ("wl-copy", &[]),
+                                                                         ("xclip", &["-selection", "clipboard"]),
+                                                                         ("xsel", &["--clipboard", "--input"])];
+
+///
+/// Copies the text to the clipboard with the first tool which works, returns the name of it
+///
+pub fn copy_to_clipboard(text: &str) -> Option<&'static str> {
+    for &(tool, args) in CLIPBOARD_COMMANDS.iter() {
+        let child = Command::new(tool)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(_) => continue, // not installed
+        };
+        let written = child.stdin.take().map(|mut stdin| stdin.write_all(text.as_bytes()).is_ok()).unwrap_or(false);
+        match child.wait() {
+            Ok(ref status) if written && status.success() => return Some(tool),
+            _ => (),
+        }
+    }
+    None
+}
+
+///
+/// Points stdout to the file so everything printed goes into it
+///
+pub fn redirect_stdout(path: &Path) -> io::Result<()> {
+    let file = File::create(path)?;
+    io::stdout().flush()?;
+    if unsafe { libc::dup2(file.as_raw_fd(), libc::STDOUT_FILENO) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
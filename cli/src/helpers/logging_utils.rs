@@ -49,4 +49,16 @@ pub fn log_loaded_top_stories(length: usize) {
 
 pub fn log_response_status(url: &String, status: &String) {
     info!("Request to {} finished with status {}", url, status);
+}
+
+pub fn log_live_updates(updated: usize, invalidated: usize) {
+    info!("Received {} updated items of which {} were cached", updated, invalidated);
+}
+
+pub fn log_checked_watches(watches: usize, notifications: usize) {
+    info!("Checked {} watches with {} new items", watches, notifications);
+}
+
+pub fn log_alert_pass(checkpoint: Option<i32>, matches: usize) {
+    info!("Alert pass finished at item {:?} with {} matches", checkpoint, matches);
 }
\ No newline at end of file
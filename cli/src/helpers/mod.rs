@@ -1,4 +1,8 @@
 pub mod io_utils;
 pub mod gen_utils;
 pub mod logging_utils;
-pub mod path_utils;
\ No newline at end of file
+pub mod path_utils;
+#[cfg(all(feature = "nightly", test))]
+pub mod bench_utils;
+#[cfg(test)]
+pub mod test_utils;
@@ -1,6 +1,12 @@
 use core::models::HnItem;
+use regex::Regex;
 use url::{Url};
-use std::path::Path;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DATA_DIR_ENV: &'static str = "HNCLI_HOME";
+const DATA_DIR_NAME: &'static str = ".hncli";
 
 pub fn get_host_from_link(path: &str) -> Option<String> {
     let result = Url::parse(path);
@@ -15,6 +21,13 @@ pub fn get_host_from_link(path: &str) -> Option<String> {
     }
 }
 
+///
+/// Whether the host is the domain or one of its subdomains, story alerts and kill rules match domains with it
+///
+pub fn host_matches_domain(host: &str, domain: &str) -> bool {
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
 pub fn get_filesystem_safe_url_as_string(path: &str) -> Option<String> {
     let url_result = Url::parse(path);
     if url_result.is_ok() {
@@ -39,15 +52,67 @@ pub fn generate_filename_for_hnitem(item: &HnItem) -> String {
     }
 }
 
+///
+/// Directory for config and persisted state, $HNCLI_HOME or ~/.hncli
+///
+pub fn get_data_dir() -> PathBuf {
+    match env::var(DATA_DIR_ENV) {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => {
+            let home = env::var("HOME").map(|home| PathBuf::from(home)).unwrap_or(PathBuf::from("."));
+            home.join(DATA_DIR_NAME)
+        }
+    }
+}
+
+///
+/// Path to a file in the data directory, creates the directory if it's missing
+///
+pub fn get_data_file_path(filename: &str) -> PathBuf {
+    let dir = get_data_dir();
+    if fs::create_dir_all(&dir).is_err() {
+        warn!("Could not create data directory {}", dir.to_string_lossy());
+    }
+    dir.join(filename)
+}
+
+///
+/// Absolute paths are used as is and relative paths are placed under the data directory
+///
+pub fn resolve_data_path(path: &str) -> PathBuf {
+    let path_buf = PathBuf::from(path);
+    if path_buf.is_absolute() {
+        path_buf
+    } else {
+        get_data_file_path(path)
+    }
+}
+
 fn combine_strings(strings: Vec<&str>) -> String {
     let combine = strings.join("");
     combine
 }
 
+///
+/// Targets of the links in the html of an item text, in the order they appear
+///
+pub fn find_links(html: &str) -> Vec<String> {
+    let re = Regex::new(r#"<a\s[^>]*href="([^"]+)""#).unwrap();
+    re.captures_iter(html).map(|captures| String::from(&captures[1])).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn find_links_test() {
+        let html = "See <a href=\"https://example.com/a\" rel=\"nofollow\">https://example.com/a</a> \
+                    and <a href=\"http://b.org\">b</a><p>no href=\"c\"";
+        assert_eq!(vec!["https://example.com/a", "http://b.org"], find_links(html));
+        assert!(find_links("plain text").is_empty());
+    }
+
     #[test]
     fn get_host_from_link_test() {
         let mut s = "http://www.google.fi";
@@ -58,6 +123,21 @@ mod tests {
         os = get_host_from_link(s);
         assert!(os.is_none());
     }
+
+    #[test]
+    fn host_matches_domain_test() {
+        assert!(host_matches_domain("getdropbox.com", "getdropbox.com"));
+        assert!(host_matches_domain("www.getdropbox.com", "getdropbox.com"));
+        assert!(!host_matches_domain("notgetdropbox.com", "getdropbox.com"));
+    }
+    #[test]
+    fn resolve_data_path_test() {
+        let absolute = resolve_data_path("/tmp/digest.txt");
+        assert_eq!(Path::new("/tmp/digest.txt"), absolute.as_path());
+        let relative = resolve_data_path("digest.txt");
+        assert_eq!(get_data_dir().join("digest.txt"), relative);
+    }
+
     #[test]
     fn get_filesystem_safe_url_as_string_test() {
         let s = "http://www.google.fi/search/";
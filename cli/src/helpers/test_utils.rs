@@ -0,0 +1,10 @@
+use serde_json;
+use core::models::HnItem;
+use super::io_utils::read_file;
+
+///
+/// Item of a json fixture under res/test
+///
+pub fn read_item(path: &str) -> HnItem {
+    serde_json::from_str(&read_file(path).unwrap()).unwrap()
+}
@@ -1,4 +1,5 @@
 // Unused mut warning is false in this context
+#![cfg_attr(feature = "nightly", feature(test))]
 #[macro_use] extern crate log;
 #[macro_use] extern crate fern;
 #[macro_use] extern crate serde_derive;
@@ -15,67 +16,259 @@ extern crate native_tls;
 extern crate regex;
 extern crate url;
 extern crate chrono;
+extern crate termion;
+extern crate libc;
+#[cfg(all(feature = "nightly", test))] extern crate test;
 
 mod ui;
 mod decoding;
 mod formatting;
 mod core;
 mod helpers;
+mod export;
+mod server;
 
 use core::app::*;
 use core::models::*;
 use core::client;
 use ui::cli;
 use helpers::logging_utils;
+use helpers::gen_utils;
 
 use std::cmp::min;
-use tokio_core::reactor::Core;
-use std::io::{self, BufRead};
+use std::collections::HashMap;
+use std::env;
+use std::time::{Duration, Instant};
+use tokio_core::reactor::{Core, Interval};
+use std::io::{self, BufRead, Read};
+use std::fs::File;
 use std::thread::spawn;
 use std::process;
 use futures::{Stream, Sink, Future};
+use futures::stream;
 use futures::sync::mpsc;
 use ui::backend::UiCommand;
+use ui::args::{AppArgs, AppMode, ServeProtocol};
+use core::watch::{self, WatchList, WatchTarget};
+use core::notifier::{Notifiers, Notify};
+use core::alerts::{self, AlertRules};
+use core::checkpoint::Checkpoint;
+use core::item_walker::WalkSource;
+use core::event_stream::{self, EventWriter, StreamError, StreamFormat};
+use core::killfile::{KillAction, Killfile, KillRule};
+use core::config::AppConfig;
+use core::feed_view::FeedSort;
+use core::search::SearchQuery;
+use core::text_index::{IndexQuery, TextIndex};
+use core::thread_search::ThreadSearch;
+use ui::history::History;
+use ui::completion::CompletionContext;
+use ui::line_editor::{EditMode, LineEditor, PromptTerminal};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc as std_mpsc;
+use helpers::path_utils::resolve_data_path;
+use helpers::io_utils;
+use ui::script::{OnError, Script, ScriptStep, ScriptVars, MAX_SCRIPT_DEPTH};
+use std::path::Path;
+use export::feed_writer::{self, FeedEntry, FeedFormat, FeedInfo};
+use export::{mailbox, message, site_writer, table_writer};
+use export::table_writer::TableFormat;
+use server::nntp_server::{self, GroupSource, NewsGroup};
+use server::http_api::{self, ApiSource};
+use server::smallnet_server;
+use export::gophermap_writer::GopherHost;
+use formatting::formatter::{FormatStr, Formatters};
+use formatting::quote_formatter::QuoteFormatter;
+use std::net::TcpListener;
+
+const DEFAULT_PUBLISH_LIMIT: usize = 30;
+
+enum AppEvent {
+    Input(Result<String, io::Error>),
+    InputClosed,
+    Tick,
+}
 
 fn main() {
 
+    let app_args = AppArgs::parse(env::args());
     let mut app_domain = AppDomain::new();
     let mut app_cache: AppCache = AppCache::new();
     let mut app_state_machine: AppStateMachine = AppStateMachine::new();
     let mut main_core = Core::new().expect("Failed to create core");
 
     info!("Application started");
-    app_cache.retrieved_top_stories =
-        client::get_top_story_ids(&mut app_domain, &mut app_state_machine).ok();
+    match app_args.output {
+        Some(ref path) => {
+            if let Err(e) = io_utils::redirect_stdout(Path::new(path)) {
+                eprintln!("Could not write output to {}: {}", path, e);
+                process::exit(1);
+            }
+        }
+        None => (),
+    }
+    app_cache.watch_list = WatchList::load();
+    app_cache.killfile = Killfile::load();
+    if app_args.mode == AppMode::WatchDaemon {
+        run_watch_daemon(&mut app_domain, &mut app_cache, &mut app_state_machine);
+        return;
+    }
+    if app_args.mode == AppMode::Alerts {
+        run_alerts(&app_args, &mut app_domain, &mut app_state_machine);
+        return;
+    }
+    if app_args.mode == AppMode::Nntp {
+        run_nntp(&app_args, &mut app_domain, &mut app_cache, &mut app_state_machine);
+        return;
+    }
+    if app_args.mode == AppMode::Serve {
+        run_api_server(&app_args, &mut app_domain, &mut app_cache, &mut app_state_machine);
+        return;
+    }
+    if app_args.mode == AppMode::Digest {
+        run_digest(&app_args, &mut app_domain, &mut app_cache, &mut app_state_machine);
+        return;
+    }
+    if app_args.mode == AppMode::Stream {
+        run_stream(&app_args, &mut app_domain, &mut app_state_machine);
+        return;
+    }
+    if app_args.mode == AppMode::FeedExport {
+        run_feed_export(&app_args, &mut app_domain, &mut app_cache, &mut app_state_machine);
+        return;
+    }
+    app_state_machine.enable_live_updates(app_args.live_updates);
+    app_state_machine.raw_comments = app_args.raw_comments;
+    app_cache.text_index = TextIndex::load();
+    app_cache.text_index.set_max_docs(app_domain.config.index_max_docs);
+    app_cache.retrieved_stories =
+        client::get_feed_story_ids(app_state_machine.current_feed, &mut app_domain, &mut app_state_machine).ok();
+    if app_args.mode == AppMode::Script {
+        run_script_mode(&app_args, &mut app_domain, &mut app_cache, &mut app_state_machine);
+        return;
+    }
     output_stories(&mut app_domain, &mut app_cache, &mut app_state_machine);
     let (sender, receiver) = mpsc::channel(1);
+    let (ready_sender, ready_receiver) = std_mpsc::channel::<()>();
+    let history = Arc::new(Mutex::new(History::load_from(&resolve_data_path(&app_domain.config.history_file),
+                                                         app_domain.config.history_size)));
+    let completion = Arc::new(Mutex::new(CompletionContext::new()));
+    update_completion(&completion, &app_cache, &app_state_machine);
+    let edit_mode = EditMode::parse(&app_domain.config.edit_mode).unwrap_or(EditMode::Emacs);
+    let input_history = history.clone();
+    let input_completion = completion.clone();
+    let prompt_terminal = PromptTerminal::new();
+    let input_terminal = prompt_terminal.clone();
 
     spawn(move || {
-        let stdin = io::stdin();
-        for line in stdin.lock().lines() {
-            let sender = sender.clone();
-            sender.send(line).wait().unwrap();
+        if termion::is_tty(&io::stdin()) {
+            let mut editor = LineEditor::new(edit_mode, input_history, input_completion, input_terminal);
+            loop {
+                let line = match editor.read_line("> ") {
+                    Ok(Some(line)) => Ok(line),
+                    Ok(None) => break,
+                    Err(e) => Err(e),
+                };
+                let failed = line.is_err();
+                sender.clone().send(line).wait().unwrap();
+                // output is printed outside raw mode so the next line is read only after the command is handled
+                if failed || ready_receiver.recv().is_err() {
+                    break;
+                }
+            }
+        } else {
+            let stdin = io::stdin();
+            for line in stdin.lock().lines() {
+                let line = match line {
+                    Ok(line) => {
+                        let mut history = input_history.lock().unwrap();
+                        match history.expand(&line) {
+                            Ok(expanded) => {
+                                history.add(&expanded);
+                                Ok(expanded)
+                            }
+                            Err(e) => {
+                                println!("{}", e);
+                                continue;
+                            }
+                        }
+                    }
+                    Err(e) => Err(e),
+                };
+                let sender = sender.clone();
+                sender.send(line).wait().unwrap();
+            }
         }
     });
-    let listener = receiver.for_each(|verb| {
-        let option_cmd = UiCommand::parse(verb);
-        match option_cmd {
-            Some(cmd) => {
-                logging_utils::log_cmd(&cmd);
-                gui_listener(cmd, &mut app_domain, &mut app_cache, &mut app_state_machine)
-            }
-            None => {
-                println!("Could not parse command");
-                Err(())
+    let inputs = receiver
+        .map(|line| AppEvent::Input(line))
+        .chain(stream::once(Ok(AppEvent::InputClosed)));
+    let ticks = Interval::new(Duration::from_secs(app_args.live_interval_secs), &main_core.handle())
+        .expect("Failed to create interval for live updates")
+        .map(|_| AppEvent::Tick)
+        .map_err(|_| ());
+    let listener = inputs.select(ticks).for_each(|event| {
+        match event {
+            AppEvent::Input(verb) => {
+                let option_cmd = UiCommand::parse(verb);
+                let result = match option_cmd {
+                    Some(ref cmd) if !cmd.valid => {
+                        match cmd.error {
+                            Some(ref error) => cli::print_command_error(error),
+                            None => (), // empty line
+                        }
+                        Ok(())
+                    }
+                    Some(ref cmd) if cmd.command.as_ref().map(|verb| verb == "history").unwrap_or(false) => {
+                        cli::print_history(history.lock().unwrap().entries());
+                        Ok(())
+                    }
+                    Some(cmd) => {
+                        logging_utils::log_cmd(&cmd);
+                        // the failure was printed, the prompt comes back for the next command
+                        let _ = gui_listener(cmd, &app_args, &mut app_domain, &mut app_cache, &mut app_state_machine);
+                        Ok(())
+                    }
+                    None => {
+                        println!("Could not parse command");
+                        Err(())
+                    }
+                };
+                update_completion(&completion, &app_cache, &app_state_machine);
+                let _ = ready_sender.send(());
+                result
+            }
+            AppEvent::Tick => {
+                // a line can be in the middle of being typed, the prompt is drawn again after the output
+                if app_state_machine.live_updates {
+                    prompt_terminal.interrupt(|| {
+                        handle_live_updates(&mut app_domain, &mut app_cache, &mut app_state_machine)
+                    });
+                }
+                let watch_interval = Duration::from_secs(app_domain.config.watch_interval_secs);
+                if !app_cache.watch_list.is_empty() && app_state_machine.last_watch_check.elapsed() >= watch_interval {
+                    prompt_terminal.interrupt(|| {
+                        handle_check_watches(&mut app_domain, &mut app_cache, &mut app_state_machine)
+                    });
+                }
+                save_text_index(&mut app_cache);
+                Ok(())
+            }
+            AppEvent::InputClosed => {
+                save_text_index(&mut app_cache);
+                Err(()) // stdin closed so there's nothing to wait for
             }
         }
-
     });
 
     let result = main_core.run(listener);
 }
 
+///
+/// Runs the command, the handlers print why a command failed and scripts use the result as the status
+///
 fn gui_listener(cmd: UiCommand,
+                app_args: &AppArgs,
                 app_domain: &mut AppDomain,
                 app_cache: &mut AppCache,
                 app_state_machine: &mut AppStateMachine)
@@ -83,7 +276,7 @@ fn gui_listener(cmd: UiCommand,
 
     if cmd.command.is_some() {
 
-        let verb: String = cmd.command.unwrap();
+        let verb: String = cmd.command.clone().unwrap();
         let mut numb: usize = 0;
         let mut has_numb = false;
         if cmd.number.is_some() {
@@ -93,94 +286,302 @@ fn gui_listener(cmd: UiCommand,
 
         if verb == "next" {
             if app_state_machine.viewing_stories() {
-                handle_next_stories(app_domain, app_cache, app_state_machine);
+                handle_next_stories(app_domain, app_cache, app_state_machine)
             } else if app_state_machine.viewing_comments() {
-                handle_next_comments(app_domain, app_cache, app_state_machine);
+                handle_next_comments(app_domain, app_cache, app_state_machine)
             } else {
                 cli::print_invalid_state();
                 logging_utils::log_invalid_state();
+                Err(())
             }
         } else if verb == "back" {
             if app_state_machine.viewing_stories() {
-                handle_previous_stories(app_domain, app_cache, app_state_machine);
+                handle_previous_stories(app_domain, app_cache, app_state_machine)
             } else if app_state_machine.viewing_comments() {
-                handle_previous_comments(app_domain, app_cache, app_state_machine);
+                handle_previous_comments(app_domain, app_cache, app_state_machine)
             } else {
                 cli::print_invalid_state();
                 logging_utils::log_invalid_state();
+                Err(())
             }
         } else if verb == "top" {
             output_stories(app_domain, app_cache, app_state_machine);
             app_state_machine.register_viewing_stories();
+            Ok(())
+        } else if verb == "feed" {
+            match cmd.extra_args.as_ref().and_then(|args| args.first()).and_then(|name| HnFeed::parse(name)) {
+                Some(feed) => handle_switch_feed(feed, app_domain, app_cache, app_state_machine),
+                None => {
+                    cli::print_feeds(app_state_machine.current_feed);
+                    Ok(())
+                }
+            }
+        } else if verb == "min-score" || verb == "min-comments" || verb == "max-age" || verb == "sort" {
+            let value = cmd.number.map(|n| n.to_string())
+                .or(cmd.extra_args.as_ref().and_then(|args| args.first().cloned()));
+            handle_feed_filter(&verb, value, app_domain, app_cache, app_state_machine)
+        } else if verb == "search" {
+            let mut words: Vec<String> = cmd.extra_args.clone().unwrap_or(Vec::new());
+            if cmd.has_flag("by-date") {
+                words.push(String::from("sort:date"));
+            }
+            handle_search(&words, app_domain, app_cache, app_state_machine)
+        } else if verb == "find" {
+            let words: Vec<String> = cmd.extra_args.unwrap_or(Vec::new());
+            handle_find(&words.join(" "), app_domain, app_cache, app_state_machine)
+        } else if verb == "/" && app_state_machine.viewing_comments() {
+            let words: Vec<String> = cmd.extra_args.unwrap_or(Vec::new());
+            handle_thread_search(&words.join(" "), app_domain, app_cache, app_state_machine)
+        } else if (verb == "n" || verb == "N") && app_state_machine.viewing_comments() {
+            handle_thread_search_step(verb == "n", app_domain, app_cache, app_state_machine)
         } else if verb == "exit" {
+            save_text_index(app_cache);
             logging_utils::log_exit();
             process::exit(0);
         } else if verb == "comments" && has_numb {
             // needs cache and state as they're retrieved from remote
-            safe_load_story(numb, app_domain, app_cache, app_state_machine).and_then(|item| { 
-                handle_comments(item, app_domain, app_cache, app_state_machine);
-                app_state_machine.register_viewing_comments(); // viewing comments 
-                Some(0)
-            });
+            let item = safe_load_story(numb, app_domain, app_cache, app_state_machine).ok_or(())?;
+            handle_comments(item, app_domain, app_cache, app_state_machine)?;
+            app_state_machine.register_viewing_comments(); // viewing comments 
+            Ok(())
         } else if verb == "expand" && has_numb {
-            safe_load_comment(numb, app_cache).and_then(|item| {
-                app_state_machine.comments_page_index = 0; // reset comments index
-                handle_comments(item, app_domain, app_cache, app_state_machine);
-                app_state_machine.register_expanded_comment(); // expanded comments
-                Some(0)
-            });
+            let item = safe_load_comment(numb, app_cache).ok_or(())?;
+            app_state_machine.comments_page_index = 0; // reset comments index
+            handle_comments(item, app_domain, app_cache, app_state_machine)?;
+            app_state_machine.register_expanded_comment(); // expanded comments
+            Ok(())
         } else if verb == "load" && has_numb {
             cli::print_warning_for_downloading_page();
-            handle_download_link(numb, app_domain, app_cache, app_state_machine);
+            let mut result = Ok(());
+            for n in cmd.numbers.iter() {
+                result = handle_download_link(n - 1, app_domain, app_cache, app_state_machine).and(result);
+            }
+            result
         } else if verb == "open" && has_numb {
-            handle_open_link(numb, app_domain, app_cache, app_state_machine);
+            let mut result = Ok(());
+            for n in cmd.numbers.iter() {
+                result = handle_open_link(n - 1, app_domain, app_cache, app_state_machine).and(result);
+            }
             app_state_machine.register_opened_story();
+            result
+        }
+        else if verb == "live" {
+            let enabled = match cmd.extra_args.as_ref().and_then(|args| args.first()) {
+                Some(arg) => arg == "on",
+                None => !app_state_machine.live_updates, // toggle without arguments
+            };
+            app_state_machine.enable_live_updates(enabled);
+            cli::print_live_updates_toggled(enabled, app_args.live_interval_secs);
+            Ok(())
+        }
+        else if verb == "quote" && has_numb {
+            handle_quote(numb, cmd.has_flag("print"), app_domain, app_cache, app_state_machine)
+        }
+        else if verb == "raw" {
+            // the registry only lets on and off through
+            let enabled = match cmd.extra_args.as_ref().and_then(|args| args.first()) {
+                Some(arg) => arg == "on",
+                None => !app_state_machine.raw_comments,
+            };
+            app_state_machine.raw_comments = enabled;
+            cli::print_raw_comments_toggled(enabled);
+            Ok(())
+        }
+        else if verb == "watch" && has_numb {
+            let mut result = Ok(());
+            for n in cmd.numbers.iter() {
+                result = handle_watch(n - 1, true, &cmd.extra_args, app_domain, app_cache, app_state_machine).and(result);
+            }
+            result
+        }
+        else if verb == "watch" {
+            handle_watch(numb, has_numb, &cmd.extra_args, app_domain, app_cache, app_state_machine)
+        }
+        else if verb == "watches" {
+            cli::print_watches(&app_cache.watch_list);
+            Ok(())
+        }
+        else if verb == "unwatch" && has_numb {
+            // highest first so the earlier indexes stay in place
+            let mut numbers = cmd.numbers.clone();
+            numbers.sort();
+            numbers.dedup();
+            let mut result = Ok(());
+            for n in numbers.iter().rev() {
+                match app_cache.watch_list.remove(n - 1) {
+                    Some(removed) => {
+                        save_watch_list(&app_cache.watch_list);
+                        cli::print_watch_removed(&removed.describe());
+                    }
+                    None => {
+                        cli::print_invalid_numb();
+                        result = Err(());
+                    }
+                }
+            }
+            result
+        }
+        else if verb == "filters" {
+            handle_filters(&cmd.extra_args, app_cache)
+        }
+        else if verb == "export-mail" && has_numb {
+            match cmd.extra_args.as_ref().and_then(|args| args.first()) {
+                Some(path) => handle_export_mail(numb, path, app_domain, app_cache, app_state_machine),
+                None => {
+                    cli::print_could_not_export_mail("give the mbox file or Maildir to export to");
+                    Err(())
+                }
+            }
+        }
+        else if verb == "publish" {
+            handle_publish(&cmd, app_domain, app_cache, app_state_machine)
+        }
+        else if verb == "export-list" {
+            handle_export_list(&cmd, app_domain, app_cache, app_state_machine)
+        }
+        else if verb == "source" {
+            match cmd.extra_args.as_ref().and_then(|args| args.first()) {
+                Some(path) => {
+                    match run_script(path, OnError::Stop, app_args, app_domain, app_cache, app_state_machine) {
+                        Ok(0) => Ok(()),
+                        Ok(failed) => {
+                            cli::print_script_commands_failed(path, failed);
+                            Err(())
+                        }
+                        Err(e) => {
+                            cli::print_script_failed(&e);
+                            Err(())
+                        }
+                    }
+                }
+                None => {
+                    cli::print_invalid_command();
+                    Err(())
+                }
+            }
         }
         else if verb == "help" {
-            cli::print_help();
+            cli::print_help(cmd.extra_args.as_ref().and_then(|args| args.first()).map(|name| name.as_str()));
+            Ok(())
         }
         else {
             cli::print_invalid_command();
+            Err(())
         }
+    } else {
+        Ok(())
     }
-    Ok(())
 }
 
 fn handle_next_comments(app_domain: &mut AppDomain,
                         app_cache: &mut AppCache,
-                        app_state_machine: &mut AppStateMachine) {
+                        app_state_machine: &mut AppStateMachine)
+                        -> Result<(), ()> {
     let max_comments = app_cache.comments_len();
     if max_comments.is_some() &&
         max_comments.map(|val| under_index_and_over10(val, app_state_machine.listing_page_index)).unwrap() {
         app_state_machine.comments_page_index += 1;
         output_comments(app_domain, app_cache, app_state_machine);
+        Ok(())
     } else {
         cli::print_tried_to_navigate_over_index();
+        Err(())
     }
 }
 
 fn handle_previous_comments(app_domain: &mut AppDomain,
                             app_cache: &mut AppCache,
-                            app_state_machine: &mut AppStateMachine) {
+                            app_state_machine: &mut AppStateMachine)
+                            -> Result<(), ()> {
     if app_state_machine.comments_page_index > 0 {
         app_state_machine.comments_page_index -= 1;
         output_comments(app_domain, app_cache, app_state_machine);
+        Ok(())
     } else {
         cli::print_tried_to_navigate_over_index();
+        Err(())
     }
 }
 
 
+///
+/// Searches the loaded comments and jumps to the first hit from the current page onwards
+///
+fn handle_thread_search(pattern: &str,
+                        app_domain: &mut AppDomain,
+                        app_cache: &mut AppCache,
+                        app_state_machine: &mut AppStateMachine)
+                        -> Result<(), ()> {
+    let mut search = match ThreadSearch::new(pattern) {
+        Ok(search) => search,
+        Err(e) => {
+            cli::print_invalid_search(&e);
+            return Err(());
+        }
+    };
+    match app_cache.last_retrieved_comments {
+        Some(ref comments) => {
+            search.run(comments, &app_domain.formatters, &app_cache.killfile);
+        }
+        None => (),
+    }
+    let hit = search.jump_from(app_state_machine.comments_page_index * 10);
+    app_cache.thread_search = Some(search);
+    show_thread_search_hit(hit, app_domain, app_cache, app_state_machine);
+    Ok(())
+}
+
+fn handle_thread_search_step(forward: bool,
+                             app_domain: &mut AppDomain,
+                             app_cache: &mut AppCache,
+                             app_state_machine: &mut AppStateMachine)
+                             -> Result<(), ()> {
+    let hit = match app_cache.thread_search {
+        Some(ref mut search) => if forward { search.next() } else { search.previous() },
+        None => {
+            cli::print_no_thread_search();
+            return Err(());
+        }
+    };
+    show_thread_search_hit(hit, app_domain, app_cache, app_state_machine);
+    Ok(())
+}
+
+///
+/// Moves to the page of the hit, hits can be on any page of the loaded comments
+///
+fn show_thread_search_hit(hit: Option<usize>,
+                          app_domain: &mut AppDomain,
+                          app_cache: &mut AppCache,
+                          app_state_machine: &mut AppStateMachine) {
+    match hit {
+        Some(index) => {
+            app_state_machine.comments_page_index = index / 10;
+            output_comments(app_domain, app_cache, app_state_machine);
+            match app_cache.thread_search {
+                Some(ref search) => {
+                    cli::print_thread_search_position(search.current_position().unwrap_or(0), search.hit_count(), index + 1)
+                }
+                None => (),
+            }
+        }
+        None => cli::print_no_thread_search_hits(),
+    }
+}
+
 fn handle_next_stories(app_domain: &mut AppDomain,
                        app_cache: &mut AppCache,
-                       app_state_machine: &mut AppStateMachine) {
+                       app_state_machine: &mut AppStateMachine)
+                       -> Result<(), ()> {
     let max_stories = app_cache.stories_len();
     if max_stories.is_some() && 
         max_stories.map(|val| under_index_and_over10(val, app_state_machine.listing_page_index)).unwrap() {
         app_state_machine.listing_page_index += 1;
         print_and_log_stories(app_domain, app_cache, app_state_machine);
+        Ok(())
     } else {
         cli::print_tried_to_navigate_over_index();
+        Err(())
     }
 }
 
@@ -190,22 +591,1157 @@ fn under_index_and_over10(val: usize, index:usize) -> bool {
 
 fn handle_previous_stories(app_domain: &mut AppDomain,
                            app_cache: &mut AppCache,
-                           app_state_machine: &mut AppStateMachine) {
+                           app_state_machine: &mut AppStateMachine)
+                           -> Result<(), ()> {
     if app_state_machine.listing_page_index > 0 {
         app_state_machine.listing_page_index -= 1;
         print_and_log_stories(app_domain, app_cache, app_state_machine);
+        Ok(())
     } else {
         cli::print_tried_to_navigate_over_index();
+        Err(())
     }
 }
 
 fn handle_comments(item: HnItem,
                    app_domain: &mut AppDomain,
                    app_cache: &mut AppCache,
-                   app_state_machine: &mut AppStateMachine) {
+                   app_state_machine: &mut AppStateMachine)
+                   -> Result<(), ()> {
     // todo This overrides the cached comments even if they were spam, probably fixed once the back traverse for comments is implemented
-    retrieve_comments_for_item(item, app_domain, app_cache, app_state_machine);
+    if !retrieve_comments_for_item(item.clone(), app_domain, app_cache, app_state_machine) {
+        cli::could_not_get_any_commments_for_item(&item);
+        return Err(());
+    }
     output_comments(app_domain, app_cache, app_state_machine);
+    Ok(())
+}
+
+///
+/// Polls the updates endpoint, invalidates the changed items from cache and refreshes the view if it had any of them
+///
+fn handle_live_updates(app_domain: &mut AppDomain,
+                       app_cache: &mut AppCache,
+                       app_state_machine: &mut AppStateMachine) {
+    let updates = match client::get_updates(app_domain, app_state_machine) {
+        Ok(updates) => updates,
+        Err(e) => {
+            warn!("Could not retrieve updates {}", e);
+            return;
+        }
+    };
+    let invalidated = app_cache.invalidate_items(&updates.items);
+    logging_utils::log_live_updates(updates.items.len(), invalidated);
+    if app_state_machine.viewing_comments() {
+        let visible = visible_comment_ids(app_cache, app_state_machine);
+        let changed = updates.items.iter().filter(|id| visible.contains(id)).count();
+        if changed > 0 {
+            refresh_comments(app_domain, app_cache, app_state_machine);
+            cli::print_refreshed_view(changed);
+        }
+    } else if app_state_machine.viewing_stories() {
+        let visible = visible_story_ids(app_cache, app_state_machine);
+        let changed = updates.items.iter().filter(|id| visible.contains(id)).count();
+        if changed > 0 {
+            output_stories(app_domain, app_cache, app_state_machine);
+            cli::print_refreshed_view(changed);
+        }
+    }
+}
+
+///
+/// Runs watch checks on a timer until the process is killed
+///
+fn run_watch_daemon(app_domain: &mut AppDomain,
+                    app_cache: &mut AppCache,
+                    app_state_machine: &mut AppStateMachine) {
+    let mut daemon_core = Core::new().expect("Failed to create core");
+    let interval_secs = app_domain.config.watch_interval_secs;
+    cli::print_watch_daemon_started(app_cache.watch_list.watches.len(), interval_secs);
+    handle_check_watches(app_domain, app_cache, app_state_machine);
+    let checks = Interval::new(Duration::from_secs(interval_secs), &daemon_core.handle())
+        .expect("Failed to create interval for watches")
+        .for_each(|_| {
+            app_cache.watch_list = WatchList::load(); // pick up watches added from interactive sessions
+            handle_check_watches(app_domain, app_cache, app_state_machine);
+            Ok(())
+        });
+    if daemon_core.run(checks).is_err() {
+        warn!("Watch daemon stopped");
+    }
+}
+
+///
+/// Evaluates alert rules against new items, once or on a timer until the process is killed
+///
+fn run_alerts(app_args: &AppArgs, app_domain: &mut AppDomain, app_state_machine: &mut AppStateMachine) {
+    let rules = match AlertRules::load(&resolve_data_path(&app_domain.config.alerts_rules_file)) {
+        Ok(ref rules) if rules.is_empty() => {
+            cli::print_invalid_alert_rules("no rules defined");
+            process::exit(1);
+        }
+        Ok(rules) => rules,
+        Err(reason) => {
+            cli::print_invalid_alert_rules(&reason);
+            process::exit(1);
+        }
+    };
+    let source = match WalkSource::parse(&app_domain.config.alerts_source) {
+        Some(source) => source,
+        None => {
+            cli::print_invalid_alert_rules("alerts_source should be maxitem or newstories");
+            process::exit(1);
+        }
+    };
+    let checkpoint = Checkpoint::new(&resolve_data_path(&app_domain.config.alerts_checkpoint_file));
+    let log_path = resolve_data_path(&app_domain.config.alerts_log_file);
+    let interval_secs = app_domain.config.alerts_interval_secs;
+    cli::print_alerts_started(&app_domain.config.alerts_source, interval_secs, &log_path.to_string_lossy());
+    let mut run_pass = |app_domain: &mut AppDomain, app_state_machine: &mut AppStateMachine| {
+        let backfill = app_domain.config.alerts_backfill;
+        match alerts::run_alert_pass(&rules, source, backfill, &checkpoint, &log_path, app_domain, app_state_machine) {
+            Ok(matches) => {
+                logging_utils::log_alert_pass(checkpoint.load(), matches.len());
+                for alert in matches.iter() {
+                    cli::print_alert_match(alert);
+                }
+            }
+            Err(e) => warn!("Alert pass stopped, continuing from checkpoint on next pass {}", e),
+        }
+    };
+    run_pass(app_domain, app_state_machine);
+    if app_args.run_once {
+        return;
+    }
+    let mut alerts_core = Core::new().expect("Failed to create core");
+    let passes = Interval::new(Duration::from_secs(interval_secs), &alerts_core.handle())
+        .expect("Failed to create interval for alerts")
+        .for_each(|_| {
+            run_pass(app_domain, app_state_machine);
+            Ok(())
+        });
+    if alerts_core.run(passes).is_err() {
+        warn!("Alerts stopped");
+    }
+}
+
+///
+/// Prints new stories and comments as they appear, following maxitem or newstories from the checkpoint
+///
+fn run_stream(app_args: &AppArgs, app_domain: &mut AppDomain, app_state_machine: &mut AppStateMachine) {
+    let source_name = app_args.stream_source.clone().unwrap_or(String::from("new"));
+    let source = match source_name.as_str() {
+        "new" => WalkSource::MaxItem,
+        "stories" => WalkSource::NewStories,
+        _ => {
+            cli::print_invalid_stream_source(&source_name);
+            process::exit(1);
+        }
+    };
+    let checkpoint = Checkpoint::new(&resolve_data_path(&app_domain.config.stream_checkpoint_file));
+    let mut last = app_args.stream_from.or(checkpoint.load());
+    let format = if app_args.ndjson { StreamFormat::Ndjson } else { StreamFormat::Text };
+    let writer = EventWriter::spawn(io::stdout(), format, app_domain.config.stream_buffer, Some(checkpoint));
+    cli::print_stream_started(&source_name, last);
+    let mut run_pass = |app_domain: &mut AppDomain, app_state_machine: &mut AppStateMachine| {
+        let backfill = app_domain.config.stream_backfill;
+        match event_stream::run_stream_pass(source, &mut last, backfill, &writer, app_domain, app_state_machine) {
+            Ok(_) => true,
+            Err(StreamError::Retrieve(e)) => {
+                warn!("Stream pass stopped, continuing after {:?} on next pass {}", last, e);
+                true
+            }
+            Err(StreamError::OutputClosed) => false,
+        }
+    };
+    if run_pass(app_domain, app_state_machine) && !app_args.run_once {
+        let mut stream_core = Core::new().expect("Failed to create core");
+        let passes = Interval::new(Duration::from_secs(app_domain.config.stream_interval_secs), &stream_core.handle())
+            .expect("Failed to create interval for stream")
+            .map_err(|_| ())
+            .for_each(|_| if run_pass(app_domain, app_state_machine) { Ok(()) } else { Err(()) });
+        if stream_core.run(passes).is_err() {
+            info!("Stream output closed");
+        }
+    }
+    if let Err(e) = writer.finish() {
+        info!("Stream output closed {}", e);
+    }
+}
+
+///
+/// Prints the feed, user's submissions or search results given as arguments as RSS or Atom
+///
+fn run_feed_export(app_args: &AppArgs,
+                   app_domain: &mut AppDomain,
+                   app_cache: &mut AppCache,
+                   app_state_machine: &mut AppStateMachine) {
+    let table_format = TableFormat::parse(&app_args.feed_format);
+    let format = FeedFormat::parse(&app_args.feed_format);
+    if table_format.is_none() && format.is_none() {
+        cli::print_export_failed("format should be rss, atom, csv or tsv");
+        process::exit(1);
+    }
+    let field_names = app_args.fields.clone().unwrap_or(String::from(table_writer::DEFAULT_FIELDS));
+    let fields = match table_writer::parse_fields(&field_names) {
+        Ok(fields) => fields,
+        Err(e) => {
+            cli::print_export_failed(&e);
+            process::exit(1);
+        }
+    };
+    let info = match retrieve_export_source(&app_args.feed_source, app_domain, app_cache, app_state_machine) {
+        Ok(info) => info,
+        Err(e) => {
+            cli::print_export_failed(&e);
+            process::exit(1);
+        }
+    };
+    let ids: Vec<i32> = app_cache.listing().cloned().unwrap_or(Vec::new());
+    let now = chrono::Utc::now().timestamp();
+    match (format, table_format) {
+        (Some(format), _) => {
+            let entries = collect_feed_entries(&ids, app_args.feed_limit, app_args.feed_comments, app_domain, app_cache, app_state_machine);
+            cli::print_document(&feed_writer::write_feed(format, &info, &entries, &app_domain.formatters, now));
+        }
+        (None, Some(table_format)) => {
+            let rows = collect_ranked_items(&ids, app_args.feed_limit, app_domain, app_cache, app_state_machine);
+            cli::print_document(&table_writer::write_table(table_format, &fields, &rows, now));
+        }
+        (None, None) => (),
+    }
+    save_text_index(app_cache);
+}
+
+///
+/// Writes the top stories of the listing with their whole comment trees as a static site into the --out directory
+///
+fn run_digest(app_args: &AppArgs,
+              app_domain: &mut AppDomain,
+              app_cache: &mut AppCache,
+              app_state_machine: &mut AppStateMachine) {
+    let dir = match app_args.site_dir {
+        Some(ref dir) => dir.clone(),
+        None => {
+            cli::print_export_failed("give the directory of the site with --out");
+            process::exit(1);
+        }
+    };
+    let info = match retrieve_export_source(&app_args.feed_source, app_domain, app_cache, app_state_machine) {
+        Ok(info) => info,
+        Err(e) => {
+            cli::print_export_failed(&e);
+            process::exit(1);
+        }
+    };
+    let ids: Vec<i32> = app_cache.listing().cloned().unwrap_or(Vec::new());
+    let entries = collect_feed_entries(&ids, app_args.feed_limit, 0, app_domain, app_cache, app_state_machine);
+    let mut stories = Vec::new();
+    for entry in entries {
+        // search hits lack kids so the story is retrieved as it is
+        let story = get_cached_item(entry.item.id, app_domain, app_cache, app_state_machine).unwrap_or(entry.item);
+        let comments: Vec<HnItem> = client::get_comment_tree(&story, app_domain, app_state_machine)
+            .into_iter()
+            .filter(|comment| !app_cache.killfile.hides_comment(comment))
+            .collect();
+        stories.push(HnItemTree::build(story, &comments));
+    }
+    let now = chrono::Utc::now().timestamp();
+    let title = format!("{}, {}", info.title, chrono::Utc::now().format("%Y-%m-%d"));
+    match site_writer::write_site(Path::new(&dir), &title, &stories, &app_domain.formatters, now) {
+        Ok(_) => cli::print_digest_written(stories.len(), &dir),
+        Err(e) => {
+            cli::print_export_failed(&e.to_string());
+            process::exit(1);
+        }
+    }
+    save_text_index(app_cache);
+}
+
+///
+/// Retrieves the listing of feed name, user [id] or search [query] into the cache like the commands would
+///
+fn retrieve_export_source(source: &Vec<String>,
+                          app_domain: &mut AppDomain,
+                          app_cache: &mut AppCache,
+                          app_state_machine: &mut AppStateMachine)
+                          -> Result<FeedInfo, String> {
+    let kind = source.first().map(|kind| kind.as_str()).unwrap_or("top");
+    if kind == "user" {
+        let id = source.get(1).ok_or(String::from("user needs an id"))?;
+        let user = client::get_optional_user_by_id(id, app_domain, app_state_machine)
+            .map_err(|e| format!("could not get user {}: {}", id, e))?
+            .ok_or(format!("no user {}", id))?;
+        app_cache.retrieved_stories = Some(HnListOfItems { values: user.submitted });
+        Ok(FeedInfo {
+            title: format!("Hacker News submissions by {}", id),
+            link: format!("https://news.ycombinator.com/submitted?id={}", id),
+        })
+    } else if kind == "search" {
+        let words: Vec<String> = source.iter().skip(1).cloned().collect();
+        let query = SearchQuery::parse(&words, chrono::Utc::now().timestamp())?;
+        let results = client::search(&query, app_domain, app_state_machine)?;
+        let items = results.to_items();
+        app_cache.retrieved_stories = Some(HnListOfItems { values: items.iter().map(|item| item.id).collect() });
+        app_cache.search_hits = items.into_iter().map(|item| (item.id, item)).collect();
+        Ok(FeedInfo {
+            title: format!("Hacker News search {}", words.join(" ")),
+            link: String::from("https://hn.algolia.com/"),
+        })
+    } else {
+        let feed = HnFeed::parse(kind).ok_or(format!("{} is not a feed, user [id] or search [query]", kind))?;
+        let stories = client::get_feed_story_ids(feed, app_domain, app_state_machine)
+            .map_err(|e| format!("could not get feed {}: {}", feed.name(), e))?;
+        app_cache.retrieved_stories = Some(stories);
+        Ok(FeedInfo {
+            title: format!("Hacker News {}", feed.name()),
+            link: feed.web_url(),
+        })
+    }
+}
+
+///
+/// Writes the story and its whole comment tree as messages threaded by their parents into mbox or Maildir
+///
+fn handle_export_mail(numb: usize,
+                      path: &str,
+                      app_domain: &mut AppDomain,
+                      app_cache: &mut AppCache,
+                      app_state_machine: &mut AppStateMachine)
+                      -> Result<(), ()> {
+    // search hits lack kids so the story is retrieved as it is
+    let story = safe_load_story(numb, app_domain, app_cache, app_state_machine)
+        .and_then(|story| get_cached_item(story.id, app_domain, app_cache, app_state_machine))
+        .ok_or(())?;
+    let comments = client::get_comment_tree(&story, app_domain, app_state_machine);
+    let messages = message::create_thread_messages(&story, &comments, &app_domain.formatters);
+    let result = if mailbox::is_maildir_path(path) {
+        mailbox::write_maildir(Path::new(path), &messages)
+    } else {
+        mailbox::write_mbox(Path::new(path), &messages)
+    };
+    match result {
+        Ok(_) => {
+            cli::print_mail_exported(messages.len(), path);
+            Ok(())
+        }
+        Err(e) => {
+            cli::print_could_not_export_mail(&e.to_string());
+            Err(())
+        }
+    }
+}
+
+///
+/// Writes the current listing, with the feed filters and killfile applied, into a file as RSS or Atom
+///
+fn handle_publish(cmd: &UiCommand,
+                  app_domain: &mut AppDomain,
+                  app_cache: &mut AppCache,
+                  app_state_machine: &mut AppStateMachine)
+                  -> Result<(), ()> {
+    let args: Vec<String> = cmd.extra_args.clone().unwrap_or(Vec::new());
+    let format = match args.first().and_then(|name| FeedFormat::parse(name)) {
+        Some(format) => format,
+        None => {
+            cli::print_could_not_publish("format should be rss or atom");
+            return Err(());
+        }
+    };
+    let path = match args.get(1) {
+        Some(path) => path.clone(),
+        None => {
+            cli::print_could_not_publish("give the file to publish to");
+            return Err(());
+        }
+    };
+    let comments = gen_utils::try_to_parse_number(cmd.flag_value("comments")).unwrap_or(0);
+    let limit = gen_utils::try_to_parse_number(cmd.flag_value("limit")).unwrap_or(DEFAULT_PUBLISH_LIMIT);
+    let info = if app_cache.search_hits.is_empty() {
+        FeedInfo {
+            title: format!("Hacker News {}", app_state_machine.current_feed.name()),
+            link: app_state_machine.current_feed.web_url(),
+        }
+    } else {
+        FeedInfo {
+            title: String::from("Hacker News results"),
+            link: String::from("https://hn.algolia.com/"),
+        }
+    };
+    let ids: Vec<i32> = app_cache.listing().cloned().unwrap_or(Vec::new());
+    let entries = collect_feed_entries(&ids, limit, comments, app_domain, app_cache, app_state_machine);
+    let now = chrono::Utc::now().timestamp();
+    let document = feed_writer::write_feed(format, &info, &entries, &app_domain.formatters, now);
+    match io_utils::write_file(Path::new(&path), &document) {
+        Ok(_) => {
+            cli::print_published(entries.len(), &path);
+            Ok(())
+        }
+        Err(e) => {
+            cli::print_could_not_publish(&e.to_string());
+            Err(())
+        }
+    }
+}
+
+///
+/// Writes the current listing, with the feed filters and killfile applied, into a file or stdout as CSV or TSV
+///
+fn handle_export_list(cmd: &UiCommand,
+                      app_domain: &mut AppDomain,
+                      app_cache: &mut AppCache,
+                      app_state_machine: &mut AppStateMachine)
+                      -> Result<(), ()> {
+    let args: Vec<String> = cmd.extra_args.clone().unwrap_or(Vec::new());
+    let format = match args.first().and_then(|name| TableFormat::parse(name)) {
+        Some(format) => format,
+        None => {
+            cli::print_could_not_export_list("format should be csv or tsv");
+            return Err(());
+        }
+    };
+    let fields = match table_writer::parse_fields(cmd.flag_value("fields").unwrap_or(table_writer::DEFAULT_FIELDS)) {
+        Ok(fields) => fields,
+        Err(e) => {
+            cli::print_could_not_export_list(&e);
+            return Err(());
+        }
+    };
+    let limit = gen_utils::try_to_parse_number(cmd.flag_value("limit")).unwrap_or(DEFAULT_PUBLISH_LIMIT);
+    let ids: Vec<i32> = app_cache.listing().cloned().unwrap_or(Vec::new());
+    let rows = collect_ranked_items(&ids, limit, app_domain, app_cache, app_state_machine);
+    let table = table_writer::write_table(format, &fields, &rows, chrono::Utc::now().timestamp());
+    match args.get(1) {
+        Some(path) => {
+            match io_utils::write_file(Path::new(path), &table) {
+                Ok(_) => {
+                    cli::print_list_exported(rows.len(), path);
+                    Ok(())
+                }
+                Err(e) => {
+                    cli::print_could_not_export_list(&e.to_string());
+                    Err(())
+                }
+            }
+        }
+        None => {
+            cli::print_document(&table);
+            Ok(())
+        }
+    }
+}
+
+///
+/// Items of the listing which pass the killfile with their ranks in the listing, counting from 1
+///
+fn collect_ranked_items(ids: &Vec<i32>,
+                        limit: usize,
+                        app_domain: &mut AppDomain,
+                        app_cache: &mut AppCache,
+                        app_state_machine: &mut AppStateMachine)
+                        -> Vec<(usize, HnItem)> {
+    let mut rows = Vec::new();
+    for (index, id) in ids.iter().enumerate() {
+        if rows.len() >= limit {
+            break;
+        }
+        match get_listing_item(*id, app_domain, app_cache, app_state_machine) {
+            Some(ref item) if app_cache.killfile.matching_story_rule(item).is_some() => (),
+            Some(item) => rows.push((index + 1, item)),
+            None => (),
+        }
+    }
+    rows
+}
+
+///
+/// Items of the listing which pass the killfile, with up to comments top comments of each
+///
+fn collect_feed_entries(ids: &Vec<i32>,
+                        limit: usize,
+                        comments: usize,
+                        app_domain: &mut AppDomain,
+                        app_cache: &mut AppCache,
+                        app_state_machine: &mut AppStateMachine)
+                        -> Vec<FeedEntry> {
+    let mut entries = Vec::new();
+    for id in ids.iter() {
+        if entries.len() >= limit {
+            break;
+        }
+        let item = match get_listing_item(*id, app_domain, app_cache, app_state_machine) {
+            Some(item) => item,
+            None => continue,
+        };
+        if app_cache.killfile.matching_story_rule(&item).is_some() {
+            continue;
+        }
+        let top_comments = if comments > 0 {
+            retrieve_top_comments(*id, comments, app_domain, app_cache, app_state_machine)
+        } else {
+            Vec::new()
+        };
+        entries.push(FeedEntry {
+            item: item,
+            comments: top_comments,
+        });
+    }
+    entries
+}
+
+fn retrieve_top_comments(id: i32,
+                         count: usize,
+                         app_domain: &mut AppDomain,
+                         app_cache: &mut AppCache,
+                         app_state_machine: &mut AppStateMachine)
+                         -> Vec<HnItem> {
+    // search hits lack kids so the item is retrieved as it is
+    let mut item = match get_cached_item(id, app_domain, app_cache, app_state_machine) {
+        Some(item) => item,
+        None => return Vec::new(),
+    };
+    item.kids = item.kids.map(|kids| kids.into_iter().take(count).collect());
+    let comments = client::get_comments_for_item(&item, app_domain, app_state_machine).unwrap_or(Vec::new());
+    comments.into_iter().filter(|comment| !app_cache.killfile.hides_comment(comment)).collect()
+}
+
+///
+/// Serves the feeds as newsgroups until the process is stopped
+///
+fn run_nntp(app_args: &AppArgs,
+            app_domain: &mut AppDomain,
+            app_cache: &mut AppCache,
+            app_state_machine: &mut AppStateMachine) {
+    let address = app_args.listen_address();
+    let listener = bind_listener(&address);
+    cli::print_nntp_started(&address);
+    let stories = app_domain.config.nntp_stories;
+    let formatter = app_domain.create_formatters(true);
+    let mut source = FeedGroupSource {
+        app_domain: app_domain,
+        app_cache: app_cache,
+        app_state_machine: app_state_machine,
+        stories: stories,
+    };
+    if let Err(e) = nntp_server::serve(listener, &mut source, &formatter) {
+        warn!("NNTP server stopped {}", e);
+    }
+}
+
+///
+/// Newsgroup hn.[feed] for each feed, with the top stories of the feed and all of their comments
+///
+struct FeedGroupSource<'a> {
+    app_domain: &'a mut AppDomain,
+    app_cache: &'a mut AppCache,
+    app_state_machine: &'a mut AppStateMachine,
+    stories: usize,
+}
+
+impl<'a> GroupSource for FeedGroupSource<'a> {
+    fn group_names(&self) -> Vec<(String, String)> {
+        HnFeed::all()
+            .iter()
+            .map(|feed| (format!("hn.{}", feed.name()), format!("Hacker News {} stories", feed.name())))
+            .collect()
+    }
+
+    fn load_group(&mut self, name: &str) -> Option<NewsGroup> {
+        let feed = HnFeed::parse(name.splitn(2, '.').nth(1)?)?;
+        let stories = match client::get_feed_story_ids(feed, self.app_domain, self.app_state_machine) {
+            Ok(stories) => stories,
+            Err(e) => {
+                warn!("Could not retrieve feed {} {}", feed.name(), e);
+                return None;
+            }
+        };
+        let mut items = Vec::new();
+        for id in stories.values.iter().take(self.stories) {
+            let story = match get_cached_item(*id, self.app_domain, self.app_cache, self.app_state_machine) {
+                Some(story) => story,
+                None => continue,
+            };
+            if self.app_cache.killfile.matching_story_rule(&story).is_some() {
+                continue;
+            }
+            let comments = client::get_comment_tree(&story, self.app_domain, self.app_state_machine);
+            items.extend(comments.into_iter().filter(|comment| !self.app_cache.killfile.hides_comment(comment)));
+            items.push(story);
+        }
+        save_text_index(self.app_cache);
+        Some(NewsGroup::new(name, items))
+    }
+}
+
+///
+/// Binds the address of a server mode or exits if it can't be listened on
+///
+fn bind_listener(address: &str) -> TcpListener {
+    match TcpListener::bind(address) {
+        Ok(listener) => listener,
+        Err(e) => {
+            cli::print_could_not_listen(address, &e.to_string());
+            process::exit(1);
+        }
+    }
+}
+
+///
+/// Serves the JSON api, or the Gemini or Gopher pages with --gemini and --gopher, until the process is stopped
+///
+fn run_api_server(app_args: &AppArgs,
+                  app_domain: &mut AppDomain,
+                  app_cache: &mut AppCache,
+                  app_state_machine: &mut AppStateMachine) {
+    let address = app_args.listen_address();
+    // the identity is read first so a missing one is reported before anything is served
+    let acceptor = if app_args.serve_protocol == ServeProtocol::Gemini {
+        Some(load_gemini_acceptor(&app_domain.config))
+    } else {
+        None
+    };
+    let listener = bind_listener(&address);
+    // gemini clients wrap the text themselves
+    let formatter = app_domain.create_formatters(app_args.serve_protocol != ServeProtocol::Gemini);
+    let mut source = AppApiSource {
+        app_domain: app_domain,
+        app_cache: app_cache,
+        app_state_machine: app_state_machine,
+    };
+    let result = match app_args.serve_protocol {
+        ServeProtocol::Http => {
+            cli::print_api_started(&address);
+            http_api::serve(listener, &mut source)
+        }
+        ServeProtocol::Gemini => {
+            cli::print_smallnet_started("Gemini", &format!("gemini://{}/", address));
+            smallnet_server::serve_gemini(listener, acceptor.unwrap(), &mut source, &formatter)
+        }
+        ServeProtocol::Gopher => {
+            let host = GopherHost {
+                host: address.rsplitn(2, ':').nth(1).unwrap_or("localhost").to_string(),
+                port: listener.local_addr().map(|addr| addr.port()).unwrap_or(70),
+            };
+            cli::print_smallnet_started("Gopher", &format!("gopher://{}/", address));
+            smallnet_server::serve_gopher(listener, &host, &mut source, &formatter)
+        }
+    };
+    if let Err(e) = result {
+        warn!("Server stopped {}", e);
+    }
+}
+
+fn load_gemini_acceptor(config: &AppConfig) -> native_tls::TlsAcceptor {
+    let path = resolve_data_path(&config.gemini_identity);
+    let mut der = Vec::new();
+    let result = File::open(&path)
+        .and_then(|mut file| file.read_to_end(&mut der))
+        .map_err(|e| format!("could not read {} {}", path.to_string_lossy(), e))
+        .and_then(|_| smallnet_server::create_acceptor(&der, &config.gemini_identity_password));
+    match result {
+        Ok(acceptor) => acceptor,
+        Err(e) => {
+            cli::print_could_not_load_identity(&e);
+            process::exit(1);
+        }
+    }
+}
+
+///
+/// Data of the api from the client, items go through the same cache and killfile as in the cli
+///
+struct AppApiSource<'a> {
+    app_domain: &'a mut AppDomain,
+    app_cache: &'a mut AppCache,
+    app_state_machine: &'a mut AppStateMachine,
+}
+
+impl<'a> ApiSource for AppApiSource<'a> {
+    fn feed_ids(&mut self, feed: HnFeed) -> Result<Vec<i32>, String> {
+        client::get_feed_story_ids(feed, self.app_domain, self.app_state_machine)
+            .map(|stories| stories.values)
+            .map_err(|e| format!("could not retrieve feed {} {}", feed.name(), e))
+    }
+
+    fn item(&mut self, id: i32) -> Result<Option<HnItem>, String> {
+        match self.app_cache.get_cached_item(id) {
+            Some(item) => return Ok(Some(item.clone())),
+            None => (),
+        }
+        let item = client::get_optional_item_by_id(&id.to_string(), self.app_domain, self.app_state_machine)
+            .map_err(|e| format!("could not retrieve item {} {}", id, e))?;
+        match item {
+            Some(ref item) => {
+                self.app_cache.text_index.add(item, &self.app_domain.formatters);
+                self.app_cache.cache_item(item.clone());
+            }
+            None => (),
+        }
+        Ok(item)
+    }
+
+    fn comments(&mut self, item: &HnItem) -> Vec<HnItem> {
+        client::get_comment_tree(item, self.app_domain, self.app_state_machine)
+    }
+
+    fn user(&mut self, id: &str) -> Result<Option<HnUser>, String> {
+        client::get_optional_user_by_id(id, self.app_domain, self.app_state_machine)
+            .map_err(|e| format!("could not retrieve user {} {}", id, e))
+    }
+
+    fn search(&mut self, query: &SearchQuery) -> Result<Vec<HnItem>, String> {
+        client::search(query, self.app_domain, self.app_state_machine).map(|results| results.to_items())
+    }
+
+    fn feed_filter_stories(&self) -> usize {
+        self.app_domain.config.feed_filter_stories
+    }
+
+    fn hides_story(&self, item: &HnItem) -> bool {
+        self.app_cache.killfile.matching_story_rule(item).is_some()
+    }
+
+    fn hides_comment(&self, item: &HnItem) -> bool {
+        self.app_cache.killfile.hides_comment(item)
+    }
+
+    fn finish_request(&mut self) {
+        save_text_index(self.app_cache);
+    }
+}
+
+///
+/// Runs the script given with --script and exits with 1 if any of its commands failed
+///
+fn run_script_mode(app_args: &AppArgs,
+                   app_domain: &mut AppDomain,
+                   app_cache: &mut AppCache,
+                   app_state_machine: &mut AppStateMachine) {
+    let path = app_args.script.clone().unwrap_or(String::new());
+    let on_error = if app_args.continue_on_error { OnError::Continue } else { OnError::Stop };
+    let result = run_script(&path, on_error, app_args, app_domain, app_cache, app_state_machine);
+    save_text_index(app_cache);
+    let code = match result {
+        Ok(0) => 0,
+        Ok(failed) => {
+            cli::print_script_commands_failed(&path, failed);
+            1
+        }
+        Err(e) => {
+            cli::print_script_failed(&e);
+            1
+        }
+    };
+    logging_utils::log_exit();
+    process::exit(code);
+}
+
+///
+/// Runs the commands of the script like they were typed in, returns how many commands failed when the script
+/// continues on errors and an error when it stopped
+///
+fn run_script(path: &str,
+              on_error: OnError,
+              app_args: &AppArgs,
+              app_domain: &mut AppDomain,
+              app_cache: &mut AppCache,
+              app_state_machine: &mut AppStateMachine)
+              -> Result<usize, String> {
+    if app_state_machine.script_depth >= MAX_SCRIPT_DEPTH {
+        return Err(format!("{} sources scripts deeper than {} levels", path, MAX_SCRIPT_DEPTH));
+    }
+    let script = Script::load(path)?;
+    app_state_machine.script_depth += 1;
+    let result = run_script_steps(&script, on_error, app_args, app_domain, app_cache, app_state_machine);
+    app_state_machine.script_depth -= 1;
+    result
+}
+
+fn run_script_steps(script: &Script,
+                    mut on_error: OnError,
+                    app_args: &AppArgs,
+                    app_domain: &mut AppDomain,
+                    app_cache: &mut AppCache,
+                    app_state_machine: &mut AppStateMachine)
+                    -> Result<usize, String> {
+    let mut vars = ScriptVars::new();
+    let mut failed = 0;
+    for step in script.steps.iter() {
+        let (line, command) = match *step {
+            ScriptStep::SetOnError(mode) => {
+                on_error = mode;
+                continue;
+            }
+            ScriptStep::Command(line, ref command) => (line, vars.substitute(command)),
+        };
+        let cmd = UiCommand::parse_line(&command);
+        if cmd.command.as_ref().map(|verb| verb == "exit").unwrap_or(false) {
+            break; // ends the script, not the application
+        }
+        let result = if cmd.valid {
+            logging_utils::log_cmd(&cmd);
+            gui_listener(cmd, app_args, app_domain, app_cache, app_state_machine)
+        } else {
+            cli::print_command_error(cmd.error.as_ref().map(|e| e.as_str()).unwrap_or("Empty command"));
+            Err(())
+        };
+        vars.last = visible_indexes(app_cache, app_state_machine);
+        vars.status = if result.is_ok() { 0 } else { 1 };
+        if vars.status != 0 {
+            if on_error == OnError::Stop {
+                return Err(format!("{}:{} {} failed, stopping the script", script.path, line, command));
+            }
+            failed += 1;
+        }
+    }
+    Ok(failed)
+}
+
+fn handle_check_watches(app_domain: &mut AppDomain,
+                        app_cache: &mut AppCache,
+                        app_state_machine: &mut AppStateMachine) {
+    app_state_machine.last_watch_check = Instant::now();
+    let notifications = watch::check_watches(&mut app_cache.watch_list, app_domain, app_state_machine);
+    logging_utils::log_checked_watches(app_cache.watch_list.watches.len(), notifications.len());
+    if !notifications.is_empty() {
+        Notifiers::from_config(&app_domain.config).notify(&notifications);
+        save_watch_list(&app_cache.watch_list);
+    }
+}
+
+fn handle_watch(numb: usize,
+                has_numb: bool,
+                extra_args: &Option<Vec<String>>,
+                app_domain: &mut AppDomain,
+                app_cache: &mut AppCache,
+                app_state_machine: &mut AppStateMachine)
+                -> Result<(), ()> {
+    let args: Vec<String> = extra_args.clone().unwrap_or(Vec::new());
+    let target = if !has_numb && args.len() == 2 && args[0] == "user" {
+        Some(WatchTarget::User(args[1].clone()))
+    } else if has_numb && app_state_machine.viewing_comments() {
+        check_numb_against_comments(numb, app_cache)
+            .and_then(|n| app_cache.last_retrieved_comments.as_ref().map(|comments| WatchTarget::Item(comments[n].id)))
+    } else if has_numb {
+        check_numb_against_stories(numb, app_cache)
+            .and_then(|n| app_cache.story_id_at(n))
+            .map(|id| WatchTarget::Item(id))
+    } else {
+        None
+    };
+    let target = match target {
+        Some(target) => target,
+        None => {
+            cli::print_invalid_command();
+            return Err(());
+        }
+    };
+    let description = target.describe();
+    match watch::create_watch(target, app_domain, app_state_machine) {
+        Ok(new_watch) => {
+            let added = app_cache.watch_list.add(new_watch);
+            if added {
+                save_watch_list(&app_cache.watch_list);
+            }
+            cli::print_watch_added(&description, added);
+            Ok(())
+        }
+        Err(e) => {
+            warn!("Could not create watch {}", e);
+            cli::print_could_not_watch(&description);
+            Err(())
+        }
+    }
+}
+
+fn handle_filters(extra_args: &Option<Vec<String>>, app_cache: &mut AppCache) -> Result<(), ()> {
+    let args: Vec<String> = extra_args.clone().unwrap_or(Vec::new());
+    let killfile = &mut app_cache.killfile;
+    match args.first().map(|arg| arg.as_str()) {
+        None => {
+            cli::print_filters(killfile);
+            return Ok(());
+        }
+        Some("add") if args.len() >= 3 => {
+            match KillRule::parse(&args[1], &args[2..].join(" ")) {
+                Some(rule) => {
+                    match killfile.add(rule.clone()) {
+                        Ok(_) => cli::print_filter_added(&rule),
+                        Err(reason) => {
+                            cli::print_invalid_filter(&reason);
+                            return Err(());
+                        }
+                    }
+                }
+                None => {
+                    cli::print_invalid_filter("filter type should be domain, title, author or comment-author");
+                    return Err(());
+                }
+            }
+        }
+        Some("remove") if args.len() == 2 => {
+            let removed = gen_utils::try_to_parse_number(Some(&args[1]))
+                .and_then(|numb| if numb > 0 { killfile.remove(numb - 1) } else { None });
+            match removed {
+                Some(rule) => cli::print_filter_removed(&rule),
+                None => {
+                    cli::print_invalid_numb();
+                    return Err(());
+                }
+            }
+        }
+        Some("mode") if args.len() == 2 => {
+            killfile.story_action = match args[1].as_str() {
+                "hide" => KillAction::Hide,
+                "collapse" => KillAction::Collapse,
+                _ => {
+                    cli::print_invalid_filter("mode should be hide or collapse");
+                    return Err(());
+                }
+            };
+            cli::print_filters(killfile);
+        }
+        _ => {
+            cli::print_invalid_command();
+            return Err(());
+        }
+    }
+    if killfile.save().is_err() {
+        warn!("Could not save killfile");
+    }
+    Ok(())
+}
+
+fn save_watch_list(watch_list: &WatchList) {
+    if watch_list.save().is_err() {
+        warn!("Could not save watch list");
+    }
+}
+
+fn visible_story_ids(app_cache: &AppCache, app_state_machine: &AppStateMachine) -> Vec<i32> {
+    let skipped = app_state_machine.listing_page_index * 10;
+    match app_cache.listing() {
+        Some(listing) => listing.iter().skip(skipped).take(10).cloned().collect(),
+        None => Vec::new(),
+    }
+}
+
+///
+/// Offers the indexes printed on the current page for completion
+///
+fn update_completion(completion: &Arc<Mutex<CompletionContext>>, app_cache: &AppCache, app_state_machine: &AppStateMachine) {
+    completion.lock().unwrap().indexes = visible_indexes(app_cache, app_state_machine);
+}
+
+///
+/// Indexes of the stories or comments printed on the current page, counting from 1
+///
+fn visible_indexes(app_cache: &AppCache, app_state_machine: &AppStateMachine) -> Vec<usize> {
+    let (page_index, len) = if app_state_machine.viewing_comments() {
+        (app_state_machine.comments_page_index, app_cache.comments_len().unwrap_or(0))
+    } else {
+        (app_state_machine.listing_page_index, app_cache.stories_len().unwrap_or(0))
+    };
+    let first = page_index * 10;
+    (first..min(first + 10, len)).map(|index| index + 1).collect()
+}
+
+fn visible_comment_ids(app_cache: &AppCache, app_state_machine: &AppStateMachine) -> Vec<i32> {
+    let skipped = app_state_machine.comments_page_index * 10;
+    let mut ids: Vec<i32> = match app_cache.last_retrieved_comments {
+        Some(ref comments) => comments.iter().skip(skipped).take(10).map(|c| c.id).collect(),
+        None => Vec::new(),
+    };
+    match app_cache.last_parent_items.back() {
+        Some(parent) => ids.push(parent.id),
+        None => (),
+    }
+    ids
+}
+
+///
+/// Retrieves the parent of the visible comments again and reprints the current page in place
+///
+fn refresh_comments(app_domain: &mut AppDomain,
+                    app_cache: &mut AppCache,
+                    app_state_machine: &mut AppStateMachine) {
+    let parent_id = match app_cache.last_parent_items.back() {
+        Some(parent) => parent.id,
+        None => return,
+    };
+    let parent = match client::get_item_by_id(&parent_id.to_string(), app_domain, app_state_machine) {
+        Ok(parent) => parent,
+        Err(e) => {
+            warn!("Could not refresh item {} {}", parent_id, e);
+            return;
+        }
+    };
+    match app_cache.last_retrieved_comments {
+        Some(ref comments) => {
+            for comment in comments {
+                app_cache.previous_stats.insert(comment.id, comment.stats());
+            }
+        }
+        None => (),
+    }
+    app_cache.last_parent_items.pop_back();
+    retrieve_comments_for_item(parent, app_domain, app_cache, app_state_machine);
+    output_comments(app_domain, app_cache, app_state_machine);
+}
+
+fn handle_switch_feed(feed: HnFeed,
+                      app_domain: &mut AppDomain,
+                      app_cache: &mut AppCache,
+                      app_state_machine: &mut AppStateMachine)
+                      -> Result<(), ()> {
+    match client::get_feed_story_ids(feed, app_domain, app_state_machine) {
+        Ok(stories) => {
+            app_state_machine.current_feed = feed;
+            app_cache.retrieved_stories = Some(stories);
+            app_cache.search_hits.clear();
+            app_cache.listing_highlights.clear();
+            app_state_machine.listing_page_index = 0;
+            apply_feed_filters(app_domain, app_cache, app_state_machine);
+            print_and_log_stories(app_domain, app_cache, app_state_machine);
+            Ok(())
+        }
+        Err(e) => {
+            warn!("Could not retrieve feed {} {}", feed.name(), e);
+            cli::print_could_not_get_feed(feed.name());
+            Err(())
+        }
+    }
+}
+
+///
+/// Replaces the listing with the search results so they can be paged, filtered and opened like a feed
+///
+fn handle_search(words: &Vec<String>,
+                 app_domain: &mut AppDomain,
+                 app_cache: &mut AppCache,
+                 app_state_machine: &mut AppStateMachine)
+                 -> Result<(), ()> {
+    let query = match SearchQuery::parse(words, chrono::Utc::now().timestamp()) {
+        Ok(query) => query,
+        Err(e) => {
+            cli::print_invalid_search(&e);
+            return Err(());
+        }
+    };
+    match client::search(&query, app_domain, app_state_machine) {
+        Ok(results) => {
+            let items = results.to_items();
+            cli::print_search_results_found(items.len(), results.total_hits);
+            app_cache.retrieved_stories = Some(HnListOfItems { values: items.iter().map(|item| item.id).collect() });
+            app_cache.search_hits = items.into_iter().map(|item| (item.id, item)).collect();
+            app_cache.listing_highlights.clear();
+            app_state_machine.listing_page_index = 0;
+            apply_feed_filters(app_domain, app_cache, app_state_machine);
+            print_and_log_stories(app_domain, app_cache, app_state_machine);
+            Ok(())
+        }
+        Err(e) => {
+            warn!("Search failed {}", e);
+            cli::print_search_failed(&e);
+            Err(())
+        }
+    }
+}
+
+///
+/// Queries the local index of retrieved items and lists the hits in order of relevance
+///
+fn handle_find(query: &str,
+               app_domain: &mut AppDomain,
+               app_cache: &mut AppCache,
+               app_state_machine: &mut AppStateMachine)
+               -> Result<(), ()> {
+    let query = match IndexQuery::parse(query) {
+        Ok(query) => query,
+        Err(e) => {
+            cli::print_invalid_search(&e);
+            return Err(());
+        }
+    };
+    let hits = app_cache.text_index.search(&query, chrono::Utc::now().timestamp());
+    cli::print_index_hits_found(hits.len(), app_cache.text_index.len());
+    let terms = query.positive_terms();
+    let items: Vec<HnItem> = hits.iter()
+        .filter_map(|hit| app_cache.text_index.doc(hit.id))
+        .map(|doc| doc.to_item(&terms))
+        .collect();
+    app_cache.retrieved_stories = Some(HnListOfItems { values: items.iter().map(|item| item.id).collect() });
+    app_cache.search_hits = items.into_iter().map(|item| (item.id, item)).collect();
+    app_cache.listing_highlights = terms;
+    app_state_machine.listing_page_index = 0;
+    apply_feed_filters(app_domain, app_cache, app_state_machine);
+    print_and_log_stories(app_domain, app_cache, app_state_machine);
+    Ok(())
+}
+
+fn save_text_index(app_cache: &mut AppCache) {
+    if app_cache.text_index.is_dirty() && app_cache.text_index.save().is_err() {
+        warn!("Could not save the index to {}", TextIndex::default_path().to_string_lossy());
+    }
+}
+
+///
+/// Sets or clears (without value) a feed threshold or the sort mode and re-pages the feed from the first page
+///
+fn handle_feed_filter(verb: &str,
+                      value: Option<String>,
+                      app_domain: &mut AppDomain,
+                      app_cache: &mut AppCache,
+                      app_state_machine: &mut AppStateMachine)
+                      -> Result<(), ()> {
+    let filters = &mut app_state_machine.feed_filters;
+    let valid = match (verb, value) {
+        ("min-score", Some(v)) => v.parse::<i32>().ok().map(|n| filters.min_score = Some(n)).is_some(),
+        ("min-score", None) => { filters.min_score = None; true }
+        ("min-comments", Some(v)) => v.parse::<i32>().ok().map(|n| filters.min_comments = Some(n)).is_some(),
+        ("min-comments", None) => { filters.min_comments = None; true }
+        ("max-age", Some(v)) => gen_utils::parse_duration_secs(&v).map(|secs| filters.max_age_secs = Some(secs)).is_some(),
+        ("max-age", None) => { filters.max_age_secs = None; true }
+        ("sort", Some(v)) => FeedSort::parse(&v).map(|sort| filters.sort = sort).is_some(),
+        ("sort", None) => { filters.sort = FeedSort::Rank; true }
+        _ => false,
+    };
+    if !valid {
+        cli::print_invalid_command();
+        return Err(());
+    }
+    app_state_machine.listing_page_index = 0;
+    apply_feed_filters(app_domain, app_cache, app_state_machine);
+    cli::print_feed_filters(&app_state_machine.feed_filters, app_cache.stories_len().unwrap_or(0));
+    print_and_log_stories(app_domain, app_cache, app_state_machine);
+    Ok(())
+}
+
+///
+/// Builds the filtered view of the top feed_filter_stories stories of the feed, they're retrieved to cache first
+///
+fn apply_feed_filters(app_domain: &mut AppDomain,
+                      app_cache: &mut AppCache,
+                      app_state_machine: &mut AppStateMachine) {
+    if !app_state_machine.feed_filters.is_active() {
+        app_cache.filtered_stories = None;
+        return;
+    }
+    let ids: Vec<i32> = match app_cache.retrieved_stories {
+        Some(ref stories) => stories.values.iter().take(app_domain.config.feed_filter_stories).cloned().collect(),
+        None => return,
+    };
+    let uncached = ids.iter()
+        .filter(|id| app_cache.get_cached_item(**id).is_none() && !app_cache.search_hits.contains_key(id))
+        .count();
+    if uncached > 0 {
+        cli::print_retrieving_stories_for_filters(uncached);
+    }
+    let items: Vec<HnItem> = ids.iter()
+        .filter_map(|id| get_listing_item(*id, app_domain, app_cache, app_state_machine))
+        .collect();
+    let now = chrono::Utc::now().timestamp();
+    app_cache.filtered_stories = Some(app_state_machine.feed_filters.apply(items.iter().collect(), now));
 }
 
 fn print_and_log_stories(app_domain: &mut AppDomain,
@@ -220,6 +1756,7 @@ fn print_and_log_stories(app_domain: &mut AppDomain,
 
 fn check_numb_against_stories(numb: usize, app_cache: &mut AppCache) -> Option<usize> {
     match app_cache.stories_len() {
+        Some(0) => None, // filters can leave the listing empty
         Some(l) => Some(min((l - 1), numb)),
         None => None,
     }
@@ -256,6 +1793,41 @@ fn safe_load_story(numb: usize,
     None
 }
 
+///
+/// Copies the text of the comment quoted for replying to the clipboard, or prints it when asked or there's no clipboard
+///
+fn handle_quote(numb: usize,
+                print: bool,
+                app_domain: &AppDomain,
+                app_cache: &AppCache,
+                app_state_machine: &AppStateMachine)
+                -> Result<(), ()> {
+    let comment = if app_state_machine.viewing_comments() {
+        app_cache.last_retrieved_comments.as_ref().and_then(|comments| comments.get(numb))
+    } else {
+        None
+    };
+    let text = match comment.and_then(|comment| comment.text_unescaped()) {
+        Some(text) => text,
+        None => {
+            cli::print_invalid_numb();
+            return Err(());
+        }
+    };
+    let quote = QuoteFormatter.format(&app_domain.create_formatters(false).format(&text));
+    if !print {
+        match io_utils::copy_to_clipboard(&quote) {
+            Some(tool) => {
+                cli::print_quote_copied(tool);
+                return Ok(());
+            }
+            None => (),
+        }
+    }
+    cli::print_quote(&quote);
+    Ok(())
+}
+
 fn safe_load_comment(numb: usize,
                      app_cache: &mut AppCache)
                      -> Option<HnItem> {
@@ -268,7 +1840,11 @@ fn safe_load_comment(numb: usize,
         if act_numb != numb {
             cli::print_over_limit_but_using_index(act_numb + 1);
         }
-        return app_cache.get_comment_if_kids(act_numb);
+        let comment = app_cache.get_comment_if_kids(act_numb);
+        if comment.is_none() {
+            cli::print_no_comments_for(act_numb + 1);
+        }
+        return comment;
     }
     None
 }
@@ -281,6 +1857,10 @@ fn retrieve_comments_for_item(parent: HnItem,
     let comments = client::get_comments_for_item(&parent, app_domain, app_state_machine);
     match comments {
         Some(comments_vector) => {
+            for comment in comments_vector.iter() {
+                app_cache.text_index.add(comment, &app_domain.formatters);
+            }
+            app_cache.thread_search = None; // hits were for the previous comments
             app_cache.last_parent_items.push_back(parent); // move parent to this location
             app_cache.last_retrieved_comments = Some(comments_vector); // return comments and return
             true
@@ -294,62 +1874,153 @@ fn retrieve_story(numb: usize,
                   app_cache: &mut AppCache,
                   app_state_machine: &mut AppStateMachine)
                   -> Option<HnItem> {
-    let s = format!("{}",
-                    app_cache.retrieved_top_stories.as_ref().unwrap().values[numb]); // FIXME unsafe way to do this
+    let s = match app_cache.story_id_at(numb) {
+        Some(id) => id.to_string(),
+        None => return None,
+    };
     let item = client::get_item_by_id(&s, app_domain, app_state_machine).ok();
+    match item {
+        Some(ref item) => app_cache.text_index.add(item, &app_domain.formatters),
+        None => (),
+    }
     item
 }
 
+///
+/// Item shown in the story listing, search results are listed as they were mapped from the search api
+///
+fn get_listing_item(id: i32,
+                    app_domain: &mut AppDomain,
+                    app_cache: &mut AppCache,
+                    app_state_machine: &mut AppStateMachine)
+                    -> Option<HnItem> {
+    match app_cache.search_hits.get(&id) {
+        Some(item) => return Some(item.clone()),
+        None => (),
+    }
+    get_cached_item(id, app_domain, app_cache, app_state_machine)
+}
+
+///
+/// Gets the item from cache or retrieves and caches it if it's not there
+///
+fn get_cached_item(id: i32,
+                   app_domain: &mut AppDomain,
+                   app_cache: &mut AppCache,
+                   app_state_machine: &mut AppStateMachine)
+                   -> Option<HnItem> {
+    match app_cache.get_cached_item(id) {
+        Some(item) => return Some(item.clone()),
+        None => (),
+    }
+    match client::get_item_by_id(&id.to_string(), app_domain, app_state_machine) {
+        Ok(item) => {
+            app_cache.text_index.add(&item, &app_domain.formatters);
+            app_cache.cache_item(item.clone());
+            Some(item)
+        }
+        Err(e) => {
+            warn!("Could not retrieve item {} {}", id, e);
+            None
+        }
+    }
+}
+
 fn handle_open_link(numb: usize,
              app_domain: &mut AppDomain,
              app_cache: &mut AppCache,
-             app_state_machine: &mut AppStateMachine) {
-    let s = format!("{}",
-                    app_cache.retrieved_top_stories.as_ref().unwrap().values[numb]); // FIXME unsafe way to do this
-    let item = client::get_item_by_id(&s, app_domain, app_state_machine).unwrap();
+             app_state_machine: &mut AppStateMachine)
+             -> Result<(), ()> {
+    let item = load_listed_item(numb, app_domain, app_cache, app_state_machine)?;
     if item.url.is_some() && webbrowser::open(&item.url.as_ref().unwrap()).is_ok() {
         // todo cleanup
         logging_utils::log_open_page(item.url.as_ref().unwrap());
         println!("{} {}", "Opened browser to url", item.url.as_ref().unwrap()) // todo move to cli
     }
+    Ok(())
 }
 
 fn handle_download_link(numb: usize,
                  app_domain: &mut AppDomain,
                  app_cache: &mut AppCache,
-                 app_state_machine: &mut AppStateMachine) {
-    let s = format!("{}", app_cache.retrieved_top_stories.as_ref().unwrap().values[numb]);
-    let item = client::get_item_by_id(&s, app_domain, app_state_machine).unwrap();
+                 app_state_machine: &mut AppStateMachine)
+                 -> Result<(), ()> {
+    let item = load_listed_item(numb, app_domain, app_cache, app_state_machine)?;
     let filen = client::download_page_from_item(&item, app_domain, app_state_machine);
     match filen {
         Ok(n) => {
             cli::print_filename_of_loaded_page(&n, item.title.as_ref().unwrap());
-            logging_utils::log_loaded_page_locally(item.url.as_ref().unwrap(), &n)
+            logging_utils::log_loaded_page_locally(item.url.as_ref().unwrap(), &n);
+            Ok(())
         }
         Err(e) => {
             cli::could_not_load_page(item.title.as_ref().unwrap());
             warn!("Could not load page to file {}", e);
+            Err(())
         }
     }
 
 }
 
+///
+/// Story at the index of the listing as it is now, not from cache as the link of the story can change
+///
+fn load_listed_item(numb: usize,
+                    app_domain: &mut AppDomain,
+                    app_cache: &mut AppCache,
+                    app_state_machine: &mut AppStateMachine)
+                    -> Result<HnItem, ()> {
+    let id = match app_cache.story_id_at(numb) {
+        Some(id) => id,
+        None => {
+            cli::print_invalid_numb();
+            return Err(());
+        }
+    };
+    client::get_item_by_id(&id.to_string(), app_domain, app_state_machine).map_err(|e| {
+        warn!("Could not retrieve item {} {}", id, e);
+        cli::print_could_not_get_story(numb + 1);
+    })
+}
+
 fn output_comments(app_domain: &mut AppDomain,
                       app_cache: &mut AppCache,
                       app_state_machine: &mut AppStateMachine) {
 
     let skipped: usize = (app_state_machine.comments_page_index * 10) as usize;
-    let mut partition: Option<Vec<&HnItem>> = None;
+    let mut partition: Option<Vec<(usize, &HnItem)>> = None;
+    let mut previous_stats: HashMap<i32, HnItemStats> = HashMap::new();
+    let mut hidden = 0;
     match app_cache.last_retrieved_comments {
         Some(ref comments) => {
-            partition = Some(comments.iter().skip(skipped).take(10).collect());
+            let page: Vec<(usize, &HnItem)> = comments.iter()
+                .enumerate()
+                .skip(skipped)
+                .take(10)
+                .map(|(index, comment)| (index + 1, comment)) // UI is designed as index starting from 1
+                .collect();
+            let shown: Vec<(usize, &HnItem)> = page.into_iter()
+                .filter(|&(_, comment)| !app_cache.killfile.hides_comment(comment))
+                .collect();
+            hidden = min(10, comments.len().saturating_sub(skipped)) - shown.len();
+            for &(_, comment) in &shown {
+                match app_cache.previous_stats.remove(&comment.id) {
+                    Some(stats) => { previous_stats.insert(comment.id, stats); }
+                    None => (),
+                }
+            }
+            partition = Some(shown);
         }
         None => (),
     }
+    let raw = Formatters::raw();
     cli::print_comments_and_parent(app_cache.last_parent_items.back(),
                                   &partition,
-                                  &app_domain.formatters,
-                                  skipped);
+                                  if app_state_machine.raw_comments { &raw } else { &app_domain.formatters },
+                                  &previous_stats,
+                                  app_cache.thread_search.as_ref(),
+                                  app_domain.config.highlight_code);
+    cli::print_hidden_by_filters(hidden, "comments");
 }
 
 fn output_stories(app_domain: &mut AppDomain,
@@ -357,15 +2028,30 @@ fn output_stories(app_domain: &mut AppDomain,
                      app_state_machine: &mut AppStateMachine) {
 
     // This probably should not need all the parameters
-    let top_stories = app_cache.retrieved_top_stories.as_ref().unwrap();
-    logging_utils::log_loaded_top_stories(top_stories.values.len());
+    logging_utils::log_loaded_top_stories(app_cache.stories_len().unwrap_or(0));
     let skipped: usize = (app_state_machine.listing_page_index * 10) as usize;
     let mut index = 0 + skipped as i32;
 
-    for item_id in top_stories.values.iter().skip(skipped).take(10) {
+    let mut hidden = 0;
+
+    for item_id in visible_story_ids(app_cache, app_state_machine) {
         index += 1;
-        let s = format!("{}", item_id);
-        let item: HnItem = client::get_item_by_id(&s, app_domain, app_state_machine).unwrap();
-        cli::print_headline_with_author(&item, &index);
+        match get_listing_item(item_id, app_domain, app_cache, app_state_machine) {
+            Some(item) => {
+                let previous_stats = app_cache.take_previous_stats(item_id);
+                match app_cache.killfile.matching_story_rule(&item) {
+                    Some(rule) => {
+                        if app_cache.killfile.story_action == KillAction::Collapse {
+                            cli::print_collapsed_headline(&index, &rule.describe());
+                        } else {
+                            hidden += 1;
+                        }
+                    }
+                    None => cli::print_headline_with_author(&item, &index, previous_stats, &app_cache.listing_highlights),
+                }
+            }
+            None => cli::print_could_not_get_story(index as usize),
+        }
     }
+    cli::print_hidden_by_filters(hidden, "stories");
 }
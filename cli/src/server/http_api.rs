@@ -0,0 +1,615 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use serde::Serialize;
+use serde_json;
+use url::form_urlencoded;
+use url::percent_encoding::percent_decode;
+use core::feed_view::{FeedFilters, FeedSort};
+use core::models::{HnFeed, HnItem, HnItemTree, HnUser};
+use core::search::SearchQuery;
+use helpers::gen_utils;
+
+/// Stories per page when the request does not say, the same as in the listings of the cli
+const DEFAULT_PER_PAGE: usize = 10;
+const MAX_PER_PAGE: usize = 100;
+/// Request line and headers together, longer requests are cut
+const MAX_REQUEST: u64 = 8192;
+/// Connections are served one at a time so a client which sends nothing can't hold the others for longer
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+/// Query parameters of /search which become filter words of the search command
+const SEARCH_FILTER_PARAMS: [&'static str; 6] = ["tag", "author", "since", "until", "points", "sort"];
+
+///
+/// Request line of an HTTP request with the query decoded, headers and bodies are not used by the api
+///
+#[derive(Debug)]
+pub struct ApiRequest {
+    pub method: String,
+    pub path: String,
+    pub params: Vec<(String, String)>,
+}
+
+impl ApiRequest {
+    ///
+    /// Parses a request line like GET /feeds/top?page=2 HTTP/1.1
+    ///
+    pub fn parse(request_line: &str) -> Option<ApiRequest> {
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next()?;
+        let target = parts.next()?;
+        if !parts.next()?.starts_with("HTTP/") || !target.starts_with('/') {
+            return None;
+        }
+        let (path, query) = match target.find('?') {
+            Some(i) => (&target[..i], &target[i + 1..]),
+            None => (target, ""),
+        };
+        Some(ApiRequest {
+            method: String::from(method),
+            path: String::from(path),
+            params: form_urlencoded::parse(query.as_bytes()).into_owned().collect(),
+        })
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|&&(ref key, _)| key == name).map(|&(_, ref value)| value.as_str())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ApiRoute {
+    Feed(HnFeed),
+    Item(i32),
+    ItemTree(i32),
+    User(String),
+    Search,
+}
+
+impl ApiRoute {
+    pub fn parse(path: &str) -> Option<ApiRoute> {
+        let segments: Vec<String> = path.trim_matches('/')
+            .split('/')
+            .map(|segment| percent_decode(segment.as_bytes()).decode_utf8_lossy().into_owned())
+            .collect();
+        let name = segments.get(0).map(|segment| segment.as_str()).unwrap_or("");
+        match (name, segments.len()) {
+            ("feeds", 2) => HnFeed::parse(&segments[1]).map(ApiRoute::Feed),
+            ("items", 2) => segments[1].parse::<i32>().ok().map(ApiRoute::Item),
+            ("items", 3) if segments[2] == "tree" => segments[1].parse::<i32>().ok().map(ApiRoute::ItemTree),
+            ("users", 2) if !segments[1].is_empty() => Some(ApiRoute::User(segments[1].clone())),
+            ("search", 1) => Some(ApiRoute::Search),
+            _ => None,
+        }
+    }
+}
+
+///
+/// Page of a listing, page counts from 1 and has per_page stories like the pages of the cli
+///
+#[derive(Debug, PartialEq)]
+pub struct Paging {
+    pub page: usize,
+    pub per_page: usize,
+}
+
+impl Paging {
+    pub fn parse(request: &ApiRequest) -> Result<Paging, String> {
+        let page = match request.param("page") {
+            Some(value) => {
+                match value.parse::<usize>() {
+                    Ok(page) if page > 0 => page,
+                    _ => return Err(format!("invalid page {}", value)),
+                }
+            }
+            None => 1,
+        };
+        let per_page = match request.param("per_page") {
+            Some(value) => {
+                match value.parse::<usize>() {
+                    Ok(n) if n > 0 && n <= MAX_PER_PAGE => n,
+                    _ => return Err(format!("invalid per_page {}, it should be 1-{}", value, MAX_PER_PAGE)),
+                }
+            }
+            None => DEFAULT_PER_PAGE,
+        };
+        if (page - 1).checked_mul(per_page).is_none() {
+            return Err(format!("invalid page {}", page));
+        }
+        Ok(Paging {
+            page: page,
+            per_page: per_page,
+        })
+    }
+
+    pub fn ids_of_page(&self, ids: &Vec<i32>) -> Vec<i32> {
+        ids.iter().skip((self.page - 1) * self.per_page).take(self.per_page).cloned().collect()
+    }
+
+    pub fn page_count(&self, total: usize) -> usize {
+        (total + self.per_page - 1) / self.per_page
+    }
+}
+
+///
+/// Thresholds of the feed view from the min-score, min-comments and max-age parameters, named like the commands
+///
+pub fn parse_filters(request: &ApiRequest) -> Result<FeedFilters, String> {
+    let mut filters = FeedFilters::new();
+    match request.param("min-score") {
+        Some(value) => filters.min_score = Some(value.parse::<i32>().map_err(|_| format!("invalid min-score {}", value))?),
+        None => (),
+    }
+    match request.param("min-comments") {
+        Some(value) => {
+            filters.min_comments = Some(value.parse::<i32>().map_err(|_| format!("invalid min-comments {}", value))?)
+        }
+        None => (),
+    }
+    match request.param("max-age") {
+        Some(value) => {
+            filters.max_age_secs = Some(gen_utils::parse_duration_secs(value).ok_or(format!("invalid max-age {}", value))?)
+        }
+        None => (),
+    }
+    Ok(filters)
+}
+
+///
+/// Words of the search command from the q parameter and the filter parameters, like tag=story becoming tag:story
+///
+pub fn search_words(request: &ApiRequest) -> Vec<String> {
+    let mut words: Vec<String> = request.param("q").unwrap_or("").split_whitespace().map(String::from).collect();
+    for name in SEARCH_FILTER_PARAMS.iter() {
+        match request.param(name) {
+            Some(value) => words.push(format!("{}:{}", name, value)),
+            None => (),
+        }
+    }
+    words
+}
+
+///
+/// Where the api gets its data, the client and cache of the app
+///
+pub trait ApiSource {
+    fn feed_ids(&mut self, feed: HnFeed) -> Result<Vec<i32>, String>;
+    /// None if there's no item with the id
+    fn item(&mut self, id: i32) -> Result<Option<HnItem>, String>;
+    /// All comments under the item, parents before their replies
+    fn comments(&mut self, item: &HnItem) -> Vec<HnItem>;
+    /// None if there's no user with the id
+    fn user(&mut self, id: &str) -> Result<Option<HnUser>, String>;
+    fn search(&mut self, query: &SearchQuery) -> Result<Vec<HnItem>, String>;
+    /// How many stories from the top of a feed are retrieved to filter it
+    fn feed_filter_stories(&self) -> usize;
+    fn hides_story(&self, item: &HnItem) -> bool;
+    fn hides_comment(&self, item: &HnItem) -> bool;
+    /// Called after each response, like for saving what was retrieved for the request
+    fn finish_request(&mut self) {}
+}
+
+///
+/// Page of stories, total and pages are counted from the filtered view and hidden tells how many of the
+/// stories on this page the killfile removed
+///
+#[derive(Serialize)]
+pub struct ListingPage {
+    pub page: usize,
+    pub per_page: usize,
+    pub pages: usize,
+    pub total: usize,
+    pub hidden: usize,
+    pub items: Vec<HnItem>,
+}
+
+#[derive(Serialize)]
+struct ApiError {
+    error: String,
+}
+
+pub struct ApiResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+impl ApiResponse {
+    pub fn json<T: Serialize>(value: &T) -> ApiResponse {
+        ApiResponse {
+            status: 200,
+            body: serde_json::to_string(value).unwrap(),
+        }
+    }
+
+    pub fn error(status: u16, message: &str) -> ApiResponse {
+        ApiResponse {
+            status: status,
+            body: serde_json::to_string(&ApiError { error: String::from(message) }).unwrap(),
+        }
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Bad Gateway",
+        }
+    }
+
+    pub fn to_http(&self) -> String {
+        format!("HTTP/1.1 {} {}\r\nContent-Type: application/json; charset=utf-8\r\nContent-Length: {}\r\n\
+                 Connection: close\r\n\r\n{}",
+                self.status,
+                self.reason(),
+                self.body.len(),
+                self.body)
+    }
+}
+
+pub fn handle_request(request: &ApiRequest, source: &mut ApiSource, now: i64) -> ApiResponse {
+    if request.method != "GET" {
+        return ApiResponse::error(405, &format!("method {} is not supported", request.method));
+    }
+    let route = match ApiRoute::parse(&request.path) {
+        Some(route) => route,
+        None => return ApiResponse::error(404, &format!("no such resource {}", request.path)),
+    };
+    let result = match route {
+        ApiRoute::Feed(feed) => get_feed(feed, request, source, now),
+        ApiRoute::Item(id) => get_item(id, source).map(|item| ApiResponse::json(&item)),
+        ApiRoute::ItemTree(id) => get_item_tree(id, source).map(|tree| ApiResponse::json(&tree)),
+        ApiRoute::User(ref id) => get_user(id, source).map(|user| ApiResponse::json(&user)),
+        ApiRoute::Search => search(request, source, now),
+    };
+    match result {
+        Ok(response) => response,
+        Err(response) => response,
+    }
+}
+
+fn get_feed(feed: HnFeed, request: &ApiRequest, source: &mut ApiSource, now: i64) -> Result<ApiResponse, ApiResponse> {
+    let paging = Paging::parse(request).map_err(|e| ApiResponse::error(400, &e))?;
+    let mut filters = parse_filters(request).map_err(|e| ApiResponse::error(400, &e))?;
+    match request.param("sort") {
+        Some(value) => filters.sort = FeedSort::parse(value).ok_or(ApiResponse::error(400, &format!("invalid sort {}", value)))?,
+        None => (),
+    }
+    let ids = source.feed_ids(feed).map_err(|e| ApiResponse::error(502, &e))?;
+    let mut items = HashMap::new();
+    let ids = if filters.is_active() {
+        // only the top of the feed is filtered, like in the cli
+        let ids: Vec<i32> = ids.into_iter().take(source.feed_filter_stories()).collect();
+        for id in ids.iter() {
+            match source.item(*id) {
+                Ok(Some(item)) => {
+                    items.insert(*id, item);
+                }
+                _ => (),
+            }
+        }
+        filters.apply(ids.iter().filter_map(|id| items.get(id)).collect(), now)
+    } else {
+        ids
+    };
+    let mut page_items = Vec::new();
+    for id in paging.ids_of_page(&ids) {
+        match items.remove(&id) {
+            Some(item) => page_items.push(item),
+            None => {
+                match source.item(id) {
+                    Ok(Some(item)) => page_items.push(item),
+                    _ => warn!("Could not retrieve item {} for the api", id),
+                }
+            }
+        }
+    }
+    Ok(ApiResponse::json(&create_listing_page(&paging, ids.len(), page_items, source)))
+}
+
+fn search(request: &ApiRequest, source: &mut ApiSource, now: i64) -> Result<ApiResponse, ApiResponse> {
+    let paging = Paging::parse(request).map_err(|e| ApiResponse::error(400, &e))?;
+    let filters = parse_filters(request).map_err(|e| ApiResponse::error(400, &e))?;
+    let query = SearchQuery::parse(&search_words(request), now).map_err(|e| ApiResponse::error(400, &e))?;
+    let hits = source.search(&query).map_err(|e| ApiResponse::error(502, &e))?;
+    let ids = filters.apply(hits.iter().collect(), now);
+    let mut hits: HashMap<i32, HnItem> = hits.into_iter().map(|item| (item.id, item)).collect();
+    let page_items = paging.ids_of_page(&ids).iter().filter_map(|id| hits.remove(id)).collect();
+    Ok(ApiResponse::json(&create_listing_page(&paging, ids.len(), page_items, source)))
+}
+
+fn create_listing_page(paging: &Paging, total: usize, items: Vec<HnItem>, source: &ApiSource) -> ListingPage {
+    let count = items.len();
+    let items: Vec<HnItem> = items.into_iter().filter(|item| !source.hides_story(item)).collect();
+    ListingPage {
+        page: paging.page,
+        per_page: paging.per_page,
+        pages: paging.page_count(total),
+        total: total,
+        hidden: count - items.len(),
+        items: items,
+    }
+}
+
+fn get_item(id: i32, source: &mut ApiSource) -> Result<HnItem, ApiResponse> {
+    match source.item(id) {
+        Ok(Some(item)) => Ok(item),
+        Ok(None) => Err(ApiResponse::error(404, &format!("no item {}", id))),
+        Err(e) => Err(ApiResponse::error(502, &e)),
+    }
+}
+
+///
+/// The item with all of its comments nested, comments hidden by the killfile are left out with their replies
+///
+fn get_item_tree(id: i32, source: &mut ApiSource) -> Result<HnItemTree, ApiResponse> {
+    let item = get_item(id, source)?;
+    let comments: Vec<HnItem> = source.comments(&item)
+        .into_iter()
+        .filter(|comment| !source.hides_comment(comment))
+        .collect();
+    Ok(HnItemTree::build(item, &comments))
+}
+
+fn get_user(id: &str, source: &mut ApiSource) -> Result<HnUser, ApiResponse> {
+    match source.user(id) {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(ApiResponse::error(404, &format!("no user {}", id))),
+        Err(e) => Err(ApiResponse::error(502, &e)),
+    }
+}
+
+///
+/// Serves the api until the listener fails, one connection at a time since the client of the app is not shared
+/// between threads
+///
+pub fn serve(listener: TcpListener, source: &mut ApiSource) -> io::Result<()> {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(e) = handle_connection(stream, source) {
+                    warn!("API connection failed {}", e);
+                }
+            }
+            Err(e) => warn!("Could not accept API connection {}", e),
+        }
+    }
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream, source: &mut ApiSource) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(REQUEST_TIMEOUT_SECS)))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // the headers are read off so closing the connection does not reset it before the response is read
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+    info!("API request {}", request_line.trim());
+    let response = match ApiRequest::parse(&request_line) {
+        Some(request) => handle_request(&request, source, ::chrono::Utc::now().timestamp()),
+        None => ApiResponse::error(400, "invalid request"),
+    };
+    source.finish_request();
+    writer.write_all(response.to_http().as_bytes())?;
+    writer.flush()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::fs::File;
+    use helpers::test_utils::read_item;
+
+    /// Source of the api tests, the Gemini and Gopher tests use it too
+    pub struct TestSource {
+        items: Vec<HnItem>,
+        searched: Option<String>,
+        filter_stories: usize,
+    }
+
+    ///
+    /// Stories 1-25 with scores of their ids where story 3 is by a killed author, and a comment 100 under story 1
+    ///
+    pub fn create_source() -> TestSource {
+        let story = read_item("res/test/item.json");
+        let mut items: Vec<HnItem> = (1..26)
+            .map(|id| {
+                let mut item = story.clone();
+                item.id = id;
+                item.score = Some(id);
+                item.kids = None;
+                if id == 3 {
+                    item.by = String::from("killed");
+                }
+                item
+            })
+            .collect();
+        let mut comment = read_item("res/test/children-item.json");
+        comment.id = 100;
+        comment.parent = Some(1);
+        items.push(comment);
+        TestSource {
+            items: items,
+            searched: None,
+            filter_stories: 100,
+        }
+    }
+
+    impl ApiSource for TestSource {
+        fn feed_ids(&mut self, feed: HnFeed) -> Result<Vec<i32>, String> {
+            match feed {
+                HnFeed::Top => Ok((1..26).collect()),
+                _ => Err(String::from("feed is down")),
+            }
+        }
+
+        fn item(&mut self, id: i32) -> Result<Option<HnItem>, String> {
+            Ok(self.items.iter().find(|item| item.id == id).cloned())
+        }
+
+        fn comments(&mut self, item: &HnItem) -> Vec<HnItem> {
+            self.items.iter().filter(|comment| comment.parent == Some(item.id)).cloned().collect()
+        }
+
+        fn user(&mut self, id: &str) -> Result<Option<HnUser>, String> {
+            let mut contents = String::new();
+            File::open("res/test/user.json").and_then(|mut file| file.read_to_string(&mut contents)).unwrap();
+            let user: HnUser = serde_json::from_str(&contents).unwrap();
+            Ok(if user.id == id { Some(user) } else { None })
+        }
+
+        fn search(&mut self, query: &SearchQuery) -> Result<Vec<HnItem>, String> {
+            self.searched = Some(query.text.clone());
+            Ok(self.items.iter().filter(|item| item.id <= 5).cloned().collect())
+        }
+
+        fn feed_filter_stories(&self) -> usize {
+            self.filter_stories
+        }
+
+        fn hides_story(&self, item: &HnItem) -> bool {
+            item.by == "killed"
+        }
+
+        fn hides_comment(&self, _item: &HnItem) -> bool {
+            false
+        }
+    }
+
+    fn get(target: &str, source: &mut TestSource) -> (u16, serde_json::Value) {
+        let request = ApiRequest::parse(&format!("GET {} HTTP/1.1", target)).unwrap();
+        let response = handle_request(&request, source, 1500000000);
+        (response.status, serde_json::from_str(&response.body).unwrap())
+    }
+
+    fn ids(value: &serde_json::Value) -> Vec<i64> {
+        value["items"].as_array().unwrap().iter().map(|item| item["id"].as_i64().unwrap()).collect()
+    }
+
+    #[test]
+    fn parse_request_test() {
+        let request = ApiRequest::parse("GET /search?q=rust%20async&tag=story&page=2 HTTP/1.1").unwrap();
+        assert_eq!("GET", request.method);
+        assert_eq!("/search", request.path);
+        assert_eq!(Some("rust async"), request.param("q"));
+        assert_eq!(vec!["rust", "async", "tag:story"], search_words(&request));
+        assert_eq!(Paging { page: 2, per_page: 10 }, Paging::parse(&request).unwrap());
+
+        assert!(ApiRequest::parse("GET /feeds/top").is_none());
+        assert!(ApiRequest::parse("GET feeds HTTP/1.1").is_none());
+    }
+
+    #[test]
+    fn parse_route_test() {
+        assert_eq!(Some(ApiRoute::Feed(HnFeed::Ask)), ApiRoute::parse("/feeds/ask"));
+        assert_eq!(Some(ApiRoute::Item(8863)), ApiRoute::parse("/items/8863"));
+        assert_eq!(Some(ApiRoute::ItemTree(8863)), ApiRoute::parse("/items/8863/tree/"));
+        assert_eq!(Some(ApiRoute::User(String::from("j l"))), ApiRoute::parse("/users/j%20l"));
+        assert_eq!(Some(ApiRoute::Search), ApiRoute::parse("/search"));
+        assert!(ApiRoute::parse("/feeds/worst").is_none());
+        assert!(ApiRoute::parse("/items/abc").is_none());
+        assert!(ApiRoute::parse("/").is_none());
+    }
+
+    #[test]
+    fn feed_paging_test() {
+        let mut source = create_source();
+        let (status, page) = get("/feeds/top", &mut source);
+        assert_eq!(200, status);
+        assert_eq!(vec![1, 2, 4, 5, 6, 7, 8, 9, 10], ids(&page));
+        assert_eq!(1, page["hidden"]);
+        assert_eq!(25, page["total"]);
+        assert_eq!(3, page["pages"]);
+
+        let (_, page) = get("/feeds/top?page=3&per_page=10", &mut source);
+        assert_eq!(vec![21, 22, 23, 24, 25], ids(&page));
+        let (_, page) = get("/feeds/top?page=4", &mut source);
+        assert!(ids(&page).is_empty());
+
+        assert_eq!(400, get("/feeds/top?page=0", &mut source).0);
+        assert_eq!(400, get("/feeds/top?per_page=1000", &mut source).0);
+        assert_eq!(400, get("/feeds/top?page=18446744073709551615", &mut source).0);
+        let (status, error) = get("/feeds/new", &mut source);
+        assert_eq!(502, status);
+        assert_eq!("feed is down", error["error"]);
+    }
+
+    #[test]
+    fn request_limit_test() {
+        use std::thread;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            // no newline ever, the server answers at the limit instead of waiting for the rest
+            stream.write_all(&vec![b'a'; MAX_REQUEST as usize]).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        });
+        let (stream, _) = listener.accept().unwrap();
+        handle_connection(stream, &mut create_source()).unwrap();
+        assert!(client.join().unwrap().starts_with("HTTP/1.1 400"));
+    }
+
+    #[test]
+    fn feed_filters_test() {
+        let mut source = create_source();
+        let (_, page) = get("/feeds/top?min-score=20&sort=score&per_page=3", &mut source);
+        assert_eq!(vec![25, 24, 23], ids(&page));
+        assert_eq!(6, page["total"]);
+        assert_eq!(2, page["pages"]);
+        assert_eq!(400, get("/feeds/top?sort=random", &mut source).0);
+        assert_eq!(400, get("/feeds/top?max-age=soon", &mut source).0);
+
+        source.filter_stories = 22;
+        let (_, page) = get("/feeds/top?min-score=20", &mut source);
+        assert_eq!(vec![20, 21, 22], ids(&page));
+    }
+
+    #[test]
+    fn items_and_users_test() {
+        let mut source = create_source();
+        let (status, item) = get("/items/2", &mut source);
+        assert_eq!(200, status);
+        assert_eq!("dhouston", item["by"]);
+        assert_eq!(404, get("/items/999", &mut source).0);
+
+        let (_, tree) = get("/items/1/tree", &mut source);
+        assert_eq!(1, tree["item"]["id"]);
+        assert_eq!(100, tree["replies"][0]["item"]["id"]);
+
+        let (status, user) = get("/users/jl", &mut source);
+        assert_eq!(200, status);
+        assert_eq!(3496, user["karma"]);
+        assert_eq!(404, get("/users/nobody", &mut source).0);
+    }
+
+    #[test]
+    fn search_test() {
+        let mut source = create_source();
+        let (status, page) = get("/search?q=dropbox&min-score=2", &mut source);
+        assert_eq!(200, status);
+        assert_eq!(Some(String::from("dropbox")), source.searched);
+        assert_eq!(vec![2, 4, 5], ids(&page));
+        assert_eq!(1, page["hidden"]);
+        assert_eq!(400, get("/search", &mut source).0);
+    }
+
+    #[test]
+    fn response_test() {
+        let mut source = create_source();
+        let request = ApiRequest::parse("POST /items/1 HTTP/1.1").unwrap();
+        assert_eq!(405, handle_request(&request, &mut source, 0).status);
+        let response = ApiResponse::error(404, "no item 1");
+        assert_eq!("HTTP/1.1 404 Not Found\r\nContent-Type: application/json; charset=utf-8\r\nContent-Length: 21\r\n\
+                    Connection: close\r\n\r\n{\"error\":\"no item 1\"}",
+                   response.to_http());
+    }
+}
@@ -0,0 +1,4 @@
+#![allow(dead_code)]
+pub mod nntp_server;
+pub mod http_api;
+pub mod smallnet_server;
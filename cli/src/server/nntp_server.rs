@@ -0,0 +1,426 @@
+use std::collections::BTreeMap;
+use std::collections::Bound;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use core::models::HnItem;
+use export::message::{self, Message};
+use formatting::formatter::FormatStr;
+
+/// Clients are served one at a time, a session which sends nothing for this long is closed for the next client
+const IDLE_TIMEOUT_SECS: u64 = 300;
+
+///
+/// Stories of a feed with their comments, article numbers are the item ids so they stay the same between loads
+///
+pub struct NewsGroup {
+    pub name: String,
+    pub articles: BTreeMap<i32, HnItem>,
+}
+
+impl NewsGroup {
+    pub fn new(name: &str, items: Vec<HnItem>) -> NewsGroup {
+        NewsGroup {
+            name: String::from(name),
+            articles: items.into_iter().map(|item| (item.id, item)).collect(),
+        }
+    }
+
+    pub fn low(&self) -> i32 {
+        self.articles.keys().next().cloned().unwrap_or(1)
+    }
+
+    pub fn high(&self) -> i32 {
+        self.articles.keys().next_back().cloned().unwrap_or(0)
+    }
+
+    pub fn message(&self, id: i32, formatter: &FormatStr) -> Option<Message> {
+        let item = self.articles.get(&id)?;
+        let references = message::find_references(id, &self.articles);
+        let thread_title = references.first()
+            .and_then(|story_id| self.articles.get(story_id))
+            .and_then(|story| story.title.clone())
+            .unwrap_or(String::new());
+        Some(message::create_message(item, &references, &thread_title, Some(&self.name), formatter))
+    }
+}
+
+///
+/// Where the newsgroups come from, the feeds retrieved with the client in the app
+///
+pub trait GroupSource {
+    /// Names and descriptions of the groups
+    fn group_names(&self) -> Vec<(String, String)>;
+    fn load_group(&mut self, name: &str) -> Option<NewsGroup>;
+}
+
+///
+/// Read-only NNTP session of one client, lines in and replies out so it can be driven without a socket
+///
+pub struct NntpSession<'a> {
+    source: &'a mut GroupSource,
+    formatter: &'a FormatStr,
+    groups: Vec<NewsGroup>,
+    current_group: Option<usize>,
+    current_article: Option<i32>,
+}
+
+pub struct NntpReply {
+    pub text: String,
+    pub close: bool,
+}
+
+enum ArticlePart {
+    Article,
+    Head,
+    Body,
+}
+
+impl<'a> NntpSession<'a> {
+    pub fn new(source: &'a mut GroupSource, formatter: &'a FormatStr) -> NntpSession<'a> {
+        NntpSession {
+            source: source,
+            formatter: formatter,
+            groups: Vec::new(),
+            current_group: None,
+            current_article: None,
+        }
+    }
+
+    pub fn greeting(&self) -> String {
+        String::from("201 hncli NNTP gateway ready, posting prohibited\r\n")
+    }
+
+    pub fn handle_line(&mut self, line: &str) -> NntpReply {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let command = words.first().map(|word| word.to_uppercase()).unwrap_or(String::new());
+        let argument = words.get(1).cloned();
+        let text = match command.as_str() {
+            "CAPABILITIES" => {
+                multiline("101 Capability list:", vec![String::from("VERSION 2"),
+                                                        String::from("READER"),
+                                                        String::from("OVER"),
+                                                        String::from("LIST ACTIVE NEWSGROUPS")])
+            }
+            "MODE" => String::from("201 Posting prohibited\r\n"),
+            "LIST" => self.handle_list(argument),
+            "GROUP" => self.handle_group(argument),
+            "ARTICLE" => self.handle_article(argument, ArticlePart::Article),
+            "HEAD" => self.handle_article(argument, ArticlePart::Head),
+            "BODY" => self.handle_article(argument, ArticlePart::Body),
+            "OVER" | "XOVER" => self.handle_over(argument),
+            "POST" | "IHAVE" => String::from("440 Posting not permitted\r\n"),
+            "QUIT" => {
+                return NntpReply {
+                    text: String::from("205 Closing connection\r\n"),
+                    close: true,
+                }
+            }
+            _ => String::from("500 Unknown command\r\n"),
+        };
+        NntpReply {
+            text: text,
+            close: false,
+        }
+    }
+
+    fn handle_list(&mut self, argument: Option<&str>) -> String {
+        let keyword = argument.map(|keyword| keyword.to_uppercase()).unwrap_or(String::from("ACTIVE"));
+        let names = self.source.group_names();
+        match keyword.as_str() {
+            "ACTIVE" => {
+                // groups are loaded on GROUP, until then they're listed as empty
+                let lines = names.iter()
+                    .map(|&(ref name, _)| match self.groups.iter().find(|group| &group.name == name) {
+                        Some(group) => format!("{} {} {} n", name, group.high(), group.low()),
+                        None => format!("{} 0 1 n", name),
+                    })
+                    .collect();
+                multiline("215 List of newsgroups follows", lines)
+            }
+            "NEWSGROUPS" => {
+                let lines = names.iter().map(|&(ref name, ref description)| format!("{}\t{}", name, description)).collect();
+                multiline("215 List of newsgroups follows", lines)
+            }
+            _ => String::from("501 Unsupported LIST keyword\r\n"),
+        }
+    }
+
+    fn handle_group(&mut self, argument: Option<&str>) -> String {
+        let name = match argument {
+            Some(name) => name,
+            None => return String::from("501 GROUP needs a newsgroup\r\n"),
+        };
+        if !self.source.group_names().iter().any(|&(ref group, _)| group == name) {
+            return String::from("411 No such newsgroup\r\n");
+        }
+        let group = match self.source.load_group(name) {
+            Some(group) => group,
+            None => return String::from("403 Could not retrieve the newsgroup\r\n"),
+        };
+        let reply = format!("211 {} {} {} {}\r\n", group.articles.len(), group.low(), group.high(), name);
+        self.current_article = group.articles.keys().next().cloned();
+        self.groups.retain(|loaded| loaded.name != name);
+        self.groups.push(group);
+        self.current_group = Some(self.groups.len() - 1);
+        reply
+    }
+
+    fn handle_article(&mut self, argument: Option<&str>, part: ArticlePart) -> String {
+        let (number, group_index) = match argument {
+            Some(arg) if arg.starts_with('<') => {
+                let id = match message::parse_message_id(arg) {
+                    Some(id) => id,
+                    None => return String::from("430 No such article\r\n"),
+                };
+                match self.groups.iter().position(|group| group.articles.contains_key(&id)) {
+                    Some(index) => (id, index),
+                    None => return String::from("430 No such article\r\n"),
+                }
+            }
+            Some(arg) => {
+                let group_index = match self.current_group {
+                    Some(index) => index,
+                    None => return String::from("412 No newsgroup selected\r\n"),
+                };
+                match arg.parse::<i32>() {
+                    Ok(number) if self.groups[group_index].articles.contains_key(&number) => {
+                        self.current_article = Some(number);
+                        (number, group_index)
+                    }
+                    _ => return String::from("423 No article with that number\r\n"),
+                }
+            }
+            None => {
+                match (self.current_group, self.current_article) {
+                    (None, _) => return String::from("412 No newsgroup selected\r\n"),
+                    (Some(_), None) => return String::from("420 No current article selected\r\n"),
+                    (Some(index), Some(number)) => (number, index),
+                }
+            }
+        };
+        let message = match self.groups[group_index].message(number, self.formatter) {
+            Some(message) => message,
+            None => return String::from("423 No article with that number\r\n"),
+        };
+        let message_id = message::message_id(number);
+        let (status, content) = match part {
+            ArticlePart::Article => (220, message.to_string()),
+            ArticlePart::Head => (221, message.head_to_string()),
+            ArticlePart::Body => (222, message.body.clone()),
+        };
+        multiline(&format!("{} {} {}", status, number, message_id),
+                  content.lines().map(|line| String::from(line)).collect())
+    }
+
+    fn handle_over(&mut self, argument: Option<&str>) -> String {
+        let group_index = match self.current_group {
+            Some(index) => index,
+            None => return String::from("412 No newsgroup selected\r\n"),
+        };
+        let group = &self.groups[group_index];
+        let (start, end) = match argument {
+            Some(range) => {
+                match parse_range(range, group.high()) {
+                    Some(range) => range,
+                    None => return String::from("501 Invalid range\r\n"),
+                }
+            }
+            None => {
+                match self.current_article {
+                    Some(number) => (number, number),
+                    None => return String::from("420 No current article selected\r\n"),
+                }
+            }
+        };
+        if start > end {
+            return String::from("423 No articles in that range\r\n");
+        }
+        let lines: Vec<String> = group.articles
+            .range((Bound::Included(start), Bound::Included(end)))
+            .filter_map(|(id, _)| group.message(*id, self.formatter).map(|message| create_overview(*id, &message)))
+            .collect();
+        if lines.is_empty() {
+            return String::from("423 No articles in that range\r\n");
+        }
+        multiline("224 Overview information follows", lines)
+    }
+}
+
+///
+/// Parses n, n- and n-m, open ranges end at the high water mark
+///
+fn parse_range(range: &str, high: i32) -> Option<(i32, i32)> {
+    match range.find('-') {
+        Some(i) => {
+            let start = range[..i].parse::<i32>().ok()?;
+            let end = if range[i + 1..].is_empty() { high } else { range[i + 1..].parse::<i32>().ok()? };
+            Some((start, end))
+        }
+        None => range.parse::<i32>().ok().map(|number| (number, number)),
+    }
+}
+
+fn create_overview(number: i32, message: &Message) -> String {
+    let field = |name: &str| message.header(name).unwrap_or("").replace('\t', " ");
+    let bytes = message.to_string().len();
+    format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            number,
+            field("Subject"),
+            field("From"),
+            field("Date"),
+            field("Message-ID"),
+            field("References"),
+            bytes,
+            message.line_count())
+}
+
+///
+/// Status line and the lines of the multi-line reply, dot-stuffed and terminated with a single dot
+///
+fn multiline(status: &str, lines: Vec<String>) -> String {
+    let mut reply = format!("{}\r\n", status);
+    for line in lines {
+        if line.starts_with('.') {
+            reply.push('.');
+        }
+        reply.push_str(&line);
+        reply.push_str("\r\n");
+    }
+    reply.push_str(".\r\n");
+    reply
+}
+
+///
+/// Serves the clients one at a time, the client of the app is not shared between threads, so a newsreader
+/// which opens a second connection waits until the first is closed or idle for IDLE_TIMEOUT_SECS
+///
+pub fn serve(listener: TcpListener, source: &mut GroupSource, formatter: &FormatStr) -> io::Result<()> {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(e) = handle_connection(stream, source, formatter) {
+                    warn!("NNTP connection failed {}", e);
+                }
+            }
+            Err(e) => warn!("Could not accept NNTP connection {}", e),
+        }
+    }
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream, source: &mut GroupSource, formatter: &FormatStr) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(IDLE_TIMEOUT_SECS)))?;
+    let mut writer = stream.try_clone()?;
+    let reader = BufReader::new(stream);
+    let mut session = NntpSession::new(source, formatter);
+    writer.write_all(session.greeting().as_bytes())?;
+    for line in reader.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
+                info!("NNTP session idle for {}s, closing", IDLE_TIMEOUT_SECS);
+                return writer.write_all(b"400 Idle timeout, closing connection\r\n");
+            }
+            Err(e) => return Err(e),
+        };
+        info!("NNTP command {}", line);
+        let reply = session.handle_line(&line);
+        writer.write_all(reply.text.as_bytes())?;
+        if reply.close {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use formatting::formatter::Formatters;
+    use helpers::test_utils::read_item;
+
+    struct TestSource;
+
+    impl GroupSource for TestSource {
+        fn group_names(&self) -> Vec<(String, String)> {
+            vec![(String::from("hn.top"), String::from("Top stories")),
+                 (String::from("hn.new"), String::from("Newest stories"))]
+        }
+
+        fn load_group(&mut self, name: &str) -> Option<NewsGroup> {
+            let mut story = read_item("res/test/item.json");
+            story.id = 14626342;
+            let comment = read_item("res/test/children-item.json");
+            Some(NewsGroup::new(name, vec![story, comment]))
+        }
+    }
+
+    #[test]
+    fn group_and_over_test() {
+        let mut source = TestSource;
+        let formatter = Formatters::new();
+        let mut session = NntpSession::new(&mut source, &formatter);
+        assert_eq!("412 No newsgroup selected\r\n", session.handle_line("OVER 1-").text);
+        assert_eq!("411 No such newsgroup\r\n", session.handle_line("GROUP hn.nope").text);
+        assert_eq!("211 2 14626342 14626530 hn.top\r\n", session.handle_line("group hn.top").text);
+        assert_eq!("215 List of newsgroups follows\r\nhn.top 14626530 14626342 n\r\nhn.new 0 1 n\r\n.\r\n",
+                   session.handle_line("LIST").text);
+
+        let over = session.handle_line("XOVER 14626342-").text;
+        let lines: Vec<&str> = over.split("\r\n").collect();
+        assert_eq!("224 Overview information follows", lines[0]);
+        assert!(lines[1].starts_with("14626342\tMy YC app: Dropbox"));
+        let fields: Vec<&str> = lines[2].split('\t').collect();
+        assert_eq!("Re: My YC app: Dropbox - Throw away your USB drive", fields[1]);
+        assert_eq!("<14626530@news.ycombinator.com>", fields[4]);
+        assert_eq!("<14626342@news.ycombinator.com>", fields[5]);
+        assert_eq!(".", lines[3]);
+        assert_eq!("423 No articles in that range\r\n", session.handle_line("OVER 1-5").text);
+        assert!(session.handle_line("XOVER 1-2147483647").text.starts_with("224 "));
+        assert_eq!("423 No articles in that range\r\n", session.handle_line("OVER 2147483647").text);
+    }
+
+    #[test]
+    fn article_test() {
+        let mut source = TestSource;
+        let formatter = Formatters::new();
+        let mut session = NntpSession::new(&mut source, &formatter);
+        assert_eq!("412 No newsgroup selected\r\n", session.handle_line("ARTICLE 1").text);
+        session.handle_line("GROUP hn.top");
+
+        let head = session.handle_line("HEAD 14626530").text;
+        assert!(head.starts_with("221 14626530 <14626530@news.ycombinator.com>\r\nFrom: cholantesh"));
+        assert!(head.contains("References: <14626342@news.ycombinator.com>\r\n"));
+        assert!(head.ends_with("\r\n.\r\n"));
+
+        let body = session.handle_line("BODY").text;
+        assert!(body.starts_with("222 14626530 <14626530@news.ycombinator.com>\r\n"));
+        assert!(body.contains("is not a valid concern"));
+
+        let article = session.handle_line("ARTICLE <14626342@news.ycombinator.com>").text;
+        assert!(article.starts_with("220 14626342"));
+        assert!(article.contains("X-HN-URL: https://news.ycombinator.com/item?id=14626342\r\n\r\nhttp://www.getdropbox.com"));
+        assert_eq!("423 No article with that number\r\n", session.handle_line("ARTICLE 1").text);
+        assert_eq!("430 No such article\r\n", session.handle_line("ARTICLE <1@example.com>").text);
+    }
+
+    #[test]
+    fn other_commands_test() {
+        let mut source = TestSource;
+        let formatter = Formatters::new();
+        let mut session = NntpSession::new(&mut source, &formatter);
+        assert_eq!("215 List of newsgroups follows\r\nhn.top\tTop stories\r\nhn.new\tNewest stories\r\n.\r\n",
+                   session.handle_line("LIST NEWSGROUPS").text);
+        assert_eq!("201 Posting prohibited\r\n", session.handle_line("MODE READER").text);
+        assert_eq!("440 Posting not permitted\r\n", session.handle_line("POST").text);
+        assert_eq!("500 Unknown command\r\n", session.handle_line("FROB").text);
+        assert!(session.handle_line("QUIT").close);
+    }
+
+    #[test]
+    fn multiline_test() {
+        assert_eq!("220 1\r\n..dot\r\nline\r\n.\r\n",
+                   multiline("220 1", vec![String::from(".dot"), String::from("line")]));
+    }
+}
@@ -0,0 +1,353 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use native_tls::{Pkcs12, TlsAcceptor};
+use url::Url;
+use core::models::{HnFeed, HnItem, HnItemTree};
+use export::{gemtext_writer, gophermap_writer};
+use export::gophermap_writer::GopherHost;
+use formatting::formatter::FormatStr;
+use super::http_api::{ApiSource, Paging};
+
+const STORIES_PER_PAGE: usize = 10;
+/// Gemini requests are at most 1024 bytes of url and CRLF
+const MAX_GEMINI_REQUEST: u64 = 1026;
+/// Selector and search of a Gopher request, longer requests are cut
+const MAX_GOPHER_REQUEST: u64 = 1024;
+/// Connections are served one at a time so a client which sends nothing can't hold the others for longer
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+
+///
+/// What a selector or path of the Gemini and Gopher servers shows, they have the same pages:
+/// / for the feeds, /feeds/[kind] and /feeds/[kind]/[page] for the listings and /items/[id] for the threads
+///
+pub enum SmallnetPage {
+    Feeds,
+    Listing {
+        feed: HnFeed,
+        page: usize,
+        items: Vec<HnItem>,
+        has_next: bool,
+    },
+    Thread(HnItemTree),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PageError {
+    NotFound,
+    Failed(String),
+}
+
+impl SmallnetPage {
+    pub fn title(&self) -> String {
+        match *self {
+            SmallnetPage::Feeds => String::from("Hacker News"),
+            SmallnetPage::Listing { feed, page, .. } => format!("Hacker News {}, page {}", feed.name(), page),
+            SmallnetPage::Thread(ref tree) => tree.item.title.clone().unwrap_or(String::from("Hacker News")),
+        }
+    }
+
+    pub fn next_page_path(&self) -> Option<String> {
+        match *self {
+            SmallnetPage::Listing { feed, page, has_next, .. } if has_next => {
+                Some(format!("/feeds/{}/{}", feed.name(), page + 1))
+            }
+            _ => None,
+        }
+    }
+}
+
+///
+/// Loads the page of the path, stories and comments hidden by the killfile are left out
+///
+pub fn load_page(path: &str, source: &mut ApiSource) -> Result<SmallnetPage, PageError> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').filter(|segment| !segment.is_empty()).collect();
+    let name = segments.get(0).cloned().unwrap_or("");
+    match (name, segments.len()) {
+        ("", 0) => Ok(SmallnetPage::Feeds),
+        ("feeds", 2) | ("feeds", 3) => {
+            let feed = HnFeed::parse(segments[1]).ok_or(PageError::NotFound)?;
+            let page = match segments.get(2) {
+                // the first story of the page has to fit in usize like in Paging::parse
+                Some(page) => {
+                    page.parse::<usize>()
+                        .ok()
+                        .and_then(|page| if page > 0 { (page - 1).checked_mul(STORIES_PER_PAGE).map(|_| page) } else { None })
+                }
+                None => Some(1),
+            };
+            load_listing(feed, page.ok_or(PageError::NotFound)?, source)
+        }
+        ("items", 2) => {
+            let id = segments[1].parse::<i32>().map_err(|_| PageError::NotFound)?;
+            let item = source.item(id).map_err(PageError::Failed)?.ok_or(PageError::NotFound)?;
+            let comments: Vec<HnItem> = source.comments(&item)
+                .into_iter()
+                .filter(|comment| !source.hides_comment(comment))
+                .collect();
+            Ok(SmallnetPage::Thread(HnItemTree::build(item, &comments)))
+        }
+        _ => Err(PageError::NotFound),
+    }
+}
+
+fn load_listing(feed: HnFeed, page: usize, source: &mut ApiSource) -> Result<SmallnetPage, PageError> {
+    let paging = Paging {
+        page: page,
+        per_page: STORIES_PER_PAGE,
+    };
+    let ids = source.feed_ids(feed).map_err(PageError::Failed)?;
+    let mut items = Vec::new();
+    for id in paging.ids_of_page(&ids) {
+        match source.item(id) {
+            Ok(Some(ref item)) if source.hides_story(item) => (),
+            Ok(Some(item)) => items.push(item),
+            _ => warn!("Could not retrieve item {} for the page", id),
+        }
+    }
+    Ok(SmallnetPage::Listing {
+        feed: feed,
+        page: page,
+        items: items,
+        has_next: paging.page_count(ids.len()) > page,
+    })
+}
+
+pub fn render_gemtext(page: &SmallnetPage, formatter: &FormatStr) -> String {
+    match *page {
+        SmallnetPage::Feeds => {
+            let mut gemtext = format!("# {}\n\n", page.title());
+            for feed in HnFeed::all() {
+                gemtext.push_str(&format!("=> /feeds/{} {} stories\n", feed.name(), feed.name()));
+            }
+            gemtext
+        }
+        SmallnetPage::Listing { page: number, ref items, .. } => {
+            let next = page.next_page_path();
+            gemtext_writer::write_listing(&page.title(),
+                                          items,
+                                          (number - 1) * STORIES_PER_PAGE + 1,
+                                          next.as_ref().map(|path| path.as_str()))
+        }
+        SmallnetPage::Thread(ref tree) => gemtext_writer::write_thread(tree, formatter),
+    }
+}
+
+pub fn render_gophermap(page: &SmallnetPage, formatter: &FormatStr, host: &GopherHost) -> String {
+    match *page {
+        SmallnetPage::Feeds => {
+            let mut map = gophermap_writer::info_line(&page.title());
+            map.push_str(&gophermap_writer::info_line(""));
+            for feed in HnFeed::all() {
+                map.push_str(&gophermap_writer::menu_line('1',
+                                                          &format!("{} stories", feed.name()),
+                                                          &format!("/feeds/{}", feed.name()),
+                                                          host));
+            }
+            map.push_str(".\r\n");
+            map
+        }
+        SmallnetPage::Listing { page: number, ref items, .. } => {
+            let next = page.next_page_path();
+            gophermap_writer::write_listing(&page.title(),
+                                            items,
+                                            (number - 1) * STORIES_PER_PAGE + 1,
+                                            next.as_ref().map(|path| path.as_str()),
+                                            host)
+        }
+        SmallnetPage::Thread(ref tree) => gophermap_writer::write_thread(tree, formatter, host),
+    }
+}
+
+///
+/// Response to a Gemini request line which is the absolute url of the page
+///
+pub fn handle_gemini_request(request: &str, source: &mut ApiSource, formatter: &FormatStr) -> String {
+    let url = match Url::parse(request.trim_right_matches(|c| c == '\r' || c == '\n')) {
+        Ok(ref url) if url.scheme() == "gemini" => url.clone(),
+        _ => return String::from("59 Bad request\r\n"),
+    };
+    let response = match load_page(url.path(), source) {
+        Ok(page) => format!("20 text/gemini; charset=utf-8\r\n{}", render_gemtext(&page, formatter)),
+        Err(PageError::NotFound) => String::from("51 Not found\r\n"),
+        Err(PageError::Failed(e)) => format!("40 {}\r\n", e),
+    };
+    source.finish_request();
+    response
+}
+
+///
+/// Response to a Gopher selector, a search after a tab is ignored
+///
+pub fn handle_gopher_request(selector: &str, source: &mut ApiSource, formatter: &FormatStr, host: &GopherHost) -> String {
+    let selector = selector.trim_right_matches(|c| c == '\r' || c == '\n').split('\t').next().unwrap_or("");
+    let response = match load_page(selector, source) {
+        Ok(page) => render_gophermap(&page, formatter, host),
+        Err(PageError::NotFound) => error_menu(&format!("Not found {}", selector)),
+        Err(PageError::Failed(e)) => error_menu(&e),
+    };
+    source.finish_request();
+    response
+}
+
+fn error_menu(message: &str) -> String {
+    format!("3{}\t\terror.host\t1\r\n.\r\n", message.replace('\t', " "))
+}
+
+///
+/// Identity of the Gemini server from a PKCS #12 archive, usually with a self-signed certificate
+///
+pub fn create_acceptor(der: &[u8], password: &str) -> Result<TlsAcceptor, String> {
+    let pkcs12 = Pkcs12::from_der(der, password).map_err(|e| format!("invalid identity {}", e))?;
+    TlsAcceptor::builder(pkcs12).and_then(|builder| builder.build()).map_err(|e| e.to_string())
+}
+
+///
+/// Serves Gemini over TLS one connection at a time, the client of the app is not shared between threads
+///
+pub fn serve_gemini(listener: TcpListener, acceptor: TlsAcceptor, source: &mut ApiSource, formatter: &FormatStr) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Could not accept Gemini connection {}", e);
+                continue;
+            }
+        };
+        // the handshake reads from the client too
+        if let Err(e) = stream.set_read_timeout(Some(Duration::from_secs(REQUEST_TIMEOUT_SECS))) {
+            warn!("Gemini connection failed {}", e);
+            continue;
+        }
+        match acceptor.accept(stream) {
+            Ok(mut stream) => {
+                let result = handle_gemini_connection(&mut stream, source, formatter).and_then(|_| stream.shutdown());
+                if let Err(e) = result {
+                    warn!("Gemini connection failed {}", e);
+                }
+            }
+            Err(e) => warn!("Gemini handshake failed {}", e),
+        }
+    }
+    Ok(())
+}
+
+fn handle_gemini_connection<S: Read + Write>(stream: &mut S, source: &mut ApiSource, formatter: &FormatStr) -> io::Result<()> {
+    let mut request = String::new();
+    BufReader::new((&mut *stream).take(MAX_GEMINI_REQUEST)).read_line(&mut request)?;
+    info!("Gemini request {}", request.trim());
+    stream.write_all(handle_gemini_request(&request, source, formatter).as_bytes())?;
+    stream.flush()
+}
+
+///
+/// Serves Gopher one connection at a time, host is what the menus point back to
+///
+pub fn serve_gopher(listener: TcpListener, host: &GopherHost, source: &mut ApiSource, formatter: &FormatStr) -> io::Result<()> {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(e) = handle_gopher_connection(stream, host, source, formatter) {
+                    warn!("Gopher connection failed {}", e);
+                }
+            }
+            Err(e) => warn!("Could not accept Gopher connection {}", e),
+        }
+    }
+    Ok(())
+}
+
+fn handle_gopher_connection(stream: TcpStream, host: &GopherHost, source: &mut ApiSource, formatter: &FormatStr) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(REQUEST_TIMEOUT_SECS)))?;
+    let mut writer = stream.try_clone()?;
+    let mut selector = String::new();
+    BufReader::new(stream.take(MAX_GOPHER_REQUEST)).read_line(&mut selector)?;
+    info!("Gopher request {}", selector.trim());
+    writer.write_all(handle_gopher_request(&selector, source, formatter, host).as_bytes())?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use formatting::formatter::Formatters;
+    use server::http_api::tests::create_source;
+
+    fn listing_ids(page: &SmallnetPage) -> Vec<i32> {
+        match *page {
+            SmallnetPage::Listing { ref items, .. } => items.iter().map(|item| item.id).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn load_page_test() {
+        let mut source = create_source();
+        assert!(match load_page("/", &mut source) { Ok(SmallnetPage::Feeds) => true, _ => false });
+        let page = load_page("/feeds/top", &mut source).unwrap();
+        assert_eq!(vec![1, 2, 4, 5, 6, 7, 8, 9, 10], listing_ids(&page));
+        assert_eq!(Some(String::from("/feeds/top/2")), page.next_page_path());
+        let page = load_page("/feeds/top/3", &mut source).unwrap();
+        assert_eq!(vec![21, 22, 23, 24, 25], listing_ids(&page));
+        assert!(page.next_page_path().is_none());
+        assert!(match load_page("/items/3", &mut source) { Ok(SmallnetPage::Thread(_)) => true, _ => false });
+
+        assert_eq!(Some(PageError::NotFound), load_page("/feeds/top/0", &mut source).err());
+        assert_eq!(Some(PageError::NotFound), load_page("/feeds/top/18446744073709551615", &mut source).err());
+        assert_eq!(Some(PageError::NotFound), load_page("/items/99", &mut source).err());
+        assert_eq!(Some(PageError::NotFound), load_page("/users/jl", &mut source).err());
+        assert_eq!(Some(PageError::Failed(String::from("feed is down"))), load_page("/feeds/new", &mut source).err());
+    }
+
+    #[test]
+    fn gemini_request_test() {
+        let mut source = create_source();
+        let formatter = Formatters::without_wrapping();
+        let response = handle_gemini_request("gemini://localhost/feeds/top/2\r\n", &mut source, &formatter);
+        assert!(response.starts_with("20 text/gemini; charset=utf-8\r\n# Hacker News top, page 2\n\n=> http://"));
+        assert!(response.contains(" 11. My YC app"));
+        let response = handle_gemini_request("gemini://localhost/\r\n", &mut source, &formatter);
+        assert!(response.contains("=> /feeds/ask ask stories\n"));
+        assert_eq!("51 Not found\r\n", handle_gemini_request("gemini://localhost/items/99\r\n", &mut source, &formatter));
+        assert_eq!("40 feed is down\r\n", handle_gemini_request("gemini://localhost/feeds/new\r\n", &mut source, &formatter));
+        assert_eq!("59 Bad request\r\n", handle_gemini_request("http://localhost/\r\n", &mut source, &formatter));
+    }
+
+    #[test]
+    fn gopher_request_test() {
+        let mut source = create_source();
+        let host = GopherHost {
+            host: String::from("localhost"),
+            port: 7070,
+        };
+        let formatter = Formatters::new();
+        let response = handle_gopher_request("\r\n", &mut source, &formatter, &host);
+        assert!(response.contains("1top stories\t/feeds/top\tlocalhost\t7070\r\n"));
+        let response = handle_gopher_request("/items/1\tsearch\r\n", &mut source, &formatter, &host);
+        assert!(response.starts_with("iMy YC app: Dropbox"));
+        let response = handle_gopher_request("/nothing\r\n", &mut source, &formatter, &host);
+        assert_eq!("3Not found /nothing\t\terror.host\t1\r\n.\r\n", response);
+    }
+
+    #[test]
+    fn gopher_request_limit_test() {
+        use std::thread;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            // no newline ever, the server answers at the limit instead of waiting for the rest
+            stream.write_all(&vec![b'a'; MAX_GOPHER_REQUEST as usize]).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        });
+        let host = GopherHost {
+            host: String::from("localhost"),
+            port: 7070,
+        };
+        let (stream, _) = listener.accept().unwrap();
+        handle_gopher_connection(stream, &host, &mut create_source(), &Formatters::new()).unwrap();
+        assert!(client.join().unwrap().starts_with("3Not found aaa"));
+    }
+}
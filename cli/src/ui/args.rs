@@ -0,0 +1,309 @@
+use core::models::HnFeed;
+use helpers::gen_utils;
+
+const DEFAULT_LIVE_INTERVAL_SECS: u64 = 30;
+const DEFAULT_FEED_LIMIT: usize = 30;
+const DEFAULT_DIGEST_LIMIT: usize = 10;
+const DEFAULT_NNTP_ADDRESS: &'static str = "127.0.0.1:1119";
+const DEFAULT_API_ADDRESS: &'static str = "127.0.0.1:8080";
+const DEFAULT_GEMINI_ADDRESS: &'static str = "127.0.0.1:1965";
+const DEFAULT_GOPHER_ADDRESS: &'static str = "127.0.0.1:7070";
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum AppMode {
+    Interactive,
+    WatchDaemon,
+    Alerts,
+    /// Runs the commands of a script file and exits
+    Script,
+    /// Prints a listing as RSS or Atom and exits
+    FeedExport,
+    /// Serves the feeds as newsgroups over NNTP
+    Nntp,
+    /// Serves a JSON api over HTTP
+    Serve,
+    /// Writes the top stories with their comments as a static site and exits
+    Digest,
+    /// Prints every new story or comment as it appears
+    Stream,
+}
+
+///
+/// What serve mode serves the feeds over
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ServeProtocol {
+    Http,
+    Gemini,
+    Gopher,
+}
+
+///
+/// Arguments given to the application from command line
+///
+pub struct AppArgs {
+    pub mode: AppMode,
+    pub live_updates: bool,
+    pub live_interval_secs: u64,
+    /// Comments are shown without formatting
+    pub raw_comments: bool,
+    pub run_once: bool,
+    pub script: Option<String>,
+    pub continue_on_error: bool,
+    /// File the output is written to instead of stdout
+    pub output: Option<String>,
+    /// Feed name, user [id] or search [query] to export
+    pub feed_source: Vec<String>,
+    pub feed_format: String,
+    /// How many top comments are included in each entry
+    pub feed_comments: usize,
+    pub feed_limit: usize,
+    /// Comma separated columns of CSV and TSV exports
+    pub fields: Option<String>,
+    /// Address the servers listen on, each server has its own default
+    pub listen: Option<String>,
+    pub serve_protocol: ServeProtocol,
+    /// Directory the digest site is written to
+    pub site_dir: Option<String>,
+    /// Items streamed, "new" for stories and comments or "stories" for stories only
+    pub stream_source: Option<String>,
+    /// Stream items as JSON lines instead of text
+    pub ndjson: bool,
+    /// Id the stream continues after instead of its checkpoint
+    pub stream_from: Option<i32>,
+}
+
+impl AppArgs {
+    pub fn new() -> AppArgs {
+        AppArgs {
+            mode: AppMode::Interactive,
+            live_updates: false,
+            live_interval_secs: DEFAULT_LIVE_INTERVAL_SECS,
+            raw_comments: false,
+            run_once: false,
+            script: None,
+            continue_on_error: false,
+            output: None,
+            feed_source: Vec::new(),
+            feed_format: String::from("rss"),
+            feed_comments: 0,
+            feed_limit: DEFAULT_FEED_LIMIT,
+            fields: None,
+            listen: None,
+            serve_protocol: ServeProtocol::Http,
+            site_dir: None,
+            stream_source: None,
+            ndjson: false,
+            stream_from: None,
+        }
+    }
+
+    pub fn parse<I: Iterator<Item = String>>(args: I) -> AppArgs {
+        let mut app_args = AppArgs::new();
+        let mut args = args.skip(1).peekable(); // first one is the binary
+        let mode = match args.peek().map(|arg| arg.as_str()) {
+            Some("watch") => Some(AppMode::WatchDaemon),
+            Some("alerts") => Some(AppMode::Alerts),
+            Some("feed") => Some(AppMode::FeedExport),
+            Some("nntp") => Some(AppMode::Nntp),
+            Some("serve") => Some(AppMode::Serve),
+            Some("digest") => Some(AppMode::Digest),
+            Some("stream") => Some(AppMode::Stream),
+            _ => None,
+        };
+        // hncli top --format csv is the same as hncli feed top --format csv
+        let feed_shorthand = args.peek().map(|arg| HnFeed::parse(arg).is_some()).unwrap_or(false);
+        if feed_shorthand {
+            app_args.mode = AppMode::FeedExport;
+        } else if let Some(mode) = mode {
+            args.next();
+            if mode == AppMode::Digest {
+                app_args.feed_limit = DEFAULT_DIGEST_LIMIT;
+            }
+            app_args.mode = mode;
+        }
+        while let Some(arg) = args.next() {
+            if arg == "--live" {
+                app_args.live_updates = true;
+            } else if arg == "--raw" {
+                app_args.raw_comments = true;
+            } else if arg == "--once" {
+                app_args.run_once = true;
+            } else if arg == "--script" {
+                match args.next() {
+                    Some(path) => {
+                        app_args.mode = AppMode::Script;
+                        app_args.script = Some(path);
+                    }
+                    None => warn!("Missing file for --script"),
+                }
+            } else if arg == "--output" {
+                match args.next() {
+                    Some(path) => app_args.output = Some(path),
+                    None => warn!("Missing file for --output"),
+                }
+            } else if arg == "--continue-on-error" {
+                app_args.continue_on_error = true;
+            } else if arg == "--listen" {
+                match args.next() {
+                    Some(address) => app_args.listen = Some(address),
+                    None => warn!("Missing address for --listen"),
+                }
+            } else if arg == "--port" {
+                match args.next().and_then(|port| port.parse::<u16>().ok()) {
+                    Some(port) => app_args.listen = Some(format!("127.0.0.1:{}", port)),
+                    None => warn!("Invalid value for --port"),
+                }
+            } else if arg == "--gemini" {
+                app_args.serve_protocol = ServeProtocol::Gemini;
+            } else if arg == "--gopher" {
+                app_args.serve_protocol = ServeProtocol::Gopher;
+            } else if arg == "--fields" {
+                match args.next() {
+                    Some(fields) => app_args.fields = Some(fields),
+                    None => warn!("Missing value for --fields"),
+                }
+            } else if arg == "--out" {
+                match args.next() {
+                    Some(dir) => app_args.site_dir = Some(dir),
+                    None => warn!("Missing directory for --out"),
+                }
+            } else if arg == "--ndjson" {
+                app_args.ndjson = true;
+            } else if arg == "--from" {
+                match args.next().and_then(|id| id.parse::<i32>().ok()) {
+                    Some(id) => app_args.stream_from = Some(id),
+                    None => warn!("Invalid value for --from"),
+                }
+            } else if arg == "--format" {
+                match args.next() {
+                    Some(format) => app_args.feed_format = format,
+                    None => warn!("Missing value for --format"),
+                }
+            } else if arg == "--comments" || arg == "--limit" {
+                let value = gen_utils::try_to_parse_number(args.next().as_ref().map(|s| s.as_str()));
+                match value {
+                    Some(n) if arg == "--comments" => app_args.feed_comments = n,
+                    Some(n) if n > 0 => app_args.feed_limit = n,
+                    _ => warn!("Invalid value for {}", arg),
+                }
+            } else if (app_args.mode == AppMode::FeedExport || app_args.mode == AppMode::Digest) &&
+                      !arg.starts_with("--") {
+                app_args.feed_source.push(arg);
+            } else if app_args.mode == AppMode::Stream && app_args.stream_source.is_none() && !arg.starts_with("--") {
+                app_args.stream_source = Some(arg);
+            } else if arg == "--live-interval" {
+                let interval = gen_utils::try_to_parse_number(args.next().as_ref().map(|s| s.as_str()));
+                match interval {
+                    Some(secs) if secs > 0 => app_args.live_interval_secs = secs as u64,
+                    _ => warn!("Invalid value for --live-interval, using {} seconds", DEFAULT_LIVE_INTERVAL_SECS),
+                }
+            } else {
+                warn!("Unknown argument {}", arg);
+            }
+        }
+        app_args
+    }
+
+    pub fn listen_address(&self) -> String {
+        match self.listen {
+            Some(ref address) => address.clone(),
+            None if self.mode == AppMode::Nntp => String::from(DEFAULT_NNTP_ADDRESS),
+            None => {
+                match self.serve_protocol {
+                    ServeProtocol::Http => String::from(DEFAULT_API_ADDRESS),
+                    ServeProtocol::Gemini => String::from(DEFAULT_GEMINI_ADDRESS),
+                    ServeProtocol::Gopher => String::from(DEFAULT_GOPHER_ADDRESS),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(|s| String::from(s)).collect()
+    }
+
+    #[test]
+    fn parse_args_test() {
+        let args = AppArgs::parse(to_args("hncli").into_iter());
+        assert_eq!(AppMode::Interactive, args.mode);
+        assert!(!args.live_updates);
+        assert_eq!(DEFAULT_LIVE_INTERVAL_SECS, args.live_interval_secs);
+
+        let args = AppArgs::parse(to_args("hncli --live --live-interval 5 --raw").into_iter());
+        assert!(args.live_updates);
+        assert!(args.raw_comments);
+        assert_eq!(5, args.live_interval_secs);
+
+        let args = AppArgs::parse(to_args("hncli --live-interval abc").into_iter());
+        assert_eq!(DEFAULT_LIVE_INTERVAL_SECS, args.live_interval_secs);
+
+        let args = AppArgs::parse(to_args("hncli watch").into_iter());
+        assert_eq!(AppMode::WatchDaemon, args.mode);
+
+        let args = AppArgs::parse(to_args("hncli alerts --once").into_iter());
+        assert_eq!(AppMode::Alerts, args.mode);
+        assert!(args.run_once);
+
+        let args = AppArgs::parse(to_args("hncli --script daily.hn --output report.txt --continue-on-error").into_iter());
+        assert_eq!(AppMode::Script, args.mode);
+        assert_eq!(Some(String::from("daily.hn")), args.script);
+        assert_eq!(Some(String::from("report.txt")), args.output);
+        assert!(args.continue_on_error);
+
+        let args = AppArgs::parse(to_args("hncli feed top --format atom --comments 3").into_iter());
+        assert_eq!(AppMode::FeedExport, args.mode);
+        assert_eq!(vec!["top"], args.feed_source);
+        assert_eq!("atom", args.feed_format);
+        assert_eq!(3, args.feed_comments);
+        assert_eq!(DEFAULT_FEED_LIMIT, args.feed_limit);
+
+        let args = AppArgs::parse(to_args("hncli feed search rust async --limit 10").into_iter());
+        assert_eq!(vec!["search", "rust", "async"], args.feed_source);
+        assert_eq!(10, args.feed_limit);
+
+        let args = AppArgs::parse(to_args("hncli top --format csv --fields rank,id,title").into_iter());
+        assert_eq!(AppMode::FeedExport, args.mode);
+        assert_eq!(vec!["top"], args.feed_source);
+        assert_eq!("csv", args.feed_format);
+        assert_eq!(Some(String::from("rank,id,title")), args.fields);
+
+        let args = AppArgs::parse(to_args("hncli nntp --listen 127.0.0.1:2119").into_iter());
+        assert_eq!(AppMode::Nntp, args.mode);
+        assert_eq!("127.0.0.1:2119", args.listen_address());
+        assert_eq!(DEFAULT_NNTP_ADDRESS, AppArgs::parse(to_args("hncli nntp").into_iter()).listen_address());
+
+        let args = AppArgs::parse(to_args("hncli serve").into_iter());
+        assert_eq!(AppMode::Serve, args.mode);
+        assert_eq!(ServeProtocol::Http, args.serve_protocol);
+        assert_eq!(DEFAULT_API_ADDRESS, args.listen_address());
+        let args = AppArgs::parse(to_args("hncli serve --port 9000").into_iter());
+        assert_eq!("127.0.0.1:9000", args.listen_address());
+        let args = AppArgs::parse(to_args("hncli serve --gemini").into_iter());
+        assert_eq!(ServeProtocol::Gemini, args.serve_protocol);
+        assert_eq!(DEFAULT_GEMINI_ADDRESS, args.listen_address());
+        let args = AppArgs::parse(to_args("hncli serve --port 70 --gopher").into_iter());
+        assert_eq!(ServeProtocol::Gopher, args.serve_protocol);
+        assert_eq!("127.0.0.1:70", args.listen_address());
+
+        let args = AppArgs::parse(to_args("hncli digest best --out site/").into_iter());
+        assert_eq!(AppMode::Digest, args.mode);
+        assert_eq!(vec!["best"], args.feed_source);
+        assert_eq!(Some(String::from("site/")), args.site_dir);
+        assert_eq!(DEFAULT_DIGEST_LIMIT, args.feed_limit);
+
+        let args = AppArgs::parse(to_args("hncli stream new --ndjson --from 14626530").into_iter());
+        assert_eq!(AppMode::Stream, args.mode);
+        assert_eq!(Some(String::from("new")), args.stream_source);
+        assert!(args.ndjson);
+        assert_eq!(Some(14626530), args.stream_from);
+
+        let args = AppArgs::parse(to_args("hncli --script").into_iter());
+        assert_eq!(AppMode::Interactive, args.mode);
+    }
+}
@@ -1,33 +1,220 @@
-use helpers::gen_utils;
+use super::commands::{self, ArgSchema, CommandSpec};
 use io;
 
+/// Ranges longer than this are not expanded into indexes
+const MAX_RANGE_LEN: usize = 100;
+
 pub struct UiCommand {
     pub command: Option<String>,
     pub number: Option<usize>,
+    /// Every index given with ranges and lists, number is the first of them
+    pub numbers: Vec<usize>,
     pub extra_args: Option<Vec<String>>,
+    /// Flags given as --name or --name=value
+    pub flags: Vec<(String, Option<String>)>,
     pub valid: bool,
+    pub error: Option<String>,
 }
 
 impl UiCommand {
     pub fn parse(msg: Result<String, io::Error>) -> Option<UiCommand> {
         match msg {
-            Ok(text) => {
-                let mut parts = text.split_whitespace().collect::<Vec<_>>().into_iter();
-                let command = Some(parts.next().unwrap_or("invalid").to_owned());
-                let number = gen_utils::try_to_parse_number(parts.next());
-                let extra_args = parts.map(|s| String::from(s)).collect::<Vec<_>>();
+            Ok(text) => Some(UiCommand::parse_line(&text)),
+            Err(ref e) => None, // todo wrong command
+        }
+    }
+
+    ///
+    /// Parses the line with the command registry, commands are resolved from aliases and glued shorthands like c3
+    ///
+    pub fn parse_line(text: &str) -> UiCommand {
+        let mut tokens: Vec<String> = text.split_whitespace().map(|s| String::from(s)).collect();
+        if tokens.is_empty() {
+            return UiCommand::invalid(None);
+        }
+        let first = tokens.remove(0);
+        if first.starts_with('/') {
+            // the pattern is glued to the command
+            if first.len() > 1 {
+                tokens.insert(0, String::from(&first[1..]));
+            }
+            return UiCommand::new("/", Vec::new(), tokens, Vec::new());
+        }
+        let spec = match commands::find_command(&first) {
+            Some(spec) => spec,
+            None => {
+                match split_glued_shorthand(&first) {
+                    Some((spec, indexes)) => {
+                        tokens.insert(0, String::from(indexes));
+                        spec
+                    }
+                    None => return UiCommand::invalid(Some(create_unknown_command_error(&first))),
+                }
+            }
+        };
+        match parse_arguments(spec, tokens) {
+            Ok(command) => command,
+            Err(e) => UiCommand::invalid(Some(e)),
+        }
+    }
+
+    fn new(name: &str, numbers: Vec<usize>, extra_args: Vec<String>, flags: Vec<(String, Option<String>)>) -> UiCommand {
+        UiCommand {
+            command: Some(String::from(name)),
+            number: numbers.first().cloned(),
+            numbers: numbers,
+            extra_args: Some(extra_args),
+            flags: flags,
+            valid: true,
+            error: None,
+        }
+    }
+
+    fn invalid(error: Option<String>) -> UiCommand {
+        UiCommand {
+            command: None,
+            number: None,
+            numbers: Vec::new(),
+            extra_args: None,
+            flags: Vec::new(),
+            valid: false,
+            error: error,
+        }
+    }
 
-                // todo check that command is in dict
+    pub fn has_flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|&(ref flag, _)| flag == name)
+    }
 
-                Some(UiCommand {
-                    command: command,
-                    number: number,
-                    extra_args: Some(extra_args),
-                    valid: true,
-                })
+    pub fn flag_value(&self, name: &str) -> Option<&str> {
+        self.flags.iter().find(|&&(ref flag, _)| flag == name).and_then(|&(_, ref value)| value.as_ref().map(|v| v.as_str()))
+    }
+}
+
+fn parse_arguments(spec: &CommandSpec, tokens: Vec<String>) -> Result<UiCommand, String> {
+    let mut flags = Vec::new();
+    let mut words = Vec::new();
+    for token in tokens {
+        if token.starts_with("--") && token.len() > 2 {
+            flags.push(parse_flag(spec, &token[2..])?);
+        } else {
+            words.push(token);
+        }
+    }
+    let indexes = words.first().and_then(|word| parse_indexes(word));
+    let numbers = match spec.args {
+        ArgSchema::Index => {
+            match indexes {
+                Some(ref numbers) if numbers.len() == 1 => numbers.clone(),
+                Some(_) => return Err(format!("{} takes one index, usage: {}", spec.name, spec.usage)),
+                None => return Err(format!("{} needs an index, usage: {}", spec.name, spec.usage)),
             }
-            Err(ref e) => None, // todo wrong command
         }
+        ArgSchema::Indexes => {
+            match indexes {
+                Some(ref numbers) => numbers.clone(),
+                None => return Err(format!("{} needs indexes, usage: {}", spec.name, spec.usage)),
+            }
+        }
+        ArgSchema::IndexOrWords => indexes.clone().unwrap_or(Vec::new()),
+        ArgSchema::NoArgs => {
+            if !words.is_empty() {
+                return Err(format!("{} takes no arguments, usage: {}", spec.name, spec.usage));
+            }
+            Vec::new()
+        }
+        ArgSchema::Choice(choices) => {
+            if words.len() > 1 {
+                return Err(format!("{} takes one argument, usage: {}", spec.name, spec.usage));
+            }
+            check_choice(spec, choices, words.first())?;
+            Vec::new()
+        }
+        ArgSchema::ChoiceAndPath(choices) => {
+            if words.len() > 2 {
+                return Err(format!("{} takes a format and a file, usage: {}", spec.name, spec.usage));
+            }
+            match words.first() {
+                Some(_) => check_choice(spec, choices, words.first())?,
+                None => return Err(format!("{} needs a format, usage: {}", spec.name, spec.usage)),
+            }
+            Vec::new()
+        }
+        ArgSchema::Words => Vec::new(),
+    };
+    if !numbers.is_empty() {
+        words.remove(0);
+    }
+    Ok(UiCommand::new(spec.name, numbers, words, flags))
+}
+
+fn check_choice(spec: &CommandSpec, choices: &[&'static str], value: Option<&String>) -> Result<(), String> {
+    match value {
+        Some(value) if !choices.contains(&value.as_str()) => {
+            let suggestions = commands::suggest_choices(value, choices);
+            if suggestions.is_empty() {
+                Err(format!("Unknown {} for {}, usage: {}", value, spec.name, spec.usage))
+            } else {
+                Err(format!("Unknown {} for {}, did you mean {}?", value, spec.name, suggestions.join(" or ")))
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+fn parse_flag(spec: &CommandSpec, flag: &str) -> Result<(String, Option<String>), String> {
+    let (name, value) = match flag.find('=') {
+        Some(i) => (&flag[..i], Some(String::from(&flag[i + 1..]))),
+        None => (flag, None),
+    };
+    match spec.flags.iter().find(|known| known.name == name) {
+        Some(known) if known.takes_value && value.is_none() => Err(format!("--{} needs a value like --{}=[value]", name, name)),
+        Some(known) if !known.takes_value && value.is_some() => Err(format!("--{} does not take a value", name)),
+        Some(_) => Ok((String::from(name), value)),
+        None => Err(format!("Unknown flag --{} for {}", name, spec.name)),
+    }
+}
+
+///
+/// Parses indexes like 3, 1-3 and 2,5,7 counting from 1, None if the text is not indexes
+///
+pub fn parse_indexes(text: &str) -> Option<Vec<usize>> {
+    let mut indexes = Vec::new();
+    for part in text.split(',') {
+        let (start, end) = match part.find('-') {
+            Some(i) => (part[..i].parse::<usize>().ok()?, part[i + 1..].parse::<usize>().ok()?),
+            None => {
+                let index = part.parse::<usize>().ok()?;
+                (index, index)
+            }
+        };
+        if start == 0 || end < start || end - start >= MAX_RANGE_LEN {
+            return None;
+        }
+        indexes.extend(start..end + 1);
+    }
+    Some(indexes)
+}
+
+///
+/// Splits shorthands like c3 or e12 into the command and its indexes
+///
+fn split_glued_shorthand(token: &str) -> Option<(&'static CommandSpec, &str)> {
+    let split = token.find(|c: char| c.is_digit(10))?;
+    if split == 0 {
+        return None;
+    }
+    let spec = commands::find_command(&token[..split])?;
+    parse_indexes(&token[split..])?;
+    Some((spec, &token[split..]))
+}
+
+fn create_unknown_command_error(name: &str) -> String {
+    let suggestions = commands::suggest_commands(name);
+    if suggestions.is_empty() {
+        format!("Unknown command {}, see help for the commands", name)
+    } else {
+        format!("Unknown command {}, did you mean {}?", name, suggestions.join(" or "))
     }
 }
 
@@ -47,5 +234,96 @@ mod tests {
 
         let cmd3:UiCommand = UiCommand::parse(Ok("open 10 abc".to_owned())).unwrap();
         assert_eq!("abc", cmd3.extra_args.unwrap()[0]);
+
+        let cmd4:UiCommand = UiCommand::parse(Ok("live off".to_owned())).unwrap();
+        assert_eq!("live", cmd4.command.unwrap());
+        assert!(cmd4.number.is_none());
+        assert_eq!("off", cmd4.extra_args.unwrap()[0]);
+    }
+
+    #[test]
+    fn test_aliases_and_shorthands() {
+        let cmd = UiCommand::parse_line("e 1");
+        assert_eq!("expand", cmd.command.unwrap());
+        assert_eq!(Some(1), cmd.number);
+
+        let glued = UiCommand::parse_line("e12");
+        assert_eq!("expand", glued.command.unwrap());
+        assert_eq!(Some(12), glued.number);
+
+        let comments = UiCommand::parse_line("c3");
+        assert_eq!("comments", comments.command.unwrap());
+        assert_eq!(vec![3], comments.numbers);
+
+        let grep = UiCommand::parse_line("grep rust 2017");
+        assert_eq!("find", grep.command.unwrap());
+        assert_eq!(vec!["rust", "2017"], grep.extra_args.unwrap());
+
+        let pattern = UiCommand::parse_line("/rust async");
+        assert_eq!("/", pattern.command.unwrap());
+        assert_eq!(vec!["rust", "async"], pattern.extra_args.unwrap());
+    }
+
+    #[test]
+    fn test_ranges_and_lists() {
+        assert_eq!(vec![1, 2, 3], UiCommand::parse_line("open 1-3").numbers);
+        assert_eq!(vec![2, 5, 7, 8], UiCommand::parse_line("o2,5,7-8").numbers);
+        assert_eq!(Some(vec![4]), parse_indexes("4"));
+        assert!(parse_indexes("3-1").is_none());
+        assert!(parse_indexes("0").is_none());
+        assert!(parse_indexes("1-1000").is_none());
+        assert!(parse_indexes("a,b").is_none());
+
+        let single = UiCommand::parse_line("comments 1-3");
+        assert!(!single.valid);
+        assert!(single.error.unwrap().contains("takes one index"));
+        assert!(!UiCommand::parse_line("open").valid);
+
+        let watch_user = UiCommand::parse_line("watch user pg");
+        assert!(watch_user.numbers.is_empty());
+        assert_eq!(vec!["user", "pg"], watch_user.extra_args.unwrap());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_flags() {
+        let cmd = UiCommand::parse_line("search rust --by-date");
+        assert!(cmd.has_flag("by-date"));
+        assert!(cmd.flag_value("by-date").is_none());
+        assert_eq!(vec!["rust"], cmd.extra_args.unwrap());
+
+        let unknown = UiCommand::parse_line("search rust --sideways");
+        assert_eq!("Unknown flag --sideways for search", unknown.error.unwrap());
+        assert!(!UiCommand::parse_line("search rust --by-date=yes").valid);
+    }
+
+    #[test]
+    fn test_choices_and_extra_words() {
+        assert_eq!(vec!["on"], UiCommand::parse_line("live on").extra_args.unwrap());
+        assert!(UiCommand::parse_line("live").valid);
+        assert_eq!("Unknown maybe for live, usage: live [on|off]",
+                   UiCommand::parse_line("live maybe").error.unwrap());
+        assert_eq!("Unknown bset for feed, did you mean best?", UiCommand::parse_line("feed bset").error.unwrap());
+        assert!(!UiCommand::parse_line("feed top new").valid);
+        assert_eq!(vec!["off"], UiCommand::parse_line("raw off").extra_args.unwrap());
+        assert_eq!("Unknown maybe for raw, usage: raw [on|off]", UiCommand::parse_line("raw maybe").error.unwrap());
+        assert_eq!("next takes no arguments, usage: next", UiCommand::parse_line("next page").error.unwrap());
+
+        let publish = UiCommand::parse_line("publish atom feed.xml --limit=5");
+        assert_eq!(vec!["atom", "feed.xml"], publish.extra_args.unwrap());
+        assert_eq!("Unknown rs for publish, did you mean rss?",
+                   UiCommand::parse_line("publish rs feed.xml").error.unwrap());
+        assert!(!UiCommand::parse_line("publish").valid);
+        assert!(UiCommand::parse_line("export-list tsv").valid);
+        assert!(!UiCommand::parse_line("export-list csv a.csv b.csv").valid);
+    }
+
+    #[test]
+    fn test_unknown_commands() {
+        let cmd = UiCommand::parse_line("coments 2");
+        assert!(!cmd.valid);
+        assert_eq!("Unknown command coments, did you mean comments?", cmd.error.unwrap());
+        assert_eq!("Unknown command xyzzy, see help for the commands",
+                   UiCommand::parse_line("xyzzy").error.unwrap());
+        assert!(UiCommand::parse_line("   ").error.is_none());
+    }
+}
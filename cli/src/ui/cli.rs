@@ -1,21 +1,35 @@
+use std::cmp::max;
+use std::collections::HashMap;
+use std::io;
 use core::models::*;
+use core::watch::{WatchList, WatchNotification};
+use core::alerts::AlertMatch;
+use core::killfile::{KillAction, Killfile, KillRule};
+use core::feed_view::FeedFilters;
+use helpers::gen_utils::format_relative_age;
+use chrono;
 use formatting::formatter::FormatStr;
+use formatting::quote_formatter::{is_quote, split_quote};
+use formatting::code_block::{detect_language, is_fence, tokenize, Language, Token};
 use helpers::path_utils;
+use core::text_index::find_term_ranges;
+use core::thread_search::ThreadSearch;
+use termion::{self, color, style};
+use ui::commands;
 
-const HELP_STR: &'static str  = "
-top             > opens the currently opened page of stories (reprints)
-next            > retrieves the next 10 stories or comments
-back            > retrieves the previous 10 stories or comments
-comments [num]  > retrieves comments for given story, based on the id of the story shown in [num] ten at a time
-expand [num]    > once comments are open you can retrieve the sub comments for the comment with it ten at a time
-load [num]      > loads the page linked in the story as local html
-open [num]      > opens the link with default browser
-exit            > quits the application
-
-[num] replace the number with the printed out index";
-
-pub fn print_help() {
-    println!("{}", HELP_STR);
+///
+/// Prints the help of all commands or of the named command, both generated from the command registry
+///
+pub fn print_help(command: Option<&str>) {
+    match command {
+        Some(name) => {
+            match commands::find_command(name) {
+                Some(spec) => print!("{}", commands::create_command_help(spec)),
+                None => println!("No help for {}, it is not a command", name),
+            }
+        }
+        None => println!("\n{}", commands::create_help()),
+    }
 }
 
 pub fn print_tried_to_navigate_over_index() {
@@ -23,13 +37,15 @@ pub fn print_tried_to_navigate_over_index() {
 }
 
 pub fn print_comments_and_parent(item: Option<&HnItem>,
-                                comments: &Option<Vec<&HnItem>>,
+                                comments: &Option<Vec<(usize, &HnItem)>>,
                                 format: &FormatStr, 
-                                index:usize) {
+                                previous_stats: &HashMap<i32, HnItemStats>,
+                                search: Option<&ThreadSearch>,
+                                highlight_code: bool) {
     match item {
         Some(ref item) => {
             match *comments {
-                Some(ref comments) => print_comments(item, comments, format, index),
+                Some(ref comments) => print_comments(item, comments, format, previous_stats, search, highlight_code),
                 None => could_not_get_any_commments_for_item(item), 
             }
         }
@@ -37,25 +53,453 @@ pub fn print_comments_and_parent(item: Option<&HnItem>,
     }
 }
 
+pub fn print_command_error(error: &str) {
+    println!("{}", error);
+}
+
 pub fn print_invalid_command() {
     println!("Could not understand command, please try again or check help");
 }
 
-pub fn print_headline_with_author(item: &HnItem, index: &i32) {
-    let s = create_headline_with_author(item, index).unwrap(); // Not handling errs
+pub fn print_headline_with_author(item: &HnItem,
+                                  index: &i32,
+                                  previous_stats: Option<HnItemStats>,
+                                  highlights: &Vec<String>) {
+    let mut s = create_headline_with_author(item, index).unwrap(); // Not handling errs
+    if !highlights.is_empty() && output_is_terminal() {
+        s = highlight_ranges(&s, &find_term_ranges(&s, highlights));
+    }
+    match previous_stats.and_then(|stats| create_change_marker(&stats, item)) {
+        Some(marker) => s.push_str(&marker),
+        None => (),
+    }
     println!("{}", s);
 }
 
+///
+/// Wraps the byte ranges of text in highlight, ranges are expected in order and not to overlap
+///
+pub fn highlight_ranges(text: &str, ranges: &Vec<(usize, usize)>) -> String {
+    let mut highlighted = String::new();
+    let mut last = 0;
+    for &(start, end) in ranges {
+        highlighted.push_str(&text[last..start]);
+        highlighted.push_str(&format!("{}{}{}{}", style::Bold, color::Fg(color::Yellow), &text[start..end], style::Reset));
+        last = end;
+    }
+    highlighted.push_str(&text[last..]);
+    highlighted
+}
+
+///
+/// How comment text is decorated in a terminal
+///
+pub struct CommentStyle {
+    /// Columns the lines of code blocks are cut to
+    pub width: usize,
+    pub highlight_code: bool,
+}
+
+impl CommentStyle {
+    fn for_terminal(highlight_code: bool) -> CommentStyle {
+        CommentStyle {
+            width: termion::terminal_size().map(|(width, _)| width as usize).unwrap_or(80),
+            highlight_code: highlight_code,
+        }
+    }
+}
+
+///
+/// Quoted lines are dimmed behind a bar for each level of quoting instead of the >
+/// and code blocks are framed instead of the fences
+///
+pub fn style_comment(text: &str, comment_style: &CommentStyle) -> String {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let mut styled: Vec<String> = Vec::new();
+    let mut idx = 0;
+    while idx < lines.len() {
+        let line = lines[idx];
+        if is_fence(line) {
+            let code: Vec<&str> = lines[idx + 1..].iter().cloned().take_while(|line| !is_fence(line)).collect();
+            styled.extend(style_code_block(&code, comment_style));
+            idx += code.len() + 2;
+            continue;
+        }
+        if is_quote(line) {
+            let (depth, quoted) = split_quote(line);
+            // search highlights end with a reset, the rest of the quote stays dimmed
+            let quoted = quoted.replace(&style::Reset.to_string(), &format!("{}{}", style::Reset, style::Faint));
+            styled.push(format!("{}{}{}{}", style::Faint, "\u{2502} ".repeat(depth), quoted, style::Reset));
+        } else {
+            styled.push(String::from(line));
+        }
+        idx += 1;
+    }
+    styled.join("\n")
+}
+
+///
+/// Code lines are never wrapped, the ones wider than the terminal are cut with a marker
+///
+fn style_code_block(code: &Vec<&str>, comment_style: &CommentStyle) -> Vec<String> {
+    let language = if comment_style.highlight_code {
+        detect_language(&code.join("\n"))
+    } else {
+        None
+    };
+    let label = language.map(|language| language.name).unwrap_or("code");
+    let mut lines = vec![format!("{}\u{250C}\u{2500}\u{2500} {}{}", style::Faint, label, style::Reset)];
+    for line in code {
+        let line = line.replace('\t', "    ");
+        let (visible, cut) = cut_to_width(&line, max(comment_style.width.saturating_sub(2), 1));
+        let mut styled = format!("{}\u{2502}{} ", style::Faint, style::Reset);
+        match language {
+            // lines with search highlights are left as they are
+            Some(language) if !visible.contains('\x1b') => styled.push_str(&highlight_code(visible, language)),
+            _ => styled.push_str(visible),
+        }
+        if cut {
+            styled.push_str(&format!("{}\u{2192}{}", style::Faint, style::Reset));
+        }
+        lines.push(styled);
+    }
+    lines.push(format!("{}\u{2514}\u{2500}\u{2500}{}", style::Faint, style::Reset));
+    lines
+}
+
+///
+/// Cuts the line leaving a column for the marker when it's wider than the columns,
+/// escape sequences of highlights take no room
+///
+fn cut_to_width(line: &str, width: usize) -> (&str, bool) {
+    let mut columns = 0;
+    let mut in_escape = false;
+    let mut marker_at = 0;
+    for (idx, c) in line.char_indices() {
+        if in_escape {
+            in_escape = !c.is_ascii_alphabetic();
+            continue;
+        }
+        if c == '\x1b' {
+            in_escape = true;
+            continue;
+        }
+        columns += 1;
+        if columns == width {
+            marker_at = idx;
+        }
+        if columns > width {
+            return (&line[..marker_at], true);
+        }
+    }
+    (line, false)
+}
+
+fn highlight_code(line: &str, language: &Language) -> String {
+    tokenize(line, language)
+        .iter()
+        .map(|&(token, text)| match token {
+            Token::Plain => String::from(text),
+            Token::Keyword => format!("{}{}{}", color::Fg(color::Magenta), text, color::Fg(color::Reset)),
+            Token::Str => format!("{}{}{}", color::Fg(color::Green), text, color::Fg(color::Reset)),
+            Token::Number => format!("{}{}{}", color::Fg(color::Cyan), text, color::Fg(color::Reset)),
+            Token::Comment => format!("{}{}{}", color::Fg(color::LightBlack), text, color::Fg(color::Reset)),
+        })
+        .collect()
+}
+
+///
+/// Highlights are left out when the output goes to a file
+///
+fn output_is_terminal() -> bool {
+    termion::is_tty(&io::stdout())
+}
+
+fn create_change_marker(previous_stats: &HnItemStats, item: &HnItem) -> Option<String> {
+    let stats = item.stats();
+    if stats == *previous_stats {
+        return None;
+    }
+    let mut changes = Vec::new();
+    if stats.score != previous_stats.score {
+        changes.push(format!("{:+} points", stats.score - previous_stats.score));
+    }
+    if stats.comments != previous_stats.comments {
+        changes.push(format!("{:+} comments", stats.comments - previous_stats.comments));
+    }
+    Some(format!(" <- {}", changes.join(", ")))
+}
+
+pub fn print_live_updates_toggled(enabled: bool, interval_secs: u64) {
+    if enabled {
+        println!("Live updates enabled, checking for changes every {} seconds", interval_secs);
+    } else {
+        println!("Live updates disabled");
+    }
+}
+
+pub fn print_quote(quote: &str) {
+    print!("{}", quote);
+}
+
+pub fn print_quote_copied(tool: &str) {
+    println!("Quote copied to the clipboard with {}", tool);
+}
+
+pub fn print_raw_comments_toggled(enabled: bool) {
+    if enabled {
+        println!("Comments are shown without formatting");
+    } else {
+        println!("Comments are formatted");
+    }
+}
+
+pub fn print_watch_notification(notification: &WatchNotification) {
+    println!("[watch {}] new item {}: {}", notification.watch, notification.item_id, notification.excerpt);
+}
+
+pub fn print_watches(watch_list: &WatchList) {
+    if watch_list.is_empty() {
+        println!("Not watching anything, use watch [num] or watch user [id]");
+    }
+    let mut index = 0;
+    for watch in &watch_list.watches {
+        index += 1;
+        println!("[{:3}] {} with {} seen items", index, watch.describe(), watch.seen.len());
+    }
+}
+
+pub fn print_watch_added(description: &str, added: bool) {
+    if added {
+        println!("Watching {}", description);
+    } else {
+        println!("Already watching {}", description);
+    }
+}
+
+pub fn print_watch_removed(description: &str) {
+    println!("Stopped watching {}", description);
+}
+
+pub fn print_could_not_watch(description: &str) {
+    println!("Could not watch {}", description);
+}
+
+pub fn print_watch_daemon_started(watches: usize, interval_secs: u64) {
+    println!("Checking {} watches every {} seconds", watches, interval_secs);
+}
+
+pub fn print_alert_match(alert: &AlertMatch) {
+    println!("[alert] {} {} by {} matched {}: {}",
+             alert.type_str,
+             alert.item_id,
+             alert.by,
+             alert.reasons.join(", "),
+             alert.excerpt);
+}
+
+pub fn print_alerts_started(source: &str, interval_secs: u64, log_file: &str) {
+    println!("Evaluating alert rules against {} every {} seconds, matches are written to {}",
+             source,
+             interval_secs,
+             log_file);
+}
+
+pub fn print_invalid_alert_rules(reason: &str) {
+    println!("Could not use alert rules: {}", reason);
+}
+
+pub fn print_collapsed_headline(index: &i32, reason: &str) {
+    println!("[{:3}] [filtered by {}]", index, reason);
+}
+
+pub fn print_hidden_by_filters(hidden: usize, kind: &str) {
+    if hidden > 0 {
+        println!("{} {} hidden by filters on this page", hidden, kind);
+    }
+}
+
+pub fn print_filters(killfile: &Killfile) {
+    if killfile.rules().is_empty() {
+        println!("No filters, add one with filters add [domain|title|author|comment-author] [value]");
+    }
+    let mut index = 0;
+    for rule in killfile.rules() {
+        index += 1;
+        println!("[{:3}] {}", index, rule.describe());
+    }
+    let action = match killfile.story_action {
+        KillAction::Hide => "hidden",
+        KillAction::Collapse => "collapsed",
+    };
+    println!("Filtered stories are {}, filtered comments are hidden", action);
+}
+
+pub fn print_filter_added(rule: &KillRule) {
+    println!("Added filter {}", rule.describe());
+}
+
+pub fn print_filter_removed(rule: &KillRule) {
+    println!("Removed filter {}", rule.describe());
+}
+
+pub fn print_invalid_filter(reason: &str) {
+    println!("Could not change filters: {}", reason);
+}
+
+pub fn print_feeds(current: HnFeed) {
+    let names: Vec<&str> = HnFeed::all().iter().map(|feed| feed.name()).collect();
+    println!("Viewing feed {}, available feeds are {}", current.name(), names.join(", "));
+}
+
+pub fn print_could_not_get_feed(name: &str) {
+    println!("Could not get stories for feed {}", name);
+}
+
+pub fn print_feed_filters(filters: &FeedFilters, matching: usize) {
+    println!("Feed filters: {} with {} matching stories",
+             filters.describe(chrono::Utc::now().timestamp()),
+             matching);
+}
+
+pub fn print_retrieving_stories_for_filters(count: usize) {
+    println!("Retrieving {} stories of the feed for filtering, this can take a while", count);
+}
+
+pub fn print_invalid_search(reason: &str) {
+    println!("Invalid search, {}", reason);
+}
+
+pub fn print_search_failed(reason: &str) {
+    println!("Search failed, {}", reason);
+}
+
+pub fn print_search_results_found(listed: usize, total: usize) {
+    println!("Found {} results, listing {}", total, listed);
+}
+
+pub fn print_index_hits_found(hits: usize, indexed: usize) {
+    println!("Found {} matches from {} indexed items", hits, indexed);
+}
+
+pub fn print_thread_search_position(position: usize, hits: usize, index: usize) {
+    println!("Match {} of {} at comment [{}]", position, hits, index);
+}
+
+pub fn print_no_thread_search_hits() {
+    println!("No loaded comments match the search");
+}
+
+pub fn print_no_thread_search() {
+    println!("Search the comments first with /pattern");
+}
+
+pub fn print_script_failed(reason: &str) {
+    println!("Script failed, {}", reason);
+}
+
+pub fn print_script_commands_failed(path: &str, failed: usize) {
+    println!("{} commands of {} failed", failed, path);
+}
+
+pub fn print_published(count: usize, path: &str) {
+    println!("Published {} stories to {}", count, path);
+}
+
+pub fn print_list_exported(count: usize, path: &str) {
+    println!("Exported {} stories to {}", count, path);
+}
+
+pub fn print_could_not_export_list(reason: &str) {
+    println!("Could not export the list, {}", reason);
+}
+
+pub fn print_could_not_publish(reason: &str) {
+    println!("Could not publish, {}", reason);
+}
+
+///
+/// Documents like exported feeds are printed as they are so they can be redirected to a file
+///
+pub fn print_document(document: &str) {
+    print!("{}", document);
+}
+
+pub fn print_digest_written(count: usize, dir: &str) {
+    println!("Wrote the digest of {} stories to {}", count, dir);
+}
+
+///
+/// Errors of exports go to stderr so they don't end up in the redirected document
+///
+pub fn print_export_failed(reason: &str) {
+    eprintln!("Export failed, {}", reason);
+}
+
+///
+/// Goes to stderr, stdout has only the streamed items
+///
+pub fn print_stream_started(source: &str, after: Option<i32>) {
+    match after {
+        Some(id) => eprintln!("Streaming {} items after {}", source, id),
+        None => eprintln!("Streaming the newest {} items", source),
+    }
+}
+
+pub fn print_invalid_stream_source(source: &str) {
+    eprintln!("Unknown stream {}, use new or stories", source);
+}
+
+pub fn print_nntp_started(address: &str) {
+    println!("Serving the feeds as newsgroups hn.top, hn.new... over NNTP at {}", address);
+}
+
+pub fn print_api_started(address: &str) {
+    println!("Serving the JSON api at http://{}/ (feeds/:kind, items/:id, items/:id/tree, users/:id, search)", address);
+}
+
+pub fn print_smallnet_started(protocol: &str, url: &str) {
+    println!("Serving the feeds over {} at {}", protocol, url);
+}
+
+pub fn print_could_not_load_identity(reason: &str) {
+    println!("Could not load the Gemini identity, {}", reason);
+}
+
+pub fn print_could_not_listen(address: &str, reason: &str) {
+    println!("Could not listen at {}: {}", address, reason);
+}
+
+pub fn print_mail_exported(count: usize, path: &str) {
+    println!("Exported {} messages to {}", count, path);
+}
+
+pub fn print_could_not_export_mail(reason: &str) {
+    println!("Could not export mail, {}", reason);
+}
+
+pub fn print_history(entries: &Vec<String>) {
+    for (index, entry) in entries.iter().enumerate() {
+        println!("[{:4}] {}", index + 1, entry);
+    }
+}
+
+pub fn print_refreshed_view(changed: usize) {
+    println!("Refreshed view, {} visible items changed", changed);
+}
+
 fn create_headline_with_author(item: &HnItem, index: &i32) -> Result<String, String> {
     let link = item.url.as_ref().and_then(|link| path_utils::get_host_from_link(link)).unwrap_or("could not parse link".to_owned());
     match item.title {
         Some(_) => {
             let headline_with_link = format!("{} ({})",item.title.as_ref().unwrap(), link);
-            let s = format!("[{:3}] {:70} by {} with [{}] comments",
+            let s = format!("[{:3}] {:70} {} points by {} {} with [{}] comments",
                             index,
                             headline_with_link,
+                            item.score.unwrap_or(0),
                             item.by,
-                            item.kids.as_ref().unwrap_or(&Vec::new()).len());
+                            format_relative_age(item.time, chrono::Utc::now().timestamp()),
+                            item.comment_count());
             Ok(s)
 
         }
@@ -86,20 +530,31 @@ pub fn print_invalid_state() {
     println!("App has an invalid state, could cause problems.");
 }
 
-pub fn print_comments(item: &HnItem, comments: &Vec<&HnItem>, format: &FormatStr, index:usize) {
+pub fn print_comments(item: &HnItem,
+                      comments: &Vec<(usize, &HnItem)>,
+                      format: &FormatStr,
+                      previous_stats: &HashMap<i32, HnItemStats>,
+                      search: Option<&ThreadSearch>,
+                      highlight_code: bool) {
+    let comment_style = CommentStyle::for_terminal(highlight_code);
     if comments.len() > 0 {
         match item.title {
             Some(ref title) => println!("Comments for item id {} with title {}", &item.id, title),
             None => println!("Comments for item id {}", &item.id),
         }
-        let mut comment_index = index;
-        for comment in comments {
-            comment_index += 1;
-            let res = create_comment_row(comment_index, &comment, format);
+        for &(comment_index, comment) in comments {
+            let mut marker = previous_stats.get(&comment.id).and_then(|stats| create_change_marker(stats, comment));
+            if search.and_then(|search| search.current_hit()) == Some(comment_index - 1) {
+                marker = Some(format!("{} <- match", marker.unwrap_or(String::new())));
+            }
+            let res = create_comment_row(comment_index,
+                                         &comment,
+                                         format,
+                                         marker,
+                                         search.filter(|_| output_is_terminal()),
+                                         Some(&comment_style).filter(|_| output_is_terminal()));
             if res.is_some() {
                 println!("{}", res.unwrap());
-            } else {
-                comment_index -= 1;
             }
         }
     } else {
@@ -124,14 +579,33 @@ pub fn print_could_not_get_story(numb: usize) {
     println!("Could not get story at index {}", numb);
 }
 
-fn create_comment_row(index: usize, item: &HnItem, format: &FormatStr) -> Option<String> {
+fn create_comment_row(index: usize,
+                      item: &HnItem,
+                      format: &FormatStr,
+                      marker: Option<String>,
+                      search: Option<&ThreadSearch>,
+                      comment_style: Option<&CommentStyle>)
+                      -> Option<String> {
     match item.text_unescaped() {
         Some(ref text) => {
-            let mut s = format!("[{:3}] {:70} by {}", index, &format.format(text), &item.by);
+            let mut formatted = format.format(text);
+            match search {
+                Some(search) => formatted = highlight_ranges(&formatted, &search.match_ranges(&formatted)),
+                None => (),
+            }
+            match comment_style {
+                Some(comment_style) => formatted = style_comment(&formatted, comment_style),
+                None => (),
+            }
+            let mut s = format!("[{:3}] {:70} by {}", index, &formatted, &item.by);
             match item.kids {
                 Some(ref kids) => s.push_str(&format!(" with [{:3}] comments", kids.len())),
                 None => (),
             }
+            match marker {
+                Some(marker) => s.push_str(&marker),
+                None => (),
+            }
             Some(s)
         }
         None => None,
@@ -143,6 +617,39 @@ fn create_comment_row(index: usize, item: &HnItem, format: &FormatStr) -> Option
 mod tests {
     use super::*;
 
+    #[test]
+    fn style_code_block_test() {
+        let comment_style = CommentStyle {
+            width: 12,
+            highlight_code: true,
+        };
+        let text = "Try:\n```\n  $ curl example.com\n  $ echo 1\n```\n> after";
+        let lines: Vec<String> = style_comment(text, &comment_style).split('\n').map(String::from).collect();
+        assert_eq!(6, lines.len());
+        assert_eq!(format!("{}\u{250C}\u{2500}\u{2500} shell{}", style::Faint, style::Reset), lines[1]);
+        assert_eq!(format!("{}\u{2502}{}   $ curl {}\u{2192}{}", style::Faint, style::Reset, style::Faint, style::Reset),
+                   lines[2]);
+        assert!(lines[3].contains(&format!("{}echo{}", color::Fg(color::Magenta), color::Fg(color::Reset))));
+        assert!(lines[5].contains("\u{2502} after"));
+
+        let plain = CommentStyle {
+            width: 80,
+            highlight_code: false,
+        };
+        assert!(style_comment(text, &plain).contains("\u{2500} code"));
+        assert_eq!(("ab", true), cut_to_width("abcd", 3));
+        assert_eq!(("abc", false), cut_to_width("abc", 3));
+        let highlighted = format!("{}abc{}", style::Bold, style::Reset);
+        assert_eq!((highlighted.as_str(), false), cut_to_width(&highlighted, 3));
+    }
+
+    #[test]
+    fn highlight_ranges_test() {
+        let highlighted = highlight_ranges("use rust now", &vec![(4, 8)]);
+        assert_eq!(format!("use {}{}rust{} now", style::Bold, color::Fg(color::Yellow), style::Reset), highlighted);
+        assert_eq!("unchanged", highlight_ranges("unchanged", &Vec::new()));
+    }
+
     #[test]
     fn create_headline_with_author_test() {
         use std::fs::File;
@@ -158,6 +665,8 @@ mod tests {
         assert!(s.len() != 0);
         assert!(s.contains("1"));
         assert!(s.contains("dhouston"));
+        assert!(s.contains("111 points"));
+        assert!(s.contains("y ago"));
         assert!(s.contains("My YC app: Dropbox - Throw away your USB drive"));
         assert!(deserialized.by.len() != 0);
         assert!(deserialized.title.unwrap().len() != 0);
@@ -176,10 +685,46 @@ mod tests {
             .and_then(|mut file| file.read_to_string(&mut contents))
             .unwrap();
         let deserialized: HnItem = serde_json::from_str(&contents).unwrap();
-        let comment_str = create_comment_row(1, &deserialized, &formatting).unwrap();
+        let comment_str = create_comment_row(1, &deserialized, &formatting, None, None, None).unwrap();
         assert!(comment_str.contains("is not a valid concern. Unless you are planning"));
         assert!(comment_str.contains("cholantesh"));
 
+        let search = ThreadSearch::new("valid").unwrap();
+        let highlighted = create_comment_row(1, &deserialized, &formatting, None, Some(&search), None).unwrap();
+        assert!(highlighted.contains(&format!("a {}{}valid{} concern", style::Bold, color::Fg(color::Yellow), style::Reset)));
+
+        let mut quoting = deserialized.clone();
+        quoting.text = Some(String::from("<i>They said it&#x27;s fine</i><p>It isn&#x27;t."));
+        let comment_style = CommentStyle {
+            width: 30,
+            highlight_code: true,
+        };
+        let styled = create_comment_row(1, &quoting, &formatting, None, None, Some(&comment_style)).unwrap();
+        assert!(styled.contains(&format!("{}\u{2502} They said it's fine{}\n", style::Faint, style::Reset)));
+        assert!(create_comment_row(1, &quoting, &formatting, None, None, None).unwrap().contains("> They said it's fine"));
+        let search = ThreadSearch::new("said").unwrap();
+        let highlighted = create_comment_row(1, &quoting, &formatting, None, Some(&search), Some(&comment_style)).unwrap();
+        assert!(highlighted.contains(&format!("said{}{} it's fine{}\n", style::Reset, style::Faint, style::Reset)));
+
+    }
+
+    #[test]
+    fn create_change_marker_test() {
+        use std::fs::File;
+        use std::io::prelude::*;
+        use serde_json;
+        let mut contents = String::new();
+        File::open("res/test/item.json")
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .unwrap();
+        let deserialized: HnItem = serde_json::from_str(&contents).unwrap();
+        let unchanged = deserialized.stats();
+        assert!(create_change_marker(&unchanged, &deserialized).is_none());
+
+        let previous = HnItemStats { score: unchanged.score - 11, comments: unchanged.comments };
+        let marker = create_change_marker(&previous, &deserialized).unwrap();
+        assert!(marker.contains("+11 points"));
+        assert!(!marker.contains("comments"));
     }
     
 }
\ No newline at end of file
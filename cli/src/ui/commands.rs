@@ -0,0 +1,417 @@
+use helpers::gen_utils::edit_distance;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ArgSchema {
+    NoArgs,
+    /// Exactly one index of the current view
+    Index,
+    /// One or more indexes given as ranges like 1-3 or lists like 2,5,7
+    Indexes,
+    /// Optional index followed by words, like watch 3 or watch user pg
+    IndexOrWords,
+    /// Optional word which is one of the choices
+    Choice(&'static [&'static str]),
+    /// One of the choices followed by an optional file, like publish rss feed.xml
+    ChoiceAndPath(&'static [&'static str]),
+    /// Words taken as they are, numbers included
+    Words,
+}
+
+pub struct FlagSpec {
+    pub name: &'static str,
+    /// Whether the flag is given as --name=value
+    pub takes_value: bool,
+    pub help: &'static str,
+}
+
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub args: ArgSchema,
+    pub flags: &'static [FlagSpec],
+    pub usage: &'static str,
+    pub help: &'static str,
+}
+
+const FEEDS: &'static [&'static str] = &["top", "new", "best", "ask", "show", "job"];
+const SORTS: &'static [&'static str] = &["rank", "score", "comments", "time"];
+const NO_FLAGS: &'static [FlagSpec] = &[];
+
+pub const COMMANDS: &'static [CommandSpec] = &[
+    CommandSpec {
+        name: "top",
+        aliases: &["t"],
+        args: ArgSchema::NoArgs,
+        flags: NO_FLAGS,
+        usage: "top",
+        help: "opens the currently opened page of stories (reprints)",
+    },
+    CommandSpec {
+        name: "feed",
+        aliases: &[],
+        args: ArgSchema::Choice(FEEDS),
+        flags: NO_FLAGS,
+        usage: "feed [name]",
+        help: "switches to feed top, new, best, ask, show or job",
+    },
+    CommandSpec {
+        name: "min-score",
+        aliases: &[],
+        args: ArgSchema::Words,
+        flags: NO_FLAGS,
+        usage: "min-score [num]",
+        help: "only shows stories with at least [num] points, without [num] clears it",
+    },
+    CommandSpec {
+        name: "max-age",
+        aliases: &[],
+        args: ArgSchema::Words,
+        flags: NO_FLAGS,
+        usage: "max-age [age]",
+        help: "only shows stories newer than [age] like 30m, 24h or 7d, without [age] clears it",
+    },
+    CommandSpec {
+        name: "min-comments",
+        aliases: &[],
+        args: ArgSchema::Words,
+        flags: NO_FLAGS,
+        usage: "min-comments [num]",
+        help: "only shows stories with at least [num] comments, without [num] clears it",
+    },
+    CommandSpec {
+        name: "sort",
+        aliases: &[],
+        args: ArgSchema::Choice(SORTS),
+        flags: NO_FLAGS,
+        usage: "sort [mode]",
+        help: "sorts the feed by rank, score, comments or time",
+    },
+    CommandSpec {
+        name: "find",
+        aliases: &["grep", "f"],
+        args: ArgSchema::Words,
+        flags: NO_FLAGS,
+        usage: "find [query]",
+        help: "finds stories and comments retrieved earlier from the local index, supports AND, OR, NOT,\n-word, (groups) and \"phrases\"",
+    },
+    CommandSpec {
+        name: "search",
+        aliases: &["s"],
+        args: ArgSchema::Words,
+        flags: &[FlagSpec {
+                     name: "by-date",
+                     takes_value: false,
+                     help: "newest results first, same as sort:date",
+                 }],
+        usage: "search [query]",
+        help: "searches stories and comments, filter with tag:story,comment,ask_hn,show_hn author:[id]\nsince:[date|age] until:[date|age] points:[num] and sort:date, results are listed like a feed",
+    },
+    CommandSpec {
+        name: "/",
+        aliases: &[],
+        args: ArgSchema::Words,
+        flags: NO_FLAGS,
+        usage: "/[pattern]",
+        help: "searches the loaded comments for the regex [pattern], lowercase patterns ignore case",
+    },
+    CommandSpec {
+        name: "n",
+        aliases: &[],
+        args: ArgSchema::NoArgs,
+        flags: NO_FLAGS,
+        usage: "n",
+        help: "jumps to the next comment matching the search",
+    },
+    CommandSpec {
+        name: "N",
+        aliases: &[],
+        args: ArgSchema::NoArgs,
+        flags: NO_FLAGS,
+        usage: "N",
+        help: "jumps to the previous comment matching the search",
+    },
+    CommandSpec {
+        name: "next",
+        aliases: &[],
+        args: ArgSchema::NoArgs,
+        flags: NO_FLAGS,
+        usage: "next",
+        help: "retrieves the next 10 stories or comments",
+    },
+    CommandSpec {
+        name: "back",
+        aliases: &["b"],
+        args: ArgSchema::NoArgs,
+        flags: NO_FLAGS,
+        usage: "back",
+        help: "retrieves the previous 10 stories or comments",
+    },
+    CommandSpec {
+        name: "comments",
+        aliases: &["c"],
+        args: ArgSchema::Index,
+        flags: NO_FLAGS,
+        usage: "comments [num]",
+        help: "retrieves comments for given story, based on the id of the story shown in [num] ten at a time",
+    },
+    CommandSpec {
+        name: "expand",
+        aliases: &["e"],
+        args: ArgSchema::Index,
+        flags: NO_FLAGS,
+        usage: "expand [num]",
+        help: "once comments are open you can retrieve the sub comments for the comment with it ten at a time",
+    },
+    CommandSpec {
+        name: "load",
+        aliases: &["l"],
+        args: ArgSchema::Indexes,
+        flags: NO_FLAGS,
+        usage: "load [nums]",
+        help: "loads the pages linked in the stories as local html",
+    },
+    CommandSpec {
+        name: "open",
+        aliases: &["o"],
+        args: ArgSchema::Indexes,
+        flags: NO_FLAGS,
+        usage: "open [nums]",
+        help: "opens the links with default browser",
+    },
+    CommandSpec {
+        name: "live",
+        aliases: &[],
+        args: ArgSchema::Choice(&["on", "off"]),
+        flags: NO_FLAGS,
+        usage: "live [on|off]",
+        help: "polls for changed items and refreshes the current view in place",
+    },
+    CommandSpec {
+        name: "quote",
+        aliases: &[],
+        args: ArgSchema::Index,
+        flags: &[FlagSpec {
+                     name: "print",
+                     takes_value: false,
+                     help: "prints the quote instead of copying it",
+                 }],
+        usage: "quote [num]",
+        help: "copies the comment in [num] to the clipboard quoted with > for replying, prints it without a clipboard",
+    },
+    CommandSpec {
+        name: "raw",
+        aliases: &[],
+        args: ArgSchema::Choice(&["on", "off"]),
+        flags: NO_FLAGS,
+        usage: "raw [on|off]",
+        help: "shows comments without formatting, as html with only the entities decoded",
+    },
+    CommandSpec {
+        name: "watch",
+        aliases: &["w"],
+        args: ArgSchema::IndexOrWords,
+        flags: NO_FLAGS,
+        usage: "watch [nums]",
+        help: "watches the stories or comments and notifies about new replies,\nwatch user [id] watches the user and notifies about new submissions",
+    },
+    CommandSpec {
+        name: "watches",
+        aliases: &[],
+        args: ArgSchema::NoArgs,
+        flags: NO_FLAGS,
+        usage: "watches",
+        help: "lists the watches",
+    },
+    CommandSpec {
+        name: "unwatch",
+        aliases: &[],
+        args: ArgSchema::Indexes,
+        flags: NO_FLAGS,
+        usage: "unwatch [nums]",
+        help: "removes the watches at [nums] in the watches listing",
+    },
+    CommandSpec {
+        name: "filters",
+        aliases: &["filter"],
+        args: ArgSchema::Words,
+        flags: NO_FLAGS,
+        usage: "filters",
+        help: "lists the filters hiding stories and comments,\nfilters add [domain|title|author|comment-author] [value] adds a filter, title takes a regex,\nfilters remove [num] removes the filter at [num],\nfilters mode [hide|collapse] chooses whether filtered stories are hidden or collapsed",
+    },
+    CommandSpec {
+        name: "export-mail",
+        aliases: &[],
+        args: ArgSchema::Index,
+        flags: NO_FLAGS,
+        usage: "export-mail [num] [path]",
+        help: "writes the story at [num] and all of its comments as mail into the mbox file [path],\nor into the Maildir when [path] is a directory or ends with /",
+    },
+    CommandSpec {
+        name: "publish",
+        aliases: &[],
+        args: ArgSchema::ChoiceAndPath(&["rss", "atom"]),
+        flags: &[FlagSpec {
+                     name: "comments",
+                     takes_value: true,
+                     help: "includes the top [value] comments of each story",
+                 },
+                 FlagSpec {
+                     name: "limit",
+                     takes_value: true,
+                     help: "publishes at most [value] stories, 30 by default",
+                 }],
+        usage: "publish [rss|atom] [file]",
+        help: "writes the stories of the listing, like a feed or search results, into [file] as RSS or Atom",
+    },
+    CommandSpec {
+        name: "export-list",
+        aliases: &[],
+        args: ArgSchema::ChoiceAndPath(&["csv", "tsv"]),
+        flags: &[FlagSpec {
+                     name: "fields",
+                     takes_value: true,
+                     help: "comma separated columns, rank,id,title,domain,by,score,descendants,time by default",
+                 },
+                 FlagSpec {
+                     name: "limit",
+                     takes_value: true,
+                     help: "exports at most [value] stories, 30 by default",
+                 }],
+        usage: "export-list [csv|tsv] [file]",
+        help: "writes the stories of the listing into [file] as CSV or TSV, or prints them without [file],\nthe fields are the ones of the items and rank, domain, age, comments and hn_url",
+    },
+    CommandSpec {
+        name: "source",
+        aliases: &[],
+        args: ArgSchema::Words,
+        flags: NO_FLAGS,
+        usage: "source [file]",
+        help: "runs the commands in [file] one per line, # starts a comment, $last, $count and $status\nrefer to the previous command and on-error stop|continue sets what happens when a command fails",
+    },
+    CommandSpec {
+        name: "history",
+        aliases: &[],
+        args: ArgSchema::NoArgs,
+        flags: NO_FLAGS,
+        usage: "history",
+        help: "lists the previous commands, !! runs the previous command and !n the command [n]",
+    },
+    CommandSpec {
+        name: "help",
+        aliases: &["h", "?"],
+        args: ArgSchema::Words,
+        flags: NO_FLAGS,
+        usage: "help [command]",
+        help: "prints the commands or the help of one command",
+    },
+    CommandSpec {
+        name: "exit",
+        aliases: &["quit", "q"],
+        args: ArgSchema::NoArgs,
+        flags: NO_FLAGS,
+        usage: "exit",
+        help: "quits the application",
+    },
+];
+
+///
+/// Finds the command by its name or alias
+///
+pub fn find_command(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|command| command.name == name || command.aliases.contains(&name))
+}
+
+///
+/// Choices which are a typo or two away from the value, closest first
+///
+pub fn suggest_choices(value: &str, choices: &[&'static str]) -> Vec<&'static str> {
+    let mut suggestions: Vec<(usize, &'static str)> = choices.iter()
+        .map(|choice| (edit_distance(value, choice), *choice))
+        .filter(|&(distance, _)| distance <= 2 && distance < value.len())
+        .collect();
+    suggestions.sort();
+    suggestions.into_iter().map(|(_, choice)| choice).collect()
+}
+
+///
+/// Commands and aliases which are close to the unknown name, closest first
+///
+pub fn suggest_commands(name: &str) -> Vec<&'static str> {
+    let mut suggestions: Vec<(usize, &'static str)> = COMMANDS.iter()
+        .flat_map(|command| {
+            let names: Vec<&'static str> = vec![command.name].into_iter().chain(command.aliases.iter().cloned()).collect();
+            names.into_iter()
+                .filter(|candidate| candidate.len() > 1) // one letter aliases are close to anything
+                .map(|candidate| (edit_distance(name, candidate), command.name))
+                .collect::<Vec<_>>()
+        })
+        .filter(|&(distance, _)| distance <= 2 && distance < name.len())
+        .collect();
+    suggestions.sort();
+    let mut names: Vec<&'static str> = Vec::new();
+    for (_, command) in suggestions {
+        if !names.contains(&command) {
+            names.push(command);
+        }
+    }
+    names
+}
+
+///
+/// Help listing of all commands, the help texts come from the registry
+///
+pub fn create_help() -> String {
+    let mut help = String::new();
+    for command in COMMANDS {
+        help.push_str(&create_command_help(command));
+    }
+    help.push_str("\n[num] replace the number with the printed out index, [nums] take ranges like 1-3 and lists like 2,5,7");
+    help
+}
+
+pub fn create_command_help(command: &CommandSpec) -> String {
+    let mut lines = command.help.lines();
+    let mut help = format!("{:18} > {}\n", command.usage, lines.next().unwrap_or(""));
+    for line in lines {
+        help.push_str(&format!("{:18}   {}\n", "", line));
+    }
+    if !command.aliases.is_empty() {
+        help.push_str(&format!("{:18}   aliases: {}\n", "", command.aliases.join(", ")));
+    }
+    for flag in command.flags {
+        let flag_usage = if flag.takes_value { format!("--{}=[value]", flag.name) } else { format!("--{}", flag.name) };
+        help.push_str(&format!("{:18}   {} {}\n", "", flag_usage, flag.help));
+    }
+    help
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_command_test() {
+        assert_eq!("comments", find_command("c").unwrap().name);
+        assert_eq!("find", find_command("grep").unwrap().name);
+        assert_eq!("exit", find_command("exit").unwrap().name);
+        assert!(find_command("commentz").is_none());
+    }
+
+    #[test]
+    fn suggest_commands_test() {
+        assert_eq!(vec!["comments"], suggest_commands("coments"));
+        assert_eq!(vec!["expand"], suggest_commands("exapnd"));
+        assert!(suggest_commands("xyzzy").is_empty());
+        assert!(suggest_commands("z").is_empty());
+    }
+
+    #[test]
+    fn create_help_test() {
+        let help = create_help();
+        for command in COMMANDS {
+            assert!(help.contains(command.usage));
+        }
+        assert!(help.contains("aliases: c\n"));
+        assert!(help.contains("--by-date"));
+    }
+}
@@ -0,0 +1,101 @@
+use super::commands::{self, ArgSchema, COMMANDS};
+
+///
+/// What is on screen, kept up to date by the main loop so completion can offer valid indexes
+///
+pub struct CompletionContext {
+    pub indexes: Vec<usize>,
+}
+
+impl CompletionContext {
+    pub fn new() -> CompletionContext {
+        CompletionContext { indexes: Vec::new() }
+    }
+}
+
+///
+/// Completes the word which ends at the end of the text, returns where the word starts and the candidates for it
+///
+pub fn complete(text: &str, context: &CompletionContext) -> (usize, Vec<String>) {
+    let word_start = text.rfind(' ').map(|i| i + 1).unwrap_or(0);
+    let word = &text[word_start..];
+    let previous_words: Vec<&str> = text[..word_start].split_whitespace().collect();
+    let options: Vec<String> = match previous_words.len() {
+        0 => COMMANDS.iter().filter(|command| command.name != "/").map(|command| String::from(command.name)).collect(),
+        1 => argument_options(previous_words[0], context),
+        _ => Vec::new(),
+    };
+    let mut candidates: Vec<String> = options.into_iter().filter(|option| option.starts_with(word)).collect();
+    candidates.sort();
+    candidates.dedup();
+    (word_start, candidates)
+}
+
+///
+/// Options for the first argument, taken from the argument schema of the command in the registry
+///
+fn argument_options(command: &str, context: &CompletionContext) -> Vec<String> {
+    let spec = match commands::find_command(command) {
+        Some(spec) => spec,
+        None => return Vec::new(),
+    };
+    match spec.args {
+        ArgSchema::Choice(choices) | ArgSchema::ChoiceAndPath(choices) => choices.iter().map(|choice| String::from(*choice)).collect(),
+        ArgSchema::Index | ArgSchema::Indexes | ArgSchema::IndexOrWords => {
+            context.indexes.iter().map(|index| index.to_string()).collect()
+        }
+        ArgSchema::Words if spec.name == "filters" => vec![String::from("add"), String::from("remove"), String::from("mode")],
+        ArgSchema::Words | ArgSchema::NoArgs => Vec::new(),
+    }
+}
+
+///
+/// Longest prefix shared by all of the candidates
+///
+pub fn common_prefix(candidates: &Vec<String>) -> String {
+    let mut prefix: String = match candidates.first() {
+        Some(first) => first.clone(),
+        None => return String::new(),
+    };
+    for candidate in candidates.iter().skip(1) {
+        while !candidate.starts_with(prefix.as_str()) {
+            prefix.pop();
+        }
+    }
+    prefix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn complete_command_test() {
+        let context = CompletionContext::new();
+        assert_eq!((0, vec![String::from("watch"), String::from("watches")]), complete("wat", &context));
+        assert_eq!((0, vec![String::from("exit"), String::from("expand"), String::from("export-list"),
+                            String::from("export-mail")]),
+                   complete("ex", &context));
+        assert_eq!(COMMANDS.len() - 1, complete("", &context).1.len());
+        assert!(complete("xyz", &context).1.is_empty());
+    }
+
+    #[test]
+    fn complete_arguments_test() {
+        let mut context = CompletionContext::new();
+        context.indexes = vec![11, 12, 13, 21];
+        assert_eq!((5, vec![String::from("best")]), complete("feed b", &context));
+        assert_eq!(vec!["11", "12", "13"], complete("comments 1", &context).1);
+        assert_eq!(vec!["off", "on"], complete("live ", &context).1);
+        assert_eq!(vec!["11", "12", "13"], complete("c 1", &context).1);
+        assert!(complete("next 1", &context).1.is_empty());
+        assert!(complete("filters add d", &context).1.is_empty());
+    }
+
+    #[test]
+    fn common_prefix_test() {
+        assert_eq!("watch", common_prefix(&vec![String::from("watch"), String::from("watches")]));
+        assert_eq!("ex", common_prefix(&vec![String::from("expand"), String::from("exit")]));
+        assert_eq!("", common_prefix(&Vec::new()));
+    }
+}
@@ -0,0 +1,153 @@
+use std::path::{Path, PathBuf};
+
+use helpers::io_utils::{read_file, write_file_atomic, append_to_file};
+
+///
+/// Command history shared by the prompt and the history command, persisted one command per line
+///
+pub struct History {
+    entries: Vec<String>,
+    path: Option<PathBuf>,
+    max_entries: usize,
+    /// Commands in the file, it's rewritten with the entries once it has more than max entries
+    file_entries: usize,
+}
+
+impl History {
+    pub fn new(max_entries: usize) -> History {
+        History {
+            entries: Vec::new(),
+            path: None,
+            max_entries: max_entries,
+            file_entries: 0,
+        }
+    }
+
+    ///
+    /// Loads the history from the file and appends new commands to it, the file is kept to max entries
+    ///
+    pub fn load_from(path: &Path, max_entries: usize) -> History {
+        let mut history = History::new(max_entries);
+        let lines: Vec<String> = read_file(&path.to_string_lossy())
+            .map(|contents| contents.lines().map(|line| String::from(line)).collect())
+            .unwrap_or(Vec::new());
+        let skipped = lines.len().saturating_sub(max_entries);
+        history.entries = lines.into_iter().skip(skipped).collect();
+        history.file_entries = history.entries.len() + skipped;
+        history.path = Some(path.to_path_buf());
+        history.trim_file();
+        history
+    }
+
+    fn trim_file(&mut self) {
+        if self.file_entries <= self.max_entries {
+            return;
+        }
+        match self.path {
+            Some(ref path) => {
+                let mut contents = self.entries.join("\n");
+                contents.push('\n');
+                if write_file_atomic(path, &contents).is_err() {
+                    warn!("Could not trim history in {}", path.to_string_lossy());
+                    return;
+                }
+            }
+            None => (),
+        }
+        self.file_entries = self.entries.len();
+    }
+
+    pub fn entries(&self) -> &Vec<String> {
+        &self.entries
+    }
+
+    ///
+    /// Adds the command unless it's empty or the same as the previous one
+    ///
+    pub fn add(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() || self.entries.last().map(|last| last == line).unwrap_or(false) {
+            return;
+        }
+        self.entries.push(String::from(line));
+        if self.entries.len() > self.max_entries {
+            self.entries.remove(0);
+        }
+        match self.path {
+            Some(ref path) => {
+                if append_to_file(path, &format!("{}\n", line)).is_err() {
+                    warn!("Could not write history to {}", path.to_string_lossy());
+                }
+            }
+            None => (),
+        }
+        self.file_entries += 1;
+        self.trim_file();
+    }
+
+    ///
+    /// Expands `!!` to the previous command and `!n` to the command numbered n in the history listing
+    ///
+    pub fn expand(&self, line: &str) -> Result<String, String> {
+        let trimmed = line.trim();
+        if !trimmed.starts_with('!') {
+            return Ok(String::from(line));
+        }
+        let (reference, rest) = match trimmed.find(' ') {
+            Some(i) => (&trimmed[..i], &trimmed[i..]),
+            None => (trimmed, ""),
+        };
+        let entry = if reference == "!!" {
+            self.entries.last().ok_or(String::from("!!: no previous command"))?
+        } else {
+            let numb = reference[1..].parse::<usize>().map_err(|_| format!("{}: not a history reference", reference))?;
+            if numb == 0 || numb > self.entries.len() {
+                return Err(format!("{}: no such command in history", reference));
+            }
+            &self.entries[numb - 1]
+        };
+        Ok(format!("{}{}", entry, rest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn history_expand_test() {
+        let mut history = History::new(10);
+        assert!(history.expand("!!").is_err());
+        history.add("comments 1");
+        history.add("next");
+        history.add("next");
+        history.add("  ");
+        assert_eq!(2, history.entries().len());
+        assert_eq!("next", history.expand("!!").unwrap());
+        assert_eq!("comments 1", history.expand("!1").unwrap());
+        assert_eq!("comments 1 extra", history.expand("!1 extra").unwrap());
+        assert_eq!("top", history.expand("top").unwrap());
+        assert!(history.expand("!3").is_err());
+        assert!(history.expand("!x").is_err());
+    }
+
+    #[test]
+    fn history_persist_test() {
+        let path = env::temp_dir().join("hncli-history-test");
+        let _ = fs::remove_file(&path);
+        {
+            let mut history = History::load_from(&path, 3);
+            for line in vec!["a", "b", "c", "d"] {
+                history.add(line);
+            }
+            assert_eq!(vec!["b", "c", "d"], *history.entries());
+            assert_eq!("b\nc\nd\n", read_file(&path.to_string_lossy()).unwrap());
+        }
+        let loaded = History::load_from(&path, 2);
+        assert_eq!(vec!["c", "d"], *loaded.entries());
+        assert_eq!("c\nd\n", read_file(&path.to_string_lossy()).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,618 @@
+use std::io::{self, Stdout, Write};
+use std::sync::{Arc, Mutex};
+
+use termion::{clear, cursor};
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::{IntoRawMode, RawTerminal};
+
+use super::completion::{self, CompletionContext};
+use super::history::History;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EditMode {
+    Emacs,
+    Vi,
+}
+
+impl EditMode {
+    pub fn parse(s: &str) -> Option<EditMode> {
+        match s {
+            "emacs" => Some(EditMode::Emacs),
+            "vi" => Some(EditMode::Vi),
+            _ => None,
+        }
+    }
+}
+
+///
+/// Text of the line being edited with the cursor as a char index
+///
+pub struct LineBuffer {
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl LineBuffer {
+    pub fn new() -> LineBuffer {
+        LineBuffer {
+            chars: Vec::new(),
+            cursor: 0,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    /// Replaces the text and moves the cursor to the end
+    pub fn set(&mut self, text: &str) {
+        self.chars = text.chars().collect();
+        self.cursor = self.chars.len();
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.chars.insert(self.cursor, c);
+        self.cursor += 1;
+    }
+
+    pub fn insert_str(&mut self, s: &str) {
+        for c in s.chars() {
+            self.insert(c);
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if self.cursor < self.chars.len() {
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    pub fn left(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+        }
+    }
+
+    pub fn right(&mut self) {
+        if self.cursor < self.chars.len() {
+            self.cursor += 1;
+        }
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.chars.len();
+    }
+
+    /// Moves to the start of the word before the cursor
+    pub fn word_left(&mut self) {
+        while self.cursor > 0 && self.chars[self.cursor - 1].is_whitespace() {
+            self.cursor -= 1;
+        }
+        while self.cursor > 0 && !self.chars[self.cursor - 1].is_whitespace() {
+            self.cursor -= 1;
+        }
+    }
+
+    /// Moves past the end of the word after the cursor
+    pub fn word_right(&mut self) {
+        while self.cursor < self.chars.len() && self.chars[self.cursor].is_whitespace() {
+            self.cursor += 1;
+        }
+        while self.cursor < self.chars.len() && !self.chars[self.cursor].is_whitespace() {
+            self.cursor += 1;
+        }
+    }
+
+    /// Moves to the start of the next word like vi w
+    pub fn next_word_start(&mut self) {
+        while self.cursor < self.chars.len() && !self.chars[self.cursor].is_whitespace() {
+            self.cursor += 1;
+        }
+        while self.cursor < self.chars.len() && self.chars[self.cursor].is_whitespace() {
+            self.cursor += 1;
+        }
+    }
+
+    pub fn delete_word_before(&mut self) {
+        let end = self.cursor;
+        self.word_left();
+        self.chars.drain(self.cursor..end);
+    }
+
+    pub fn delete_word_after(&mut self) {
+        let start = self.cursor;
+        self.next_word_start();
+        self.chars.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    pub fn kill_to_end(&mut self) {
+        self.chars.truncate(self.cursor);
+    }
+
+    pub fn kill_to_start(&mut self) {
+        self.chars.drain(..self.cursor);
+        self.cursor = 0;
+    }
+
+    fn text_before_cursor(&self) -> String {
+        self.chars[..self.cursor].iter().collect()
+    }
+}
+
+///
+/// Terminal of the prompt, shared with the event loop so output which comes while a line is edited, like live
+/// updates and watch notifications, is printed above the prompt instead of into it
+///
+#[derive(Clone)]
+pub struct PromptTerminal {
+    state: Arc<Mutex<PromptState>>,
+}
+
+struct PromptState {
+    /// Set while a line is read, raw mode ends when it's dropped
+    raw: Option<RawTerminal<Stdout>>,
+    prompt: String,
+    text: String,
+    /// Chars between the cursor and the end of the line
+    behind: usize,
+}
+
+impl PromptTerminal {
+    pub fn new() -> PromptTerminal {
+        PromptTerminal {
+            state: Arc::new(Mutex::new(PromptState {
+                raw: None,
+                prompt: String::new(),
+                text: String::new(),
+                behind: 0,
+            })),
+        }
+    }
+
+    fn enter_raw_mode(&self) -> io::Result<()> {
+        self.state.lock().unwrap().raw = Some(io::stdout().into_raw_mode()?);
+        Ok(())
+    }
+
+    fn leave_raw_mode(&self) {
+        self.state.lock().unwrap().raw = None;
+    }
+
+    fn write(&self, text: &str) -> io::Result<()> {
+        let _state = self.state.lock().unwrap();
+        let mut stdout = io::stdout();
+        stdout.write_all(text.as_bytes())?;
+        stdout.flush()
+    }
+
+    fn show_line(&self, prompt: &str, buffer: &LineBuffer) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.prompt = String::from(prompt);
+        state.text = buffer.text();
+        state.behind = buffer.chars.len() - buffer.cursor();
+        state.draw(&mut io::stdout())
+    }
+
+    ///
+    /// Runs the output with the line being edited cleared and raw mode left, then draws the line again below it.
+    /// Without a line being read the output is printed as it is.
+    ///
+    pub fn interrupt<R, F: FnOnce() -> R>(&self, output: F) -> R {
+        let mut state = self.state.lock().unwrap();
+        if state.raw.is_none() {
+            return output();
+        }
+        let _ = write!(io::stdout(), "\r{}", clear::CurrentLine);
+        state.raw = None;
+        let result = output();
+        match io::stdout().into_raw_mode() {
+            Ok(raw) => state.raw = Some(raw),
+            Err(e) => warn!("Could not return to raw mode {}", e),
+        }
+        let _ = state.draw(&mut io::stdout());
+        result
+    }
+}
+
+impl PromptState {
+    fn draw<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "\r{}{}{}", clear::CurrentLine, self.prompt, self.text)?;
+        if self.behind > 0 {
+            write!(out, "{}", cursor::Left(self.behind as u16))?;
+        }
+        out.flush()
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum EditResult {
+    Continue,
+    Submit(String),
+    /// Candidates to show when completion was ambiguous
+    ShowCandidates(Vec<String>),
+    /// Ctrl-C, the line is dropped
+    Cancel,
+    /// Ctrl-D on an empty line
+    Eof,
+}
+
+///
+/// Prompt with emacs or vi style editing, history browsing and tab completion
+///
+pub struct LineEditor {
+    mode: EditMode,
+    buffer: LineBuffer,
+    vi_normal: bool,
+    vi_pending_delete: bool,
+    /// Index in history when browsing it with up and down
+    history_position: Option<usize>,
+    /// Line which was being edited before browsing history
+    draft: String,
+    history: Arc<Mutex<History>>,
+    completion: Arc<Mutex<CompletionContext>>,
+    terminal: PromptTerminal,
+}
+
+impl LineEditor {
+    pub fn new(mode: EditMode,
+               history: Arc<Mutex<History>>,
+               completion: Arc<Mutex<CompletionContext>>,
+               terminal: PromptTerminal)
+               -> LineEditor {
+        LineEditor {
+            mode: mode,
+            buffer: LineBuffer::new(),
+            vi_normal: false,
+            vi_pending_delete: false,
+            history_position: None,
+            draft: String::new(),
+            history: history,
+            completion: completion,
+            terminal: terminal,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.buffer = LineBuffer::new();
+        self.vi_normal = false;
+        self.vi_pending_delete = false;
+        self.history_position = None;
+        self.draft = String::new();
+    }
+
+    ///
+    /// Reads a line from the terminal in raw mode, None when input ends. History references are expanded
+    /// and the line is added to history before it's returned.
+    ///
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        self.terminal.enter_raw_mode()?;
+        let line = self.read_keys(prompt);
+        self.terminal.leave_raw_mode();
+        line
+    }
+
+    fn read_keys(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        let stdin = io::stdin();
+        self.reset();
+        self.terminal.show_line(prompt, &self.buffer)?;
+        for key in stdin.lock().keys() {
+            match self.handle_key(key?) {
+                EditResult::Continue => (),
+                EditResult::Submit(line) => {
+                    self.terminal.write("\r\n")?;
+                    let expanded = self.history.lock().unwrap().expand(&line);
+                    match expanded {
+                        Ok(expanded) => {
+                            if expanded != line {
+                                self.terminal.write(&format!("{}\r\n", expanded))?;
+                            }
+                            self.history.lock().unwrap().add(&expanded);
+                            return Ok(Some(expanded));
+                        }
+                        Err(e) => {
+                            self.terminal.write(&format!("{}\r\n", e))?;
+                            self.reset();
+                        }
+                    }
+                }
+                EditResult::ShowCandidates(candidates) => {
+                    self.terminal.write(&format!("\r\n{}\r\n", candidates.join("  ")))?
+                }
+                EditResult::Cancel => {
+                    self.terminal.write("^C\r\n")?;
+                    self.reset();
+                }
+                EditResult::Eof => {
+                    self.terminal.write("\r\n")?;
+                    return Ok(None);
+                }
+            }
+            self.terminal.show_line(prompt, &self.buffer)?;
+        }
+        Ok(None)
+    }
+
+    pub fn handle_key(&mut self, key: Key) -> EditResult {
+        if self.mode == EditMode::Vi && self.vi_normal {
+            return self.handle_vi_normal_key(key);
+        }
+        match key {
+            Key::Char('\n') | Key::Char('\r') => EditResult::Submit(self.buffer.text()),
+            Key::Char('\t') => self.complete(),
+            Key::Char(c) => {
+                self.buffer.insert(c);
+                EditResult::Continue
+            }
+            Key::Ctrl('c') => EditResult::Cancel,
+            Key::Ctrl('d') if self.buffer.is_empty() => EditResult::Eof,
+            Key::Backspace | Key::Ctrl('h') => self.edit(|buffer| buffer.backspace()),
+            Key::Delete => self.edit(|buffer| buffer.delete()),
+            Key::Left => self.edit(|buffer| buffer.left()),
+            Key::Right => self.edit(|buffer| buffer.right()),
+            Key::Home => self.edit(|buffer| buffer.home()),
+            Key::End => self.edit(|buffer| buffer.end()),
+            Key::Up => self.history_previous(),
+            Key::Down => self.history_next(),
+            Key::Esc if self.mode == EditMode::Vi => {
+                self.vi_normal = true;
+                self.buffer.left();
+                EditResult::Continue
+            }
+            key if self.mode == EditMode::Emacs => self.handle_emacs_key(key),
+            _ => EditResult::Continue,
+        }
+    }
+
+    fn handle_emacs_key(&mut self, key: Key) -> EditResult {
+        match key {
+            Key::Ctrl('a') => self.edit(|buffer| buffer.home()),
+            Key::Ctrl('e') => self.edit(|buffer| buffer.end()),
+            Key::Ctrl('b') => self.edit(|buffer| buffer.left()),
+            Key::Ctrl('f') => self.edit(|buffer| buffer.right()),
+            Key::Ctrl('d') => self.edit(|buffer| buffer.delete()),
+            Key::Ctrl('k') => self.edit(|buffer| buffer.kill_to_end()),
+            Key::Ctrl('u') => self.edit(|buffer| buffer.kill_to_start()),
+            Key::Ctrl('w') => self.edit(|buffer| buffer.delete_word_before()),
+            Key::Alt('b') => self.edit(|buffer| buffer.word_left()),
+            Key::Alt('f') => self.edit(|buffer| buffer.word_right()),
+            Key::Alt('d') => self.edit(|buffer| buffer.delete_word_after()),
+            Key::Ctrl('p') => self.history_previous(),
+            Key::Ctrl('n') => self.history_next(),
+            _ => EditResult::Continue,
+        }
+    }
+
+    fn handle_vi_normal_key(&mut self, key: Key) -> EditResult {
+        if self.vi_pending_delete {
+            self.vi_pending_delete = false;
+            return match key {
+                Key::Char('d') => self.edit(|buffer| buffer.set("")),
+                Key::Char('w') => self.edit(|buffer| buffer.delete_word_after()),
+                Key::Char('b') => self.edit(|buffer| buffer.delete_word_before()),
+                Key::Char('$') => self.edit(|buffer| buffer.kill_to_end()),
+                Key::Char('0') => self.edit(|buffer| buffer.kill_to_start()),
+                _ => EditResult::Continue,
+            };
+        }
+        match key {
+            Key::Char('\n') | Key::Char('\r') => EditResult::Submit(self.buffer.text()),
+            Key::Char('h') | Key::Left | Key::Backspace => self.edit(|buffer| buffer.left()),
+            Key::Char('l') | Key::Right | Key::Char(' ') => self.edit(|buffer| buffer.right()),
+            Key::Char('0') | Key::Home => self.edit(|buffer| buffer.home()),
+            Key::Char('$') | Key::End => {
+                self.edit(|buffer| {
+                    buffer.end();
+                    buffer.left(); // normal mode cursor is on the last char
+                })
+            }
+            Key::Char('w') => self.edit(|buffer| buffer.next_word_start()),
+            Key::Char('b') => self.edit(|buffer| buffer.word_left()),
+            Key::Char('x') | Key::Delete => self.edit(|buffer| buffer.delete()),
+            Key::Char('D') => self.edit(|buffer| buffer.kill_to_end()),
+            Key::Char('d') => {
+                self.vi_pending_delete = true;
+                EditResult::Continue
+            }
+            Key::Char('k') | Key::Up => self.history_previous(),
+            Key::Char('j') | Key::Down => self.history_next(),
+            Key::Char('i') => self.insert_mode(|_| ()),
+            Key::Char('a') => self.insert_mode(|buffer| buffer.right()),
+            Key::Char('I') => self.insert_mode(|buffer| buffer.home()),
+            Key::Char('A') => self.insert_mode(|buffer| buffer.end()),
+            Key::Ctrl('c') => EditResult::Cancel,
+            _ => EditResult::Continue,
+        }
+    }
+
+    fn edit<F: FnOnce(&mut LineBuffer)>(&mut self, f: F) -> EditResult {
+        f(&mut self.buffer);
+        EditResult::Continue
+    }
+
+    fn insert_mode<F: FnOnce(&mut LineBuffer)>(&mut self, f: F) -> EditResult {
+        self.vi_normal = false;
+        self.edit(f)
+    }
+
+    fn history_previous(&mut self) -> EditResult {
+        let history = self.history.lock().unwrap();
+        let entries = history.entries();
+        let position = match self.history_position {
+            Some(0) => return EditResult::Continue,
+            Some(position) => position - 1,
+            None if entries.is_empty() => return EditResult::Continue,
+            None => {
+                self.draft = self.buffer.text();
+                entries.len() - 1
+            }
+        };
+        self.history_position = Some(position);
+        self.buffer.set(&entries[position]);
+        EditResult::Continue
+    }
+
+    fn history_next(&mut self) -> EditResult {
+        let history = self.history.lock().unwrap();
+        match self.history_position {
+            Some(position) if position + 1 < history.entries().len() => {
+                self.history_position = Some(position + 1);
+                self.buffer.set(&history.entries()[position + 1]);
+            }
+            Some(_) => {
+                self.history_position = None;
+                self.buffer.set(&self.draft);
+            }
+            None => (),
+        }
+        EditResult::Continue
+    }
+
+    ///
+    /// Completes the word before the cursor, as far as the candidates agree
+    ///
+    fn complete(&mut self) -> EditResult {
+        let before_cursor = self.buffer.text_before_cursor();
+        let (word_start, candidates) = completion::complete(&before_cursor, &self.completion.lock().unwrap());
+        let typed = before_cursor.len() - word_start;
+        match candidates.len() {
+            0 => EditResult::Continue,
+            1 => {
+                self.buffer.insert_str(&candidates[0][typed..]);
+                self.buffer.insert(' ');
+                EditResult::Continue
+            }
+            _ => {
+                let prefix = completion::common_prefix(&candidates);
+                if prefix.len() > typed {
+                    self.buffer.insert_str(&prefix[typed..]);
+                    EditResult::Continue
+                } else {
+                    EditResult::ShowCandidates(candidates)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor(mode: EditMode, history: Vec<&str>) -> LineEditor {
+        let mut entries = History::new(10);
+        for line in history {
+            entries.add(line);
+        }
+        LineEditor::new(mode,
+                        Arc::new(Mutex::new(entries)),
+                        Arc::new(Mutex::new(CompletionContext::new())),
+                        PromptTerminal::new())
+    }
+
+    fn type_keys(editor: &mut LineEditor, keys: Vec<Key>) -> EditResult {
+        let mut result = EditResult::Continue;
+        for key in keys {
+            result = editor.handle_key(key);
+        }
+        result
+    }
+
+    fn chars(s: &str) -> Vec<Key> {
+        s.chars().map(|c| Key::Char(c)).collect()
+    }
+
+    #[test]
+    fn line_buffer_test() {
+        let mut buffer = LineBuffer::new();
+        buffer.insert_str("comments 10");
+        buffer.word_left();
+        assert_eq!(9, buffer.cursor());
+        buffer.delete_word_before();
+        assert_eq!("10", buffer.text());
+        buffer.insert_str("open ");
+        buffer.end();
+        buffer.backspace();
+        assert_eq!("open 1", buffer.text());
+        buffer.home();
+        buffer.next_word_start();
+        buffer.kill_to_end();
+        assert_eq!("open ", buffer.text());
+        buffer.kill_to_start();
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn emacs_editing_test() {
+        let mut editor = editor(EditMode::Emacs, vec![]);
+        let mut keys = chars("ext 2");
+        keys.extend(vec![Key::Ctrl('a'), Key::Char('n'), Key::Ctrl('e'), Key::Backspace, Key::Char('3')]);
+        keys.extend(vec![Key::Alt('b'), Key::Ctrl('k'), Key::Char('\n')]);
+        assert_eq!(EditResult::Submit(String::from("next ")), type_keys(&mut editor, keys));
+        assert_eq!(EditResult::Cancel, editor.handle_key(Key::Ctrl('c')));
+    }
+
+    #[test]
+    fn vi_editing_test() {
+        let mut editor = editor(EditMode::Vi, vec![]);
+        let mut keys = chars("open 12");
+        keys.extend(vec![Key::Esc, Key::Char('0'), Key::Char('d'), Key::Char('w'), Key::Char('i')]);
+        keys.extend(chars("load "));
+        keys.extend(vec![Key::Esc, Key::Char('$'), Key::Char('x'), Key::Char('\n')]);
+        assert_eq!(EditResult::Submit(String::from("load 1")), type_keys(&mut editor, keys));
+    }
+
+    #[test]
+    fn history_browsing_test() {
+        let mut editor = editor(EditMode::Emacs, vec!["comments 1", "next"]);
+        editor.buffer.set("dra");
+        editor.handle_key(Key::Up);
+        assert_eq!("next", editor.buffer.text());
+        editor.handle_key(Key::Up);
+        editor.handle_key(Key::Up);
+        assert_eq!("comments 1", editor.buffer.text());
+        editor.handle_key(Key::Down);
+        editor.handle_key(Key::Down);
+        assert_eq!("dra", editor.buffer.text());
+    }
+
+    #[test]
+    fn tab_completion_test() {
+        let mut editor = editor(EditMode::Emacs, vec![]);
+        assert_eq!(EditResult::Continue, type_keys(&mut editor, chars("fee\t")));
+        assert_eq!("feed ", editor.buffer.text());
+        type_keys(&mut editor, chars("s\t"));
+        assert_eq!("feed show ", editor.buffer.text());
+
+        editor.buffer.set("wat");
+        editor.handle_key(Key::Char('\t'));
+        assert_eq!("watch", editor.buffer.text());
+        assert_eq!(EditResult::ShowCandidates(vec![String::from("watch"), String::from("watches")]),
+                   editor.handle_key(Key::Char('\t')));
+
+        editor.completion.lock().unwrap().indexes = vec![1, 2, 3];
+        editor.buffer.set("open ");
+        assert_eq!(EditResult::ShowCandidates(vec![String::from("1"), String::from("2"), String::from("3")]),
+                   editor.handle_key(Key::Char('\t')));
+    }
+}
@@ -1,3 +1,9 @@
 #![allow(dead_code)]
 pub mod cli;
-pub mod backend;
\ No newline at end of file
+pub mod backend;
+pub mod script;
+pub mod commands;
+pub mod args;
+pub mod history;
+pub mod completion;
+pub mod line_editor;
\ No newline at end of file
@@ -0,0 +1,165 @@
+use regex::{Captures, Regex};
+use helpers::io_utils;
+
+/// Scripts can source other scripts up to this depth, which stops scripts sourcing themselves
+pub const MAX_SCRIPT_DEPTH: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OnError {
+    /// The script stops at the first failing command
+    Stop,
+    /// Failing commands are counted and the script goes on
+    Continue,
+}
+
+impl OnError {
+    pub fn parse(name: &str) -> Option<OnError> {
+        match name {
+            "stop" => Some(OnError::Stop),
+            "continue" => Some(OnError::Continue),
+            _ => None,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum ScriptStep {
+    /// Command and the line number it is on
+    Command(usize, String),
+    SetOnError(OnError),
+}
+
+///
+/// Commands read from a script file, one per line. Lines starting with # are comments and
+/// on-error stop|continue changes what happens when the commands after it fail
+///
+pub struct Script {
+    pub path: String,
+    pub steps: Vec<ScriptStep>,
+}
+
+impl Script {
+    pub fn load(path: &str) -> Result<Script, String> {
+        match io_utils::read_file(path) {
+            Some(contents) => Script::parse(path, &contents),
+            None => Err(format!("could not read script {}", path)),
+        }
+    }
+
+    pub fn parse(path: &str, contents: &str) -> Result<Script, String> {
+        let mut steps = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words[0] == "on-error" {
+                match words.get(1).and_then(|mode| OnError::parse(mode)) {
+                    Some(mode) if words.len() == 2 => steps.push(ScriptStep::SetOnError(mode)),
+                    _ => return Err(format!("{}:{} on-error should be stop or continue", path, index + 1)),
+                }
+            } else {
+                steps.push(ScriptStep::Command(index + 1, String::from(line)));
+            }
+        }
+        Ok(Script {
+            path: String::from(path),
+            steps: steps,
+        })
+    }
+}
+
+///
+/// Results of the previous command which the next commands of the script can refer to
+///
+pub struct ScriptVars {
+    /// Indexes listed on screen after the previous command
+    pub last: Vec<usize>,
+    /// 0 when the previous command succeeded, 1 when it failed
+    pub status: i32,
+    variables: Regex,
+}
+
+impl ScriptVars {
+    pub fn new() -> ScriptVars {
+        ScriptVars {
+            last: Vec::new(),
+            status: 0,
+            variables: Regex::new(r"\$(last|count|status)\b").unwrap(),
+        }
+    }
+
+    ///
+    /// Replaces $last, $count and $status in the command, other $ signs are left as they are for regexes
+    ///
+    pub fn substitute(&self, command: &str) -> String {
+        self.variables.replace_all(command, |captures: &Captures| {
+                match &captures[1] {
+                    "last" => format_indexes(&self.last),
+                    "count" => self.last.len().to_string(),
+                    _ => self.status.to_string(),
+                }
+            })
+            .into_owned()
+    }
+}
+
+///
+/// Formats the indexes with ranges for consecutive ones like 1-3,7 so commands taking [nums] accept them
+///
+pub fn format_indexes(indexes: &Vec<usize>) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < indexes.len() {
+        let start = indexes[i];
+        let mut end = start;
+        while i + 1 < indexes.len() && indexes[i + 1] == end + 1 {
+            end += 1;
+            i += 1;
+        }
+        parts.push(if start == end { start.to_string() } else { format!("{}-{}", start, end) });
+        i += 1;
+    }
+    parts.join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_script_test() {
+        let script = Script::load("res/test/report.hn").unwrap();
+        assert_eq!(vec![ScriptStep::Command(2, String::from("feed best")),
+                        ScriptStep::Command(3, String::from("min-score 100")),
+                        ScriptStep::SetOnError(OnError::Continue),
+                        ScriptStep::Command(7, String::from("comments 1")),
+                        ScriptStep::Command(8, String::from("watch $last"))],
+                   script.steps);
+        assert!(Script::load("res/test/missing.hn").is_err());
+    }
+
+    #[test]
+    fn parse_invalid_script_test() {
+        let error = Script::parse("daily.hn", "top\non-error sometimes").err().unwrap();
+        assert_eq!("daily.hn:2 on-error should be stop or continue", error);
+    }
+
+    #[test]
+    fn substitute_test() {
+        let mut vars = ScriptVars::new();
+        vars.last = vec![11, 12, 13, 15];
+        vars.status = 1;
+        assert_eq!("open 11-13,15", vars.substitute("open $last"));
+        assert_eq!("echo 4 1", vars.substitute("echo $count $status"));
+        assert_eq!("/rust$ $lastly", vars.substitute("/rust$ $lastly"));
+    }
+
+    #[test]
+    fn format_indexes_test() {
+        assert_eq!("1-10", format_indexes(&(1..11).collect()));
+        assert_eq!("2,5,7-8", format_indexes(&vec![2, 5, 7, 8]));
+        assert_eq!("", format_indexes(&Vec::new()));
+    }
+}